@@ -0,0 +1,133 @@
+use std::f32::consts::PI;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::distribution::Distribution2D;
+use crate::film::luminance;
+use crate::texture::ImageTexture;
+use crate::vec3::Vec3;
+
+use rand::prelude::*;
+
+/// Shape of the opening light passes through in a lens, which is also the
+/// shape of out-of-focus highlights.
+///
+/// Points are sampled within the unit disk and scaled by the lens radius.
+/// Cloning is cheap, so that both eyes of a stereo camera can share a mask.
+#[derive(Clone)]
+pub enum Aperture {
+    Circular,
+    /// Regular polygon formed by `blades` straight diaphragm blades, with
+    /// its corners on the unit circle. The `rotation` is in degrees.
+    Polygon {
+        blades: u32,
+        rotation: f32,
+    },
+    /// Arbitrary image covering the square around the unit disk, where the
+    /// luminance is how much light passes through.
+    Mask(Arc<Distribution2D>),
+}
+
+impl Aperture {
+    pub fn mask(image: &ImageTexture) -> Aperture {
+        let func: Vec<f32> = image.pixels.iter().map(|&p| luminance(p)).collect();
+
+        Aperture::Mask(Arc::new(Distribution2D::new(
+            &func,
+            image.width as usize,
+            image.height as usize,
+        )))
+    }
+
+    pub fn load_mask(path: &Path) -> io::Result<Aperture> {
+        Ok(Aperture::mask(&ImageTexture::load(path, true)?))
+    }
+
+    /// Point on the lens in the `xy` plane, distributed uniformly over the
+    /// area that lets light through.
+    pub fn sample(&self, rng: &mut dyn rand::RngCore) -> Vec3 {
+        match *self {
+            Aperture::Circular => Vec3::random_in_unit_disk(rng),
+            Aperture::Polygon { blades, rotation } => {
+                let blades = blades.max(3);
+                let step = 2.0 * PI / blades as f32;
+
+                // All triangles between the center and an edge have the
+                // same area, so pick one and then a point inside it
+                let edge = rng.gen_range(0, blades) as f32;
+                let a = rotation.to_radians() + edge * step;
+                let b = a + step;
+
+                let mut s: f32 = rng.gen();
+                let mut t: f32 = rng.gen();
+                if s + t > 1.0 {
+                    s = 1.0 - s;
+                    t = 1.0 - t;
+                }
+
+                Vec3::new(s * a.cos() + t * b.cos(), s * a.sin() + t * b.sin(), 0.0)
+            }
+            Aperture::Mask(ref distribution) => {
+                let (x, y, _) = distribution.sample(rng.gen(), rng.gen());
+                Vec3::new(2.0 * x - 1.0, 1.0 - 2.0 * y, 0.0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polygon_samples_stay_inside_the_blades() {
+        let aperture = Aperture::Polygon {
+            blades: 6,
+            rotation: 0.0,
+        };
+        let mut rng = rand::thread_rng();
+
+        // The apothem of a hexagon inscribed in the unit circle
+        let apothem = (PI / 6.0).cos();
+        let mut outside_inscribed_circle = false;
+
+        for _ in 0..1000 {
+            let p = aperture.sample(&mut rng);
+            assert!(p.lenght() <= 1.0 + 1e-6);
+
+            // Distance from the center along each edge normal
+            for i in 0..6 {
+                let angle = (i as f32 + 0.5) * PI / 3.0;
+                let d = p.x() * angle.cos() + p.y() * angle.sin();
+                assert!(d <= apothem + 1e-5);
+            }
+
+            outside_inscribed_circle |= p.lenght() > apothem;
+        }
+
+        assert!(outside_inscribed_circle);
+    }
+
+    #[test]
+    fn mask_samples_follow_the_image() {
+        // Only the top left quarter of the mask is open
+        let mut pixels = vec![Vec3::zero(); 16];
+        for y in 0..2 {
+            for x in 0..2 {
+                pixels[y * 4 + x] = Vec3::new(1.0, 1.0, 1.0);
+            }
+        }
+        let aperture = Aperture::mask(&ImageTexture {
+            width: 4,
+            height: 4,
+            pixels,
+        });
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100 {
+            let p = aperture.sample(&mut rng);
+            assert!(p.x() <= 0.0 && p.y() >= 0.0);
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use crate::ray::Ray;
+use crate::stats;
+use crate::vec3::Vec3;
+
+/// Primitives per leaf below which nodes are not split further.
+const MAX_LEAF_SIZE: usize = 4;
+
+/// Axis aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Box containing nothing, which grows to fit whatever is added to it.
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Vec3::new(f32::MAX, f32::MAX, f32::MAX),
+            max: Vec3::new(f32::MIN, f32::MIN, f32::MIN),
+        }
+    }
+
+    /// Box containing all of space, for unbounded objects.
+    pub fn infinite() -> Aabb {
+        Aabb {
+            min: Vec3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+            max: Vec3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        }
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Aabb {
+        points.iter().fold(Aabb::empty(), |b, &p| b.grow(p))
+    }
+
+    pub fn grow(self, p: Vec3) -> Aabb {
+        Aabb {
+            min: Vec3::new(
+                self.min.x().min(p.x()),
+                self.min.y().min(p.y()),
+                self.min.z().min(p.z()),
+            ),
+            max: Vec3::new(
+                self.max.x().max(p.x()),
+                self.max.y().max(p.y()),
+                self.max.z().max(p.z()),
+            ),
+        }
+    }
+
+    pub fn union(self, other: Aabb) -> Aabb {
+        self.grow(other.min).grow(other.max)
+    }
+
+    /// Overlap of two boxes, which is empty if they do not touch.
+    pub fn intersection(self, other: Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::new(
+                self.min.x().max(other.min.x()),
+                self.min.y().max(other.min.y()),
+                self.min.z().max(other.min.z()),
+            ),
+            max: Vec3::new(
+                self.max.x().min(other.max.x()),
+                self.max.y().min(other.max.y()),
+                self.max.z().min(other.max.z()),
+            ),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    /// Whether `p` is inside the box or on its boundary.
+    pub fn contains(&self, p: Vec3) -> bool {
+        (0..3).all(|i| self.min[i] <= p[i] && p[i] <= self.max[i])
+    }
+
+    /// Index of the axis along which the box is longest.
+    pub fn longest_axis(&self) -> usize {
+        let size = self.max - self.min;
+        if size.x() >= size.y() && size.x() >= size.z() {
+            0
+        } else if size.y() >= size.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Whether the ray enters the box between `t_min` and `t_max`, using
+    /// the precomputed reciprocal of its direction.
+    pub fn hit(&self, ray: &Ray, inv_direction: Vec3, t_min: f32, t_max: f32) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+
+        for axis in 0..3 {
+            let t0 = (self.min[axis] - ray.origin[axis]) * inv_direction[axis];
+            let t1 = (self.max[axis] - ray.origin[axis]) * inv_direction[axis];
+            let (t0, t1) = if t0 <= t1 { (t0, t1) } else { (t1, t0) };
+
+            // Written so that NaNs from 0 * inf leave the interval alone
+            if t0 > t_min {
+                t_min = t0;
+            }
+            if t1 < t_max {
+                t_max = t1;
+            }
+            if t_min > t_max {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    bounds: Aabb,
+    /// Index of the first primitive for leaves, or of the second child for
+    /// interior nodes, whose first child follows them directly.
+    offset: u32,
+    /// Number of primitives, zero for interior nodes.
+    count: u32,
+}
+
+/// Bounding volume hierarchy over a list of primitives, which are split
+/// in half along the longest axis of their centers at every level.
+#[derive(Clone, Debug)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// Primitive indices, ordered so that every leaf covers a range.
+    indices: Vec<u32>,
+}
+
+impl Bvh {
+    pub fn build(bounds: &[Aabb]) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * bounds.len() / MAX_LEAF_SIZE + 1),
+            indices: (0..bounds.len() as u32).collect(),
+        };
+
+        if !bounds.is_empty() {
+            bvh.build_node(bounds, 0, bounds.len());
+        }
+
+        bvh
+    }
+
+    fn build_node(&mut self, bounds: &[Aabb], start: usize, end: usize) -> usize {
+        let indices = &mut self.indices[start..end];
+        let node_bounds = indices
+            .iter()
+            .fold(Aabb::empty(), |b, &i| b.union(bounds[i as usize]));
+
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            bounds: node_bounds,
+            offset: start as u32,
+            count: (end - start) as u32,
+        });
+
+        if end - start <= MAX_LEAF_SIZE {
+            return index;
+        }
+
+        let centers = indices
+            .iter()
+            .fold(Aabb::empty(), |b, &i| b.grow(bounds[i as usize].center()));
+        let axis = centers.longest_axis();
+
+        let mid = (end - start) / 2;
+        indices.select_nth_unstable_by(mid, |&a, &b| {
+            let a = bounds[a as usize].center()[axis];
+            let b = bounds[b as usize].center()[axis];
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        self.build_node(bounds, start, start + mid);
+        let second = self.build_node(bounds, start + mid, end);
+
+        self.nodes[index].offset = second as u32;
+        self.nodes[index].count = 0;
+        index
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::empty(), |n| n.bounds)
+    }
+
+    /// Visits the primitives whose bounds the ray passes through between
+    /// `t_min` and `t_max`. The callback returns the distance of a closer
+    /// hit, if it finds one, which then limits the rest of the search.
+    pub fn traverse<F>(&self, ray: &Ray, t_min: f32, t_max: f32, mut intersect: F)
+    where
+        F: FnMut(usize, f32) -> Option<f32>,
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let inv_direction = Vec3::new(
+            1.0 / ray.direction.x(),
+            1.0 / ray.direction.y(),
+            1.0 / ray.direction.z(),
+        );
+
+        let mut t_max = t_max;
+        let mut stack = [0u32; 64];
+        let mut stack_size = 1;
+
+        while stack_size > 0 {
+            stack_size -= 1;
+            let node = &self.nodes[stack[stack_size] as usize];
+            stats::NODE_VISITS.add(1);
+
+            if !node.bounds.hit(ray, inv_direction, t_min, t_max) {
+                continue;
+            }
+
+            if node.count > 0 {
+                let start = node.offset as usize;
+                let end = start + node.count as usize;
+                stats::PRIMITIVE_TESTS.add(u64::from(node.count));
+
+                for &i in &self.indices[start..end] {
+                    if let Some(t) = intersect(i as usize, t_max) {
+                        t_max = t;
+                    }
+                }
+            } else {
+                let first = stack[stack_size] + 1;
+                stack[stack_size] = node.offset;
+                stack[stack_size + 1] = first;
+                stack_size += 2;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_closest_primitive() {
+        // A row of unit boxes along x
+        let boxes: Vec<Aabb> = (0..100)
+            .map(|i| Aabb {
+                min: Vec3::new(i as f32, 0.0, 0.0),
+                max: Vec3::new(i as f32 + 0.5, 1.0, 1.0),
+            })
+            .collect();
+        let bvh = Bvh::build(&boxes);
+        assert_eq!(bvh.bounds().max, Vec3::new(99.5, 1.0, 1.0));
+
+        let ray = Ray::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        let mut tested = Vec::new();
+        let mut closest = None;
+        bvh.traverse(&ray, 0.0, f32::MAX, |i, t_max| {
+            tested.push(i);
+            let t = boxes[i].min.x() + 1.0;
+            if t < t_max {
+                closest = Some(i);
+                Some(t)
+            } else {
+                None
+            }
+        });
+
+        assert_eq!(closest, Some(0));
+        // Boxes far behind the first hit are culled
+        assert!(tested.len() < 20);
+
+        let miss = Ray::new(Vec3::new(-1.0, 2.0, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        bvh.traverse(&miss, 0.0, f32::MAX, |_, _| panic!("nothing to hit"));
+    }
+}
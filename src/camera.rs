@@ -1,18 +1,49 @@
+use std::f32::consts::PI;
+
+use crate::aperture::Aperture;
 use crate::ray::Ray;
 use crate::vec3::Vec3;
 
-pub struct Camera {
+/// Maps positions on the film to primary rays.
+pub trait Camera: Send + Sync {
+    /// Ray through the film position `(u, v)`, both in `[0, 1]` with `v`
+    /// growing upwards, or `None` if the position is outside the area the
+    /// projection covers.
+    fn get_ray(&self, u: f32, v: f32, rng: &mut dyn rand::RngCore) -> Option<Ray>;
+}
+
+/// Right-handed basis looking from `look_from` to `look_at`, where `w`
+/// points backwards like in OpenGL.
+pub(crate) fn look_at_basis(look_from: Vec3, look_at: Vec3, up: Vec3) -> (Vec3, Vec3, Vec3) {
+    let w = (look_from - look_at).normalized();
+    let u = up.cross(w).normalized();
+    let v = w.cross(u);
+
+    (u, v, w)
+}
+
+/// Pinhole or thin lens camera with a rectilinear projection.
+///
+/// A nonzero `cat_eye` models the lens barrel clipping the aperture away
+/// from the image center, which darkens the corners and squeezes
+/// out-of-focus highlights into cat's eye shapes there. It is the offset
+/// of the barrel opening at the top and bottom edges of the frame, in lens
+/// radii.
+pub struct PerspectiveCamera {
     pub origin: Vec3,
     pub lower_left_corner: Vec3,
     pub horizontal: Vec3,
     pub vertical: Vec3,
     pub lens_radius: f32,
+    pub aperture: Aperture,
+    pub cat_eye: f32,
+    aspect: f32,
     u: Vec3,
     v: Vec3,
     w: Vec3,
 }
 
-impl Camera {
+impl PerspectiveCamera {
     pub fn new(
         look_from: Vec3,
         look_at: Vec3,
@@ -21,41 +52,264 @@ impl Camera {
         aspect: f32,
         aperture: f32,
         focus_dist: f32,
-    ) -> Camera {
+    ) -> PerspectiveCamera {
         let lens_radius = aperture / 2.0;
         let half_height = (fov.to_radians() / 2.0).tan();
         let half_width = aspect * half_height;
 
         let origin = look_from;
-        let w = (look_from - look_at).normalized();
-        let u = up.cross(w).normalized();
-        let v = w.cross(u);
+        let (u, v, w) = look_at_basis(look_from, look_at, up);
 
         let lower_left_corner = origin
             - (half_width * focus_dist * u)
             - (half_height * focus_dist * v)
             - w * focus_dist;
 
-        Camera {
+        PerspectiveCamera {
             origin,
             lower_left_corner,
             horizontal: u * half_width * 2.0 * focus_dist,
             vertical: v * half_height * 2.0 * focus_dist,
             lens_radius,
+            aperture: Aperture::Circular,
+            cat_eye: 0.0,
+            aspect,
             u,
             v,
             w,
         }
     }
+}
+
+impl Camera for PerspectiveCamera {
+    fn get_ray(&self, u: f32, v: f32, rng: &mut dyn rand::RngCore) -> Option<Ray> {
+        let lens = self.aperture.sample(rng);
+
+        if self.cat_eye > 0.0 {
+            // The barrel opening seen from off-axis points is a second disk
+            // shifted away from the image position
+            let field = Vec3::new((2.0 * u - 1.0) * self.aspect, 2.0 * v - 1.0, 0.0);
+            if (lens + self.cat_eye * field).lenght_squared() > 1.0 {
+                return None;
+            }
+        }
 
-    pub fn get_ray(&self, u: f32, v: f32, rng: &mut rand::RngCore) -> Ray {
-        let point_in_lens = self.lens_radius * Vec3::random_in_unit_disk(rng);
+        let point_in_lens = self.lens_radius * lens;
         let offset = self.u * point_in_lens.x() + self.v * point_in_lens.y();
-        Ray {
+        Some(Ray {
             origin: self.origin + offset,
             direction: self.lower_left_corner + u * self.horizontal + v * self.vertical
                 - self.origin
                 - offset,
+        })
+    }
+}
+
+/// Parallel projection, where `height` is the extent of the view in world
+/// units. Useful for technical drawings and isometric views.
+pub struct OrthographicCamera {
+    pub lower_left_corner: Vec3,
+    pub horizontal: Vec3,
+    pub vertical: Vec3,
+    pub direction: Vec3,
+}
+
+impl OrthographicCamera {
+    pub fn new(
+        look_from: Vec3,
+        look_at: Vec3,
+        up: Vec3,
+        height: f32,
+        aspect: f32,
+    ) -> OrthographicCamera {
+        let (u, v, w) = look_at_basis(look_from, look_at, up);
+        let horizontal = u * height * aspect;
+        let vertical = v * height;
+
+        OrthographicCamera {
+            lower_left_corner: look_from - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            direction: -w,
         }
     }
 }
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, u: f32, v: f32, _: &mut dyn rand::RngCore) -> Option<Ray> {
+        Some(Ray::new(
+            self.lower_left_corner + u * self.horizontal + v * self.vertical,
+            self.direction,
+        ))
+    }
+}
+
+/// Circular fisheye with an equidistant projection, where the distance from
+/// the image center is proportional to the angle from the view axis.
+///
+/// The image circle spans the height of the frame and covers `fov` degrees,
+/// which may exceed 180. Positions outside of it produce no rays.
+pub struct FisheyeCamera {
+    pub origin: Vec3,
+    pub fov: f32,
+    pub aspect: f32,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl FisheyeCamera {
+    pub fn new(look_from: Vec3, look_at: Vec3, up: Vec3, fov: f32, aspect: f32) -> FisheyeCamera {
+        let (u, v, w) = look_at_basis(look_from, look_at, up);
+
+        FisheyeCamera {
+            origin: look_from,
+            fov,
+            aspect,
+            u,
+            v,
+            w,
+        }
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, u: f32, v: f32, _: &mut dyn rand::RngCore) -> Option<Ray> {
+        let x = (2.0 * u - 1.0) * self.aspect;
+        let y = 2.0 * v - 1.0;
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+
+        let theta = r * self.fov.to_radians() / 2.0;
+        let phi = y.atan2(x);
+
+        let direction =
+            theta.sin() * (phi.cos() * self.u + phi.sin() * self.v) - theta.cos() * self.w;
+
+        Some(Ray::new(self.origin, direction))
+    }
+}
+
+/// Full 360 by 180 degree latitude-longitude panorama, centered on the
+/// view direction.
+pub struct EquirectangularCamera {
+    pub origin: Vec3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl EquirectangularCamera {
+    pub fn new(look_from: Vec3, look_at: Vec3, up: Vec3) -> EquirectangularCamera {
+        let (u, v, w) = look_at_basis(look_from, look_at, up);
+
+        EquirectangularCamera {
+            origin: look_from,
+            u,
+            v,
+            w,
+        }
+    }
+
+    /// Unit direction for the panorama position `(u, v)`.
+    pub fn direction(&self, u: f32, v: f32) -> Vec3 {
+        let longitude = (u - 0.5) * 2.0 * PI;
+        let latitude = (v - 0.5) * PI;
+
+        latitude.cos() * (longitude.sin() * self.u - longitude.cos() * self.w)
+            + latitude.sin() * self.v
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, u: f32, v: f32, _: &mut dyn rand::RngCore) -> Option<Ray> {
+        Some(Ray::new(self.origin, self.direction(u, v)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::rng::Pcg32;
+
+    /// All cameras look down the `-z` axis from here.
+    const FROM: Vec3 = Vec3 { e: [0.0, 0.0, 5.0] };
+
+    fn ray(camera: &dyn Camera, u: f32, v: f32) -> Option<Ray> {
+        camera.get_ray(u, v, &mut Pcg32::new(0, 0))
+    }
+
+    fn assert_direction(camera: &dyn Camera, u: f32, v: f32, direction: Vec3) {
+        let ray = ray(camera, u, v).unwrap();
+        assert!(
+            (ray.direction.normalized() - direction).lenght() < 1e-4,
+            "({}, {}) looks along {:?}",
+            u,
+            v,
+            ray.direction
+        );
+        assert_eq!(ray.origin, FROM);
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let camera = OrthographicCamera::new(FROM, Vec3::zero(), Vec3::up(), 2.0, 2.0);
+        let forward = Vec3::new(0.0, 0.0, -1.0);
+
+        for &(u, v, origin) in &[
+            (0.5, 0.5, FROM),
+            (0.0, 0.0, Vec3::new(-2.0, -1.0, 5.0)),
+            (1.0, 1.0, Vec3::new(2.0, 1.0, 5.0)),
+            (1.0, 0.0, Vec3::new(2.0, -1.0, 5.0)),
+        ] {
+            let ray = ray(&camera, u, v).unwrap();
+            assert!((ray.origin - origin).lenght() < 1e-5, "{:?}", ray.origin);
+            assert_eq!(ray.direction.normalized(), forward);
+        }
+    }
+
+    #[test]
+    fn fisheye_angle_grows_with_the_radius() {
+        let camera = FisheyeCamera::new(FROM, Vec3::zero(), Vec3::up(), 180.0, 1.5);
+
+        assert_direction(&camera, 0.5, 0.5, Vec3::new(0.0, 0.0, -1.0));
+        // The rim of the image circle is 90 degrees off the axis
+        assert_direction(&camera, 0.5, 1.0, Vec3::up());
+        // Halfway to the rim is 45 degrees off the axis, with the radius
+        // in units of the frame height
+        let right = Vec3::new(1.0, 0.0, -1.0).normalized();
+        assert_direction(&camera, 0.5 + 0.5 / 3.0, 0.5, right);
+        let offset = 0.5 / 2.0f32.sqrt();
+        let diagonal = Vec3::new(-1.0, -1.0, -2.0f32.sqrt()).normalized();
+        assert_direction(&camera, 0.5 - offset / 3.0, 0.5 - offset / 2.0, diagonal);
+
+        // Corners and the sides of the wide frame are outside the circle
+        for &(u, v) in &[(0.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.5), (0.9, 0.5)] {
+            assert!(ray(&camera, u, v).is_none(), "({}, {})", u, v);
+        }
+
+        // Wider than a hemisphere, the rim looks backwards
+        let wide = FisheyeCamera::new(FROM, Vec3::zero(), Vec3::up(), 360.0, 1.0);
+        assert_direction(&wide, 0.5, 0.0, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn equirectangular_covers_the_whole_sphere() {
+        let camera = EquirectangularCamera::new(FROM, Vec3::zero(), Vec3::up());
+
+        assert_direction(&camera, 0.5, 0.5, Vec3::new(0.0, 0.0, -1.0));
+        assert_direction(&camera, 0.75, 0.5, Vec3::new(1.0, 0.0, 0.0));
+        assert_direction(&camera, 0.25, 0.5, Vec3::new(-1.0, 0.0, 0.0));
+        assert_direction(&camera, 0.0, 0.5, Vec3::new(0.0, 0.0, 1.0));
+
+        // The bottom and top corners are the poles
+        assert_direction(&camera, 0.0, 0.0, Vec3::down());
+        assert_direction(&camera, 1.0, 1.0, Vec3::up());
+
+        let ray = ray(&camera, 0.3, 0.8).unwrap();
+        assert!((ray.direction.lenght() - 1.0).abs() < 1e-5);
+    }
+}
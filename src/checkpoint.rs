@@ -0,0 +1,273 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::film::Film;
+use crate::rng::Pcg32;
+
+const CHECKPOINT_MAGIC: &[u8; 4] = b"CKPT";
+const CHECKPOINT_VERSION: u32 = 3;
+
+/// Everything needed to continue an interrupted render and end up with
+/// the same image as if it had never stopped.
+pub struct Checkpoint {
+    pub film: Film,
+    /// First row of the frame, counted from the top, that still has to be
+    /// rendered.
+    pub next_row: u32,
+    /// Whether the render goes over the whole window in passes instead of
+    /// row by row. Neither can be continued in the other mode.
+    pub progressive: bool,
+    /// Passes completed by a progressive render, which always end on a
+    /// full pass, or zero when rendering row by row.
+    pub passes: u32,
+    /// Seed the scene was generated with.
+    pub seed: u64,
+    pub rng: Pcg32,
+}
+
+impl Checkpoint {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        save(
+            path,
+            &self.film,
+            self.next_row,
+            self.progressive,
+            self.passes,
+            self.seed,
+            &self.rng,
+        )
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write(
+            w,
+            &self.film,
+            self.next_row,
+            self.progressive,
+            self.passes,
+            self.seed,
+            &self.rng,
+        )
+    }
+
+    pub fn load(path: &Path) -> io::Result<Checkpoint> {
+        Checkpoint::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn read<R: Read>(r: &mut R) -> io::Result<Checkpoint> {
+        let mut header = [0; 8];
+        r.read_exact(&mut header)?;
+        if &header[..4] != CHECKPOINT_MAGIC || header[4..] != CHECKPOINT_VERSION.to_le_bytes() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a checkpoint file or unsupported version",
+            ));
+        }
+
+        let mut next_row = [0; 4];
+        let mut progressive = [0; 1];
+        let mut passes = [0; 4];
+        let mut seed = [0; 8];
+        let mut state = [0; 8];
+        let mut inc = [0; 8];
+        r.read_exact(&mut next_row)?;
+        r.read_exact(&mut progressive)?;
+        r.read_exact(&mut passes)?;
+        r.read_exact(&mut seed)?;
+        r.read_exact(&mut state)?;
+        r.read_exact(&mut inc)?;
+
+        let checkpoint = Checkpoint {
+            next_row: u32::from_le_bytes(next_row),
+            progressive: progressive[0] != 0,
+            passes: u32::from_le_bytes(passes),
+            seed: u64::from_le_bytes(seed),
+            rng: Pcg32 {
+                state: u64::from_le_bytes(state),
+                inc: u64::from_le_bytes(inc),
+            },
+            film: Film::read(r)?,
+        };
+
+        // Rendering continues from this row, which has to be in the window
+        let window = checkpoint.film.window;
+        if checkpoint.next_row < window.y || checkpoint.next_row > window.y + window.height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "checkpoint row outside of the film window",
+            ));
+        }
+
+        Ok(checkpoint)
+    }
+}
+
+/// Saves checkpoints of a running render to `path` at most once every
+/// `interval`.
+pub struct Checkpointer {
+    pub path: PathBuf,
+    pub interval: Duration,
+    pub seed: u64,
+    pub progressive: bool,
+    last: Instant,
+}
+
+impl Checkpointer {
+    pub fn new(path: PathBuf, interval: Duration, seed: u64, progressive: bool) -> Checkpointer {
+        Checkpointer {
+            path,
+            interval,
+            seed,
+            progressive,
+            last: Instant::now(),
+        }
+    }
+
+    /// Saves the state of the render if the interval has passed.
+    pub fn update(
+        &mut self,
+        film: &Film,
+        next_row: u32,
+        passes: u32,
+        rng: &Pcg32,
+    ) -> io::Result<()> {
+        if self.last.elapsed() < self.interval {
+            return Ok(());
+        }
+
+        save(
+            &self.path,
+            film,
+            next_row,
+            self.progressive,
+            passes,
+            self.seed,
+            rng,
+        )?;
+        self.last = Instant::now();
+
+        Ok(())
+    }
+}
+
+/// Writes the checkpoint next to `path` first and then moves it in place,
+/// so an interruption while saving never leaves a broken file.
+fn save(
+    path: &Path,
+    film: &Film,
+    next_row: u32,
+    progressive: bool,
+    passes: u32,
+    seed: u64,
+    rng: &Pcg32,
+) -> io::Result<()> {
+    let mut tmp = PathBuf::from(path).into_os_string();
+    tmp.push(".tmp");
+
+    {
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        write(&mut w, film, next_row, progressive, passes, seed, rng)?;
+        w.flush()?;
+    }
+
+    fs::rename(tmp, path)
+}
+
+fn write<W: Write>(
+    w: &mut W,
+    film: &Film,
+    next_row: u32,
+    progressive: bool,
+    passes: u32,
+    seed: u64,
+    rng: &Pcg32,
+) -> io::Result<()> {
+    w.write_all(CHECKPOINT_MAGIC)?;
+    w.write_all(&CHECKPOINT_VERSION.to_le_bytes())?;
+    w.write_all(&next_row.to_le_bytes())?;
+    w.write_all(&[progressive as u8])?;
+    w.write_all(&passes.to_le_bytes())?;
+    w.write_all(&seed.to_le_bytes())?;
+    w.write_all(&rng.state.to_le_bytes())?;
+    w.write_all(&rng.inc.to_le_bytes())?;
+    film.write(w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn checkpoints_round_trip() {
+        let mut film = Film::new(3, 2);
+        film.pixel_mut(1, 1).add_sample(Vec3::new(0.5, 1.0, 2.0));
+        film.pixel_mut(1, 1).add_sample(Vec3::new(1.5, 1.0, 0.0));
+
+        let checkpoint = Checkpoint {
+            film,
+            next_row: 1,
+            progressive: false,
+            passes: 0,
+            seed: 7,
+            rng: Pcg32::new(7, 3),
+        };
+
+        let mut saved = Vec::new();
+        checkpoint.write(&mut saved).unwrap();
+        let loaded = Checkpoint::read(&mut &saved[..]).unwrap();
+
+        assert_eq!(loaded.next_row, 1);
+        assert!(!loaded.progressive);
+        assert_eq!(loaded.seed, 7);
+        assert_eq!(loaded.rng, checkpoint.rng);
+
+        let (a, b) = (&loaded.film.pixels[1], &checkpoint.film.pixels[1]);
+        assert_eq!(a.samples, 2);
+        assert_eq!(a.color(), b.color());
+        assert_eq!(a.variance(), b.variance());
+
+        assert!(Checkpoint::read(&mut &saved[1..]).is_err());
+    }
+
+    #[test]
+    fn rejects_rows_outside_the_window() {
+        let mut checkpoint = Checkpoint {
+            film: Film::new(3, 2),
+            next_row: 2,
+            progressive: true,
+            passes: 9,
+            seed: 7,
+            rng: Pcg32::new(7, 3),
+        };
+
+        let mut saved = Vec::new();
+        checkpoint.write(&mut saved).unwrap();
+        let loaded = Checkpoint::read(&mut &saved[..]).unwrap();
+        assert!(loaded.progressive);
+        assert_eq!(loaded.passes, 9);
+
+        checkpoint.next_row = 3;
+        let mut saved = Vec::new();
+        checkpoint.write(&mut saved).unwrap();
+        assert!(Checkpoint::read(&mut &saved[..]).is_err());
+    }
+
+    #[test]
+    fn saving_leaves_files_with_other_extensions_alone() {
+        let dir = std::env::temp_dir();
+        let name = format!("raytracer-{}-render", std::process::id());
+        let (path, other) = (dir.join(format!("{}.ckpt", name)), dir.join(name + ".tmp"));
+        fs::write(&other, b"unrelated").unwrap();
+
+        save(&path, &Film::new(3, 2), 0, false, 0, 7, &Pcg32::new(7, 3)).unwrap();
+        assert!(Checkpoint::load(&path).is_ok());
+        assert_eq!(fs::read(&other).unwrap(), b"unrelated");
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&other).unwrap();
+    }
+}
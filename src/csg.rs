@@ -0,0 +1,215 @@
+use crate::bvh::Aabb;
+use crate::geometry::{HitInfo, Hitable, Interval};
+use crate::ray::Ray;
+
+/// How a `Csg` node combines the solids of its two children.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    Union,
+    Intersection,
+    /// The left solid with the right one cut out of it.
+    Difference,
+}
+
+impl Operation {
+    fn inside(self, left: bool, right: bool) -> bool {
+        match self {
+            Operation::Union => left || right,
+            Operation::Intersection => left && right,
+            Operation::Difference => left && !right,
+        }
+    }
+}
+
+/// Solid made by combining two others, which must report the intervals
+/// that rays spend inside them; see `Hitable::intervals` for the shapes
+/// that do. Anything else, such as a plane or an open mesh, contributes
+/// nothing. Nodes can be nested.
+///
+/// Surfaces left behind by cutting into a solid keep the material of the
+/// solid that did the cutting.
+pub struct Csg {
+    pub operation: Operation,
+    pub left: Box<dyn Hitable>,
+    pub right: Box<dyn Hitable>,
+}
+
+impl Csg {
+    pub fn union(left: Box<dyn Hitable>, right: Box<dyn Hitable>) -> Csg {
+        Csg {
+            operation: Operation::Union,
+            left,
+            right,
+        }
+    }
+
+    pub fn intersection(left: Box<dyn Hitable>, right: Box<dyn Hitable>) -> Csg {
+        Csg {
+            operation: Operation::Intersection,
+            left,
+            right,
+        }
+    }
+
+    pub fn difference(left: Box<dyn Hitable>, right: Box<dyn Hitable>) -> Csg {
+        Csg {
+            operation: Operation::Difference,
+            left,
+            right,
+        }
+    }
+}
+
+impl Hitable for Csg {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo<'_>> {
+        self.intervals(ray)
+            .into_iter()
+            .flat_map(|interval| [interval.enter, interval.exit])
+            .find(|hit| hit.t > t_min && hit.t < t_max)
+    }
+
+    fn bounds(&self) -> Aabb {
+        match self.operation {
+            Operation::Union => self.left.bounds().union(self.right.bounds()),
+            Operation::Intersection => self.left.bounds().intersection(self.right.bounds()),
+            Operation::Difference => self.left.bounds(),
+        }
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        let left = self.left.intervals(ray);
+        let right = if left.is_empty() && self.operation != Operation::Union {
+            Vec::new()
+        } else {
+            self.right.intervals(ray)
+        };
+        combine(self.operation, &left, &right)
+    }
+}
+
+/// Walks along the boundaries of both children in order, keeping track of
+/// which of them the ray is inside, and records where that changes whether
+/// it is inside the combined solid.
+fn combine<'a>(
+    operation: Operation,
+    left: &[Interval<'a>],
+    right: &[Interval<'a>],
+) -> Vec<Interval<'a>> {
+    let boundaries = |intervals: &[Interval<'a>], is_left: bool| {
+        intervals
+            .iter()
+            .flat_map(move |i| [(i.enter, is_left, true), (i.exit, is_left, false)])
+            .collect::<Vec<_>>()
+    };
+    let mut events = boundaries(left, true);
+    events.extend(boundaries(right, false));
+    events.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+
+    let (mut in_left, mut in_right) = (false, false);
+    let mut enter = None;
+    let mut result = Vec::new();
+    for (mut hit, is_left, entering) in events {
+        let was_inside = operation.inside(in_left, in_right);
+        if is_left {
+            in_left = entering;
+        } else {
+            in_right = entering;
+        }
+        let inside = operation.inside(in_left, in_right);
+        if inside == was_inside {
+            continue;
+        }
+
+        // Entering the solid that is cut away leaves the result, so the
+        // normal there has to face the other way
+        if inside != entering {
+            hit.normal = -hit.normal;
+        }
+        if inside {
+            enter = Some(hit);
+        } else if let Some(enter) = enter.take() {
+            result.push(Interval { enter, exit: hit });
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::geometry::Sphere;
+    use crate::shapes::Cylinder;
+    use crate::test_util::material;
+    use crate::vec3::Vec3;
+
+    fn spheres(operation: Operation) -> Csg {
+        // Unit spheres overlapping between x = 0 and x = 1
+        Csg {
+            operation,
+            left: Box::new(Sphere::new(Vec3::zero(), 1.0, material())),
+            right: Box::new(Sphere::new(Vec3::new(1.0, 0.0, 0.0), 1.0, material())),
+        }
+    }
+
+    fn assert_hit(csg: &Csg, ray: &Ray, t_min: f32, t: f32, normal: Vec3) {
+        let hit = csg.hit(ray, t_min, f32::MAX).unwrap();
+        assert!((hit.t - t).abs() < 1e-4, "{} != {}", hit.t, t);
+        assert!((hit.normal - normal).lenght() < 1e-4, "{:?}", hit.normal);
+    }
+
+    #[test]
+    fn combines_overlapping_spheres() {
+        let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let (left, right) = (Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+
+        let union = spheres(Operation::Union);
+        assert_hit(&union, &ray, 0.001, 4.0, left);
+        assert_hit(&union, &ray, 4.5, 7.0, right);
+        assert_eq!(union.intervals(&ray).len(), 1);
+
+        let intersection = spheres(Operation::Intersection);
+        assert_hit(&intersection, &ray, 0.001, 5.0, left);
+        assert_hit(&intersection, &ray, 5.5, 6.0, right);
+        assert_eq!(intersection.bounds().min.x(), 0.0);
+
+        // The cut face belongs to the right sphere, facing out of the left
+        let difference = spheres(Operation::Difference);
+        assert_hit(&difference, &ray, 0.001, 4.0, left);
+        assert_hit(&difference, &ray, 4.5, 5.0, right);
+        assert!(difference.hit(&ray, 5.5, f32::MAX).is_none());
+
+        // Starting inside the result, behind the first boundary
+        let back = Ray::new(Vec3::new(-0.5, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert_hit(&difference, &back, 0.001, 0.5, left);
+    }
+
+    #[test]
+    fn drills_holes_through_nested_solids() {
+        let ball = Csg::union(
+            Box::new(Sphere::new(Vec3::zero(), 1.0, material())),
+            Box::new(Sphere::new(Vec3::new(0.0, 0.0, 1.0), 0.5, material())),
+        );
+        let drilled = Csg::difference(
+            Box::new(ball),
+            Box::new(Cylinder::new(
+                Vec3::new(0.0, -2.0, 0.0),
+                Vec3::new(0.0, 2.0, 0.0),
+                0.3,
+                material(),
+            )),
+        );
+
+        let down = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(drilled.hit(&down, 0.001, f32::MAX).is_none());
+
+        // Across the hole, hitting its wall from inside the hole
+        let across = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_hit(&drilled, &across, 0.001, 0.3, Vec3::new(-1.0, 0.0, 0.0));
+
+        let side = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_hit(&drilled, &side, 0.001, 3.5, Vec3::new(0.0, 0.0, 1.0));
+        assert_hit(&drilled, &side, 3.6, 4.7, Vec3::new(0.0, 0.0, -1.0));
+    }
+}
@@ -0,0 +1,178 @@
+/// Piecewise constant 1D distribution over `[0, 1)`, sampled by inverting
+/// its cumulative distribution function.
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution1D {
+    pub fn new(func: &[f32]) -> Distribution1D {
+        let n = func.len();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for i in 0..n {
+            cdf.push(cdf[i] + func[i].max(0.0) / n as f32);
+        }
+
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as f32 / n as f32
+            };
+        }
+
+        Distribution1D {
+            func: func.iter().map(|f| f.max(0.0)).collect(),
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.func.is_empty()
+    }
+
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    /// Maps `u` to a sample in `[0, 1)`, returning it with its density and
+    /// the index of the segment it landed in.
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        // Last entry whose cdf is not above u, which skips empty segments
+        let index = self
+            .cdf
+            .partition_point(|&c| c <= u)
+            .saturating_sub(1)
+            .min(self.len() - 1);
+
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0.0 {
+            (u - self.cdf[index]) / width
+        } else {
+            0.0
+        };
+
+        let x = ((index as f32 + offset) / self.len() as f32).min(1.0 - f32::EPSILON);
+        (x, self.pdf_at(index), index)
+    }
+
+    /// Density of the segment `index`.
+    pub fn pdf_at(&self, index: usize) -> f32 {
+        if self.integral > 0.0 {
+            self.func[index] / self.integral
+        } else {
+            1.0
+        }
+    }
+
+    pub fn pdf(&self, x: f32) -> f32 {
+        let index = ((x * self.len() as f32) as usize).min(self.len() - 1);
+        self.pdf_at(index)
+    }
+}
+
+/// Piecewise constant 2D distribution over `[0, 1)^2`, built from a row
+/// major grid of values with `y` indexing the rows.
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f32], width: usize, height: usize) -> Distribution2D {
+        let rows: Vec<Distribution1D> = (0..height)
+            .map(|y| Distribution1D::new(&func[y * width..(y + 1) * width]))
+            .collect();
+        let marginal: Vec<f32> = rows.iter().map(|r| r.integral()).collect();
+
+        Distribution2D {
+            rows,
+            marginal: Distribution1D::new(&marginal),
+        }
+    }
+
+    /// Maps `(u1, u2)` to a point in `[0, 1)^2` and its density.
+    pub fn sample(&self, u1: f32, u2: f32) -> (f32, f32, f32) {
+        let (y, pdf_y, row) = self.marginal.sample(u2);
+        let (x, pdf_x, _) = self.rows[row].sample(u1);
+
+        (x, y, pdf_x * pdf_y)
+    }
+
+    pub fn pdf(&self, x: f32, y: f32) -> f32 {
+        let row = ((y * self.rows.len() as f32) as usize).min(self.rows.len() - 1);
+        self.marginal.pdf_at(row) * self.rows[row].pdf(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn densities_integrate_to_one() {
+        let line = Distribution1D::new(&[1.0, 0.0, 3.0, -2.0]);
+        assert_eq!(line.integral(), 1.0);
+        let total: f32 = (0..4).map(|i| line.pdf_at(i) / 4.0).sum();
+        assert!((total - 1.0).abs() < 1e-6);
+
+        let (width, height) = (3, 4);
+        let grid = Distribution2D::new(
+            &[1.0, 2.0, 3.0, 0.0, 0.0, 0.0, 5.0, 0.0, 1.0, 0.5, 0.5, 0.5],
+            width,
+            height,
+        );
+        let mut total = 0.0;
+        for y in 0..height {
+            for x in 0..width {
+                let (x, y) = ((x as f32 + 0.5) / 3.0, (y as f32 + 0.5) / 4.0);
+                total += grid.pdf(x, y) / (width * height) as f32;
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn samples_land_where_the_weight_is() {
+        let line = Distribution1D::new(&[1.0, 0.0, 3.0, -2.0]);
+        for i in 0..100 {
+            let u = i as f32 / 100.0;
+            let (x, pdf, index) = line.sample(u);
+            assert!(index == 0 || index == 2, "{} landed in {}", u, index);
+            assert_eq!(pdf, line.pdf(x));
+        }
+        assert!(line.sample(0.2).0 < 0.25);
+        assert!(line.sample(0.3).0 >= 0.5);
+
+        // The middle row has no weight at all
+        let grid = Distribution2D::new(&[1.0, 2.0, 0.0, 0.0, 4.0, 1.0], 2, 3);
+        for i in 0..100 {
+            for j in 0..100 {
+                let (x, y, pdf) = grid.sample(i as f32 / 100.0, j as f32 / 100.0);
+                assert!(!(1.0 / 3.0..2.0 / 3.0).contains(&y), "{}", y);
+                assert!((pdf - grid.pdf(x, y)).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_functions_are_sampled_uniformly() {
+        let line = Distribution1D::new(&[0.0, 0.0]);
+        assert_eq!(line.integral(), 0.0);
+        assert_eq!(line.sample(0.75), (0.75, 1.0, 1));
+
+        let grid = Distribution2D::new(&[0.0; 4], 2, 2);
+        let (x, y, pdf) = grid.sample(0.25, 0.75);
+        assert_eq!((x, y, pdf), (0.25, 0.75, 1.0));
+    }
+}
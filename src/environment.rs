@@ -0,0 +1,205 @@
+use std::f32::consts::PI;
+use std::io;
+use std::path::Path;
+
+use crate::distribution::Distribution2D;
+use crate::film::luminance;
+use crate::texture::ImageTexture;
+use crate::vec3::Vec3;
+
+use rand::prelude::*;
+
+/// Light arriving from infinitely far away, seen by rays that escape the
+/// scene.
+pub trait Environment: Send + Sync {
+    /// Radiance arriving along the unit vector `direction`, pointing away
+    /// from the scene.
+    fn radiance(&self, direction: Vec3) -> Vec3;
+
+    /// Picks a direction for next-event estimation, returning it with its
+    /// radiance and solid angle density.
+    ///
+    /// Environments without a useful sampling strategy return `None` and
+    /// are only found by rays scattered off surfaces.
+    fn sample(&self, _rng: &mut dyn rand::RngCore) -> Option<(Vec3, Vec3, f32)> {
+        None
+    }
+
+    /// Density with which `sample` picks `direction`.
+    fn pdf(&self, _direction: Vec3) -> f32 {
+        0.0
+    }
+}
+
+/// Vertical gradient from `horizon` to `zenith`, the classic sky.
+pub struct Gradient {
+    pub horizon: Vec3,
+    pub zenith: Vec3,
+}
+
+impl Gradient {
+    pub fn sky() -> Gradient {
+        Gradient {
+            horizon: Vec3::new(1.0, 1.0, 1.0),
+            zenith: Vec3::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+impl Environment for Gradient {
+    fn radiance(&self, direction: Vec3) -> Vec3 {
+        // Puts t in the range 0..1
+        let t = 0.5 * (direction.y() + 1.0);
+
+        (1.0 - t) * self.horizon + t * self.zenith
+    }
+}
+
+/// Equirectangular (latitude-longitude) environment map, importance
+/// sampled according to its luminance.
+pub struct EnvironmentMap {
+    pub image: ImageTexture,
+    /// Rotation around the vertical axis, in degrees.
+    pub rotation: f32,
+    pub intensity: f32,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    pub fn new(image: ImageTexture, rotation: f32, intensity: f32) -> EnvironmentMap {
+        let width = image.width as usize;
+        let height = image.height as usize;
+
+        // Rows near the poles cover less solid angle than the image suggests
+        let mut func = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let sin_theta = (PI * (y as f32 + 0.5) / height as f32).sin();
+            for x in 0..width {
+                func.push(luminance(image.pixels[y * width + x]) * sin_theta);
+            }
+        }
+
+        EnvironmentMap {
+            distribution: Distribution2D::new(&func, width, height),
+            image,
+            rotation,
+            intensity,
+        }
+    }
+
+    pub fn load(path: &Path, rotation: f32, intensity: f32) -> io::Result<EnvironmentMap> {
+        Ok(EnvironmentMap::new(
+            ImageTexture::load(path, false)?,
+            rotation,
+            intensity,
+        ))
+    }
+
+    /// Position of `direction` in the image, with `y` going down the rows.
+    fn direction_to_image(&self, direction: Vec3) -> (f32, f32) {
+        let phi = direction.z().atan2(direction.x()) - self.rotation.to_radians();
+        let theta = direction.y().clamp(-1.0, 1.0).acos();
+
+        ((phi / (2.0 * PI)).rem_euclid(1.0), theta / PI)
+    }
+
+    fn image_to_direction(&self, x: f32, y: f32) -> Vec3 {
+        let phi = x * 2.0 * PI + self.rotation.to_radians();
+        let theta = y * PI;
+
+        Vec3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: Vec3) -> Vec3 {
+        let (x, y) = self.direction_to_image(direction);
+
+        self.intensity * self.image.sample(x, 1.0 - y)
+    }
+
+    fn sample(&self, rng: &mut dyn rand::RngCore) -> Option<(Vec3, Vec3, f32)> {
+        let (x, y, pdf) = self.distribution.sample(rng.gen(), rng.gen());
+        let sin_theta = (y * PI).sin();
+        if pdf == 0.0 || sin_theta == 0.0 {
+            return None;
+        }
+
+        let direction = self.image_to_direction(x, y);
+        let pdf = pdf / (2.0 * PI * PI * sin_theta);
+
+        Some((direction, self.radiance(direction), pdf))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f32 {
+        let (x, y) = self.direction_to_image(direction);
+        let sin_theta = (y * PI).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+
+        self.distribution.pdf(x, y) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::rng::Pcg32;
+
+    fn environment() -> EnvironmentMap {
+        // A bright spot in a dim map, with an empty row near the pole
+        let (width, height) = (8, 4);
+        let mut pixels = vec![Vec3::new(0.1, 0.1, 0.1); width * height];
+        for pixel in &mut pixels[..width] {
+            *pixel = Vec3::zero();
+        }
+        pixels[2 * width + 5] = Vec3::new(20.0, 10.0, 5.0);
+
+        let image = ImageTexture {
+            width: width as u32,
+            height: height as u32,
+            pixels,
+        };
+        EnvironmentMap::new(image, 30.0, 2.0)
+    }
+
+    #[test]
+    fn samples_match_the_pdf() {
+        let environment = environment();
+        let mut rng = Pcg32::new(7, 1);
+
+        for _ in 0..1000 {
+            let (direction, radiance, pdf) = environment.sample(&mut rng).unwrap();
+            assert!((direction.lenght() - 1.0).abs() < 1e-4);
+            assert!(direction.y() < 0.7072, "{:?}", direction);
+
+            let expected = environment.pdf(direction);
+            assert!(
+                (pdf - expected).abs() < 1e-3 * expected,
+                "{} != {}",
+                pdf,
+                expected
+            );
+            assert_eq!(radiance, environment.radiance(direction));
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one_over_the_sphere() {
+        let environment = environment();
+        let mut rng = Pcg32::new(3, 5);
+
+        let n = 200_000;
+        let total: f32 = (0..n)
+            .map(|_| environment.pdf(Vec3::random_in_unit_sphere(&mut rng).normalized()))
+            .sum();
+        let integral = total / n as f32 * 4.0 * PI;
+        assert!((integral - 1.0).abs() < 0.02, "{}", integral);
+    }
+}
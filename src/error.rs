@@ -0,0 +1,6 @@
+use std::io;
+
+/// Error for input files that are malformed or use something unsupported.
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
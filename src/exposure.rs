@@ -0,0 +1,95 @@
+use crate::film::Film;
+
+/// Height of a full frame 35mm sensor, in world units taken as meters.
+const SENSOR_HEIGHT: f32 = 0.024;
+
+/// Reflected light meter calibration constant used by most manufacturers.
+const METER_CALIBRATION: f32 = 12.5;
+
+/// Camera settings that determine how much light reaches the sensor.
+///
+/// The shutter time is in seconds. Scene radiance is interpreted as
+/// luminance in cd/m^2, so for example a white surface in direct sunlight
+/// comes out around one with the "sunny 16" settings of f/16, 1/100s and
+/// ISO 100.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Exposure {
+    pub f_number: f32,
+    pub shutter: f32,
+    pub iso: f32,
+}
+
+impl Exposure {
+    /// Exposure value at ISO 100 equivalent to these settings.
+    pub fn ev100(&self) -> f32 {
+        (self.f_number * self.f_number / self.shutter * 100.0 / self.iso).log2()
+    }
+
+    /// Factor from scene luminance to pixel values.
+    pub fn scale(&self) -> f32 {
+        ev100_to_scale(self.ev100())
+    }
+
+    /// Diameter of the entrance pupil, to be used as the camera aperture,
+    /// for a lens covering a vertical field of view of `fov` degrees.
+    pub fn aperture(&self, fov: f32) -> f32 {
+        let focal_length = SENSOR_HEIGHT / (2.0 * (fov.to_radians() / 2.0).tan());
+        focal_length / self.f_number
+    }
+}
+
+/// Factor from scene luminance to pixel values for an exposure value,
+/// using the saturation based sensitivity of ISO 12232 where the maximum
+/// pixel value is reached at 1.2 times the exposure the meter targets.
+pub fn ev100_to_scale(ev100: f32) -> f32 {
+    1.0 / (1.2 * 2f32.powf(ev100))
+}
+
+/// Exposure value a light meter would pick for the rendered frame, based
+/// on its log-average luminance.
+pub fn auto_ev100(film: &Film) -> f32 {
+    (film.log_average_luminance() * 100.0 / METER_CALIBRATION).log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::vec3::Vec3;
+    use std::f32::consts::PI;
+
+    #[test]
+    fn sunny_sixteen() {
+        let exposure = Exposure {
+            f_number: 16.0,
+            shutter: 1.0 / 100.0,
+            iso: 100.0,
+        };
+
+        // A white diffuse surface under about 100000 lux of sunlight
+        let white = 100_000.0 / PI * exposure.scale();
+        assert!(white > 0.8 && white < 1.3);
+
+        // One stop more light doubles the brightness
+        let open = Exposure {
+            f_number: 16.0 / 2f32.sqrt(),
+            ..exposure
+        };
+        assert!((open.scale() / exposure.scale() - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn auto_exposure_is_independent_of_scene_scale() {
+        let mut film = Film::new(4, 4);
+        for (i, pixel) in film.pixels.iter_mut().enumerate() {
+            pixel.add_sample(Vec3::new(1.0, 1.0, 1.0) * (i + 1) as f32);
+        }
+        let ev = auto_ev100(&film);
+
+        for pixel in film.pixels.iter_mut() {
+            pixel.sum *= 8.0;
+        }
+
+        assert!((auto_ev100(&film) - ev - 3.0).abs() < 1e-4);
+    }
+}
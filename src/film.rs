@@ -0,0 +1,464 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::vec3::Vec3;
+
+/// Identifies saved films, followed by the format version.
+const FILM_MAGIC: &[u8; 4] = b"FILM";
+const FILM_VERSION: u32 = 1;
+
+/// Size of a saved pixel: the color sum, sample count, mean and m2.
+const PIXEL_BYTES: usize = 24;
+
+/// Running estimate of a single pixel.
+///
+/// Besides the color sum, the luminance mean and variance are tracked with
+/// Welford's algorithm so the error of the estimate can be queried at any
+/// time without keeping the individual samples around.
+#[derive(Clone, Copy, Debug)]
+pub struct Pixel {
+    pub sum: Vec3,
+    pub samples: u32,
+    mean: f32,
+    m2: f32,
+}
+
+impl Pixel {
+    pub fn new() -> Pixel {
+        Pixel {
+            sum: Vec3::zero(),
+            samples: 0,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    pub fn add_sample(&mut self, col: Vec3) {
+        self.sum += col;
+        self.samples += 1;
+
+        let lum = luminance(col);
+        let delta = lum - self.mean;
+        self.mean += delta / self.samples as f32;
+        self.m2 += delta * (lum - self.mean);
+    }
+
+    pub fn color(&self) -> Vec3 {
+        if self.samples == 0 {
+            Vec3::zero()
+        } else {
+            self.sum / self.samples as f32
+        }
+    }
+
+    pub fn variance(&self) -> f32 {
+        if self.samples < 2 {
+            0.0
+        } else {
+            self.m2 / (self.samples - 1) as f32
+        }
+    }
+
+    /// Standard error of the luminance estimate, taken to display space.
+    ///
+    /// Images are written with a gamma of 2, so an error `e` around a mean
+    /// `m` shows up roughly as `e / (2 * sqrt(m))` in the final image.
+    pub fn error(&self) -> f32 {
+        if self.samples < 2 {
+            return f32::MAX;
+        }
+
+        let std_error = (self.variance() / self.samples as f32).sqrt();
+        std_error / (2.0 * self.mean.max(1e-4).sqrt())
+    }
+
+    /// Combines the samples of two independent estimates of the same pixel,
+    /// using Chan et al.'s parallel form of Welford's algorithm.
+    pub fn merge(&mut self, other: &Pixel) {
+        if other.samples == 0 {
+            return;
+        }
+
+        let n_a = self.samples as f32;
+        let n_b = other.samples as f32;
+        let n = n_a + n_b;
+        let delta = other.mean - self.mean;
+
+        self.sum += other.sum;
+        self.samples += other.samples;
+        self.mean += delta * n_b / n;
+        self.m2 += other.m2 + delta * delta * n_a * n_b / n;
+    }
+}
+
+impl Default for Pixel {
+    fn default() -> Pixel {
+        Pixel::new()
+    }
+}
+
+/// Rectangular region of a frame in pixels, with `y` counted from the top
+/// row like in image editors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CropWindow {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CropWindow {
+    pub fn full(width: u32, height: u32) -> CropWindow {
+        CropWindow {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    /// Part of the window that lies inside a frame of the given size.
+    pub fn clamped(self, width: u32, height: u32) -> CropWindow {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+
+        CropWindow {
+            x,
+            y,
+            width: self.width.min(width - x),
+            height: self.height.min(height - y),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+/// Accumulation buffer for a rendered frame of `width` by `height` pixels.
+///
+/// Only the pixels inside `window` are stored, top row first, so separate
+/// regions of a frame can be rendered independently and merged later.
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    pub window: CropWindow,
+    pub pixels: Vec<Pixel>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Film {
+        Film::cropped(width, height, CropWindow::full(width, height))
+    }
+
+    pub fn cropped(width: u32, height: u32, window: CropWindow) -> Film {
+        let window = window.clamped(width, height);
+
+        Film {
+            width,
+            height,
+            window,
+            pixels: vec![Pixel::new(); window.width as usize * window.height as usize],
+        }
+    }
+
+    fn index(&self, x: u32, row: u32) -> usize {
+        ((row - self.window.y) * self.window.width + x - self.window.x) as usize
+    }
+
+    /// Returns the pixel at `(x, y)` of the frame, with `y` growing upwards
+    /// as in the camera's `v` coordinate. The pixel must be inside the
+    /// window.
+    pub fn pixel_mut(&mut self, x: u32, y: u32) -> &mut Pixel {
+        let index = self.index(x, self.height - 1 - y);
+        &mut self.pixels[index]
+    }
+
+    /// Adds the samples of another film of the same frame, such as a
+    /// different region or another pass over the same one. Pixels outside
+    /// of this film's window are ignored.
+    pub fn merge(&mut self, other: &Film) -> io::Result<()> {
+        if (other.width, other.height) != (self.width, self.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "cannot merge a {}x{} film into a {}x{} one",
+                    other.width, other.height, self.width, self.height
+                ),
+            ));
+        }
+
+        let window = other.window;
+        for row in window.y..window.y + window.height {
+            for x in window.x..window.x + window.width {
+                if self.window.contains(x, row) {
+                    let index = self.index(x, row);
+                    self.pixels[index].merge(&other.pixels[other.index(x, row)]);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the film with all the per-pixel statistics, so that it can
+    /// be merged or rendered further without losing information.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write(&mut w)?;
+        w.flush()
+    }
+
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(FILM_MAGIC)?;
+        for &n in &[
+            FILM_VERSION,
+            self.width,
+            self.height,
+            self.window.x,
+            self.window.y,
+            self.window.width,
+            self.window.height,
+        ] {
+            w.write_all(&n.to_le_bytes())?;
+        }
+
+        for pixel in &self.pixels {
+            for &f in &[pixel.sum.r(), pixel.sum.g(), pixel.sum.b()] {
+                w.write_all(&f.to_le_bytes())?;
+            }
+            w.write_all(&pixel.samples.to_le_bytes())?;
+            w.write_all(&pixel.mean.to_le_bytes())?;
+            w.write_all(&pixel.m2.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> io::Result<Film> {
+        Film::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Reads a film written by `write`, which must be the last thing in
+    /// the stream.
+    pub fn read<R: Read>(r: &mut R) -> io::Result<Film> {
+        let magic = read_u32(r)?.to_le_bytes();
+        let version = read_u32(r)?;
+        if &magic != FILM_MAGIC || version != FILM_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a film file or unsupported version",
+            ));
+        }
+
+        let width = read_u32(r)?;
+        let height = read_u32(r)?;
+        let window = CropWindow {
+            x: read_u32(r)?,
+            y: read_u32(r)?,
+            width: read_u32(r)?,
+            height: read_u32(r)?,
+        };
+        if window.clamped(width, height) != window {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "film window outside of the frame",
+            ));
+        }
+
+        // The pixels run to the end of the file, so the header can be
+        // checked against them before anything is allocated
+        let mut payload = Vec::new();
+        r.read_to_end(&mut payload)?;
+        let size = (window.width as usize)
+            .checked_mul(window.height as usize)
+            .and_then(|n| n.checked_mul(PIXEL_BYTES));
+        if size != Some(payload.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "film window does not match the pixel data",
+            ));
+        }
+
+        let r = &mut &payload[..];
+        let mut film = Film::cropped(width, height, window);
+        for pixel in film.pixels.iter_mut() {
+            pixel.sum = Vec3::new(read_f32(r)?, read_f32(r)?, read_f32(r)?);
+            pixel.samples = read_u32(r)?;
+            pixel.mean = read_f32(r)?;
+            pixel.m2 = read_f32(r)?;
+        }
+
+        Ok(film)
+    }
+
+    /// Geometric mean of the pixel luminances, which unlike the arithmetic
+    /// mean is not dominated by a few very bright pixels.
+    pub fn log_average_luminance(&self) -> f32 {
+        if self.pixels.is_empty() {
+            return 0.0;
+        }
+
+        let sum: f32 = self
+            .pixels
+            .iter()
+            .map(|p| (luminance(p.color()) + 1e-4).ln())
+            .sum();
+
+        (sum / self.pixels.len() as f32).exp()
+    }
+
+    /// Converts the pixels in the window to 8 bit RGBA after multiplying
+    /// them by `exposure`.
+    pub fn to_rgba8(&self, exposure: f32) -> Vec<u8> {
+        let mut data = Vec::<u8>::with_capacity(4 * self.pixels.len());
+
+        for pixel in &self.pixels {
+            let col = exposure * pixel.color();
+            data.push((255.0 * col.r().sqrt().min(1.0)) as u8);
+            data.push((255.0 * col.g().sqrt().min(1.0)) as u8);
+            data.push((255.0 * col.b().sqrt().min(1.0)) as u8);
+            data.push(255);
+        }
+
+        data
+    }
+
+    /// Visualizes the number of samples taken per pixel, from blue for the
+    /// fewest samples in the frame to red for the most.
+    pub fn sample_heatmap(&self) -> Vec<u8> {
+        let min = self.pixels.iter().map(|p| p.samples).min().unwrap_or(0);
+        let max = self.pixels.iter().map(|p| p.samples).max().unwrap_or(0);
+        let range = (max - min).max(1) as f32;
+
+        let mut data = Vec::<u8>::with_capacity(4 * self.pixels.len());
+
+        for pixel in &self.pixels {
+            let t = (pixel.samples - min) as f32 / range;
+            let col = (1.0 - t) * Vec3::new(0.0, 0.0, 1.0) + t * Vec3::new(1.0, 0.0, 0.0);
+            data.push((255.0 * col.r()) as u8);
+            data.push((255.0 * col.g()) as u8);
+            data.push((255.0 * col.b()) as u8);
+            data.push(255);
+        }
+
+        data
+    }
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_f32<R: Read>(r: &mut R) -> io::Result<f32> {
+    read_u32(r).map(f32::from_bits)
+}
+
+pub fn luminance(col: Vec3) -> f32 {
+    0.2126 * col.r() + 0.7152 * col.g() + 0.0722 * col.b()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_variance_matches_the_two_pass_formula() {
+        let samples = [0.5f32, 2.0, 1.0, 0.0, 3.0, 0.25, 1.5];
+        let n = samples.len() as f32;
+        let mean = samples.iter().sum::<f32>() / n;
+        let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / (n - 1.0);
+
+        let mut pixel = Pixel::new();
+        assert_eq!(pixel.error(), f32::MAX);
+        pixel.add_sample(Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(pixel.variance(), 0.0);
+        assert_eq!(pixel.error(), f32::MAX);
+
+        let mut pixel = Pixel::new();
+        for &s in &samples {
+            pixel.add_sample(Vec3::new(s, s, s));
+        }
+        assert!((pixel.variance() - variance).abs() < 1e-5);
+
+        let error = (variance / n).sqrt() / (2.0 * mean.sqrt());
+        assert!((pixel.error() - error).abs() < 1e-5);
+    }
+
+    #[test]
+    fn merged_pixels_match_a_single_estimate() {
+        let samples = [0.5, 2.0, 1.0, 0.0, 3.0, 0.25, 1.5];
+        let gray = |l: f32| Vec3::new(l, l, l);
+
+        let mut all = Pixel::new();
+        let mut a = Pixel::new();
+        let mut b = Pixel::new();
+        for (i, &s) in samples.iter().enumerate() {
+            all.add_sample(gray(s));
+            if i < 3 {
+                a.add_sample(gray(s));
+            } else {
+                b.add_sample(gray(s));
+            }
+        }
+        a.merge(&b);
+
+        assert_eq!(a.samples, all.samples);
+        assert!((a.color() - all.color()).lenght() < 1e-5);
+        assert!((a.variance() - all.variance()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn cropped_films_merge_into_the_frame() {
+        let window = CropWindow {
+            x: 1,
+            y: 2,
+            width: 2,
+            height: 1,
+        };
+        let mut crop = Film::cropped(4, 4, window);
+        // Row 2 from the top is row 1 from the bottom
+        crop.pixel_mut(2, 1).add_sample(Vec3::new(1.0, 2.0, 3.0));
+
+        let mut saved = Vec::new();
+        crop.write(&mut saved).unwrap();
+        let loaded = Film::read(&mut &saved[..]).unwrap();
+        assert_eq!(loaded.window, window);
+
+        let mut frame = Film::new(4, 4);
+        frame.merge(&loaded).unwrap();
+
+        let samples: Vec<u32> = frame.pixels.iter().map(|p| p.samples).collect();
+        assert_eq!(samples.iter().sum::<u32>(), 1);
+        assert_eq!(samples[2 * 4 + 2], 1);
+        assert_eq!(frame.pixels[2 * 4 + 2].color(), Vec3::new(1.0, 2.0, 3.0));
+
+        assert!(frame.merge(&Film::new(3, 4)).is_err());
+    }
+
+    #[test]
+    fn rejects_headers_that_do_not_match_the_pixels() {
+        let mut saved = Vec::new();
+        Film::new(2, 2).write(&mut saved).unwrap();
+        assert!(Film::read(&mut &saved[..]).is_ok());
+        assert!(Film::read(&mut &saved[..saved.len() - 1]).is_err());
+
+        // A huge frame and window with only four pixels of data
+        let mut forged = saved.clone();
+        for offset in &[8, 12, 24, 28] {
+            forged[*offset..*offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        }
+        assert!(Film::read(&mut &forged[..]).is_err());
+
+        let mut trailing = saved;
+        trailing.push(0);
+        assert!(Film::read(&mut &trailing[..]).is_err());
+    }
+}
@@ -1,38 +1,87 @@
+use std::sync::Arc;
+
+use crate::bvh::Aabb;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::stats;
 use crate::vec3::Vec3;
 
 #[derive(Clone, Copy)]
-pub struct HitInfo {
+pub struct HitInfo<'a> {
     pub t: f32,
     pub p: Vec3,
     pub normal: Vec3,
-    pub material: Material,
+    pub u: f32,
+    pub v: f32,
+    /// Color interpolated from the vertices of meshes that have them.
+    pub color: Option<Vec3>,
+    pub material: &'a dyn Material,
+}
+
+/// Stretch of a ray inside a solid, from the surface where the ray enters
+/// it to where it leaves. Both normals point out of the solid.
+#[derive(Clone, Copy)]
+pub struct Interval<'a> {
+    pub enter: HitInfo<'a>,
+    pub exit: HitInfo<'a>,
 }
 
 pub trait Hitable {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo>;
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo<'_>>;
+
+    /// Box enclosing the whole object, infinite for unbounded ones.
+    fn bounds(&self) -> Aabb;
+
+    /// Every stretch of the whole line through `ray` that lies inside the
+    /// object, in order and including those behind the origin.
+    ///
+    /// Spheres, tori, capped full cylinders and cones, closed meshes and
+    /// `Csg` nodes are solid, as are quadrics where they close off a region
+    /// inside their clip box. Planes, disks and other surfaces that do not
+    /// enclose anything have none, so they vanish from CSG.
+    fn intervals(&self, _ray: &Ray) -> Vec<Interval<'_>> {
+        Vec::new()
+    }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f32,
-    pub material: Material,
+    pub material: Arc<dyn Material>,
 }
 
 impl Sphere {
-    pub fn new(center: Vec3, radius: f32, material: Material) -> Sphere {
+    pub fn new(center: Vec3, radius: f32, material: Arc<dyn Material>) -> Sphere {
         Sphere {
             center,
             radius,
             material,
         }
     }
+
+    fn hit_info(&self, ray: &Ray, t: f32) -> HitInfo<'_> {
+        let p = ray.point_at_parameter(t);
+        let normal = (p - self.center) / self.radius;
+
+        // Spherical coordinates, with v going from the bottom to the top pole
+        let phi = (-normal.z()).atan2(normal.x()) + std::f32::consts::PI;
+        let theta = (-normal.y()).clamp(-1.0, 1.0).acos();
+
+        HitInfo {
+            t,
+            p,
+            normal,
+            u: phi / (2.0 * std::f32::consts::PI),
+            v: theta / std::f32::consts::PI,
+            color: None,
+            material: &*self.material,
+        }
+    }
 }
 
 impl Hitable for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo> {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo<'_>> {
         let oc = ray.origin - self.center;
         let a = ray.direction.lenght_squared();
         let b = oc.dot(ray.direction);
@@ -42,48 +91,58 @@ impl Hitable for Sphere {
         if discriminant > 0.0 {
             let t = (-b - discriminant.sqrt()) / a;
             if t > t_min && t < t_max {
-                let p = ray.point_at_parameter(t);
-                let normal = (p - self.center) / self.radius;
-                let material = self.material;
-
-                return Some(HitInfo {
-                    t,
-                    p,
-                    normal,
-                    material,
-                });
+                return Some(self.hit_info(ray, t));
             }
             let t = (-b + discriminant.sqrt()) / a;
             if t > t_min && t < t_max {
-                let p = ray.point_at_parameter(t);
-                let normal = (p - self.center) / self.radius;
-                let material = self.material;
-
-                return Some(HitInfo {
-                    t,
-                    p,
-                    normal,
-                    material,
-                });
+                return Some(self.hit_info(ray, t));
             }
         }
 
         None
     }
+
+    fn bounds(&self) -> Aabb {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Aabb::from_points(&[self.center - r, self.center + r])
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        let oc = ray.origin - self.center;
+        let a = ray.direction.lenght_squared();
+        let b = oc.dot(ray.direction);
+        let c = oc.lenght_squared() - self.radius * self.radius;
+        let discriminant = b * b - a * c;
+
+        if discriminant > 0.0 {
+            vec![Interval {
+                enter: self.hit_info(ray, (-b - discriminant.sqrt()) / a),
+                exit: self.hit_info(ray, (-b + discriminant.sqrt()) / a),
+            }]
+        } else {
+            Vec::new()
+        }
+    }
 }
 
-impl Hitable for [Box<Hitable>] {
-    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo> {
+impl Hitable for [Box<dyn Hitable>] {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo<'_>> {
         let mut closest_so_far = t_max;
         let mut hit: Option<HitInfo> = None;
+        stats::PRIMITIVE_TESTS.add(self.len() as u64);
 
         for hitable in self.iter() {
             if let Some(h) = hitable.hit(ray, t_min, closest_so_far) {
-                hit = Some(h);
                 closest_so_far = h.t;
+                hit = Some(h);
             }
         }
 
         hit
     }
+    fn bounds(&self) -> Aabb {
+        self.iter().fold(Aabb::empty(), |bounds, hitable| {
+            bounds.union(hitable.bounds())
+        })
+    }
 }
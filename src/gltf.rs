@@ -0,0 +1,902 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::camera::{Camera, OrthographicCamera, PerspectiveCamera};
+use crate::error::invalid_data;
+use crate::geometry::Hitable;
+use crate::json::Json;
+use crate::light::{DirectionalLight, Light, PointLight, SpotLight};
+use crate::material::Material;
+use crate::mesh::{Mesh, MeshData};
+use crate::principled::Principled;
+use crate::texture::{Channel, ImageTexture, Product, Texture, VertexColor};
+use crate::transform::Mat4;
+use crate::vec3::Vec3;
+
+/// Deepest node hierarchy that is followed. Valid files form a tree, so
+/// going past this means the nodes list each other as children.
+const MAX_DEPTH: usize = 256;
+
+/// Contents of a glTF 2.0 scene, flattened into world space.
+///
+/// Every mesh primitive becomes a separate `Mesh` with a `Principled`
+/// material. Punctual lights keep their photometric units, candela for
+/// point and spot lights and lux for directional ones, so they are meant
+/// to be rendered with a physical exposure.
+pub struct GltfScene {
+    pub objects: Vec<Box<dyn Hitable>>,
+    pub lights: Vec<Box<dyn Light>>,
+    /// The first camera found while walking the node hierarchy.
+    pub camera: Option<Box<dyn Camera>>,
+}
+
+impl GltfScene {
+    /// Loads a `.gltf` file with its external or embedded buffers and
+    /// images, or a binary `.glb` file. Cameras are fitted to `aspect`
+    /// rather than their own aspect ratio so they fill the whole frame.
+    ///
+    /// Only PNG and Radiance HDR images can be decoded.
+    pub fn load(path: &Path, aspect: f32) -> io::Result<GltfScene> {
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        GltfScene::decode(&fs::read(path)?, dir, aspect)
+    }
+
+    /// Decodes glTF or GLB data, resolving relative URIs against `dir`.
+    pub fn decode(bytes: &[u8], dir: &Path, aspect: f32) -> io::Result<GltfScene> {
+        let (json, binary) = if bytes.starts_with(b"glTF") {
+            split_glb(bytes)?
+        } else {
+            (bytes, None)
+        };
+
+        let json =
+            std::str::from_utf8(json).map_err(|_| invalid_data("JSON is not valid UTF-8"))?;
+        let json = Json::parse(json)?;
+        if !json
+            .get("asset")
+            .get("version")
+            .as_str()
+            .is_some_and(|v| v.starts_with("2."))
+        {
+            return Err(invalid_data("only glTF 2.0 is supported"));
+        }
+
+        let mut loader = Loader::new(&json, dir, binary)?;
+        let mut scene = GltfScene {
+            objects: Vec::new(),
+            lights: Vec::new(),
+            camera: None,
+        };
+
+        let scenes = json.get("scenes");
+        let roots: Vec<usize> = if scenes.as_array().is_empty() {
+            // Without scenes, every node that is not a child is a root
+            let nodes = json.get("nodes").as_array();
+            let children: Vec<usize> = nodes
+                .iter()
+                .flat_map(|n| n.get("children").as_array())
+                .filter_map(Json::as_usize)
+                .collect();
+            (0..nodes.len()).filter(|i| !children.contains(i)).collect()
+        } else {
+            let index = json.get("scene").as_usize().unwrap_or(0);
+            scenes
+                .at(index)
+                .get("nodes")
+                .as_array()
+                .iter()
+                .filter_map(Json::as_usize)
+                .collect()
+        };
+
+        for root in roots {
+            loader.node(&mut scene, root, Mat4::identity(), aspect, 0)?;
+        }
+
+        Ok(scene)
+    }
+}
+
+/// Splits a binary glTF file into its JSON chunk and optional buffer chunk.
+fn split_glb(bytes: &[u8]) -> io::Result<(&[u8], Option<&[u8]>)> {
+    let word = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or_else(|| invalid_data("truncated GLB file"))
+    };
+
+    if word(4)? != 2 {
+        return Err(invalid_data("only GLB version 2 is supported"));
+    }
+    let length = word(8)?.min(bytes.len());
+
+    let mut json = None;
+    let mut binary = None;
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let chunk_length = word(offset)?;
+        let chunk_type = word(offset + 4)?;
+        let data = bytes
+            .get(offset + 8..offset + 8 + chunk_length)
+            .ok_or_else(|| invalid_data("truncated GLB chunk"))?;
+
+        match chunk_type {
+            0x4e4f_534a => json = json.or(Some(data)),
+            0x004e_4942 => binary = binary.or(Some(data)),
+            _ => {}
+        }
+        offset += 8 + chunk_length;
+    }
+
+    Ok((
+        json.ok_or_else(|| invalid_data("GLB file has no JSON chunk"))?,
+        binary,
+    ))
+}
+
+struct Loader<'a> {
+    json: &'a Json,
+    dir: &'a Path,
+    buffers: Vec<Vec<u8>>,
+    materials: Vec<Arc<dyn Material>>,
+    images: HashMap<(usize, bool), Arc<ImageTexture>>,
+}
+
+impl<'a> Loader<'a> {
+    fn new(json: &'a Json, dir: &'a Path, binary: Option<&[u8]>) -> io::Result<Loader<'a>> {
+        let mut buffers = Vec::new();
+        for (i, buffer) in json.get("buffers").as_array().iter().enumerate() {
+            let data = match buffer.get("uri").as_str() {
+                Some(uri) => load_uri(dir, uri)?,
+                None => match binary {
+                    Some(binary) if i == 0 => binary.to_vec(),
+                    _ => return Err(invalid_data("buffer has no data")),
+                },
+            };
+            if buffer.get("byteLength").as_usize().unwrap_or(0) > data.len() {
+                return Err(invalid_data("buffer is shorter than its byteLength"));
+            }
+            buffers.push(data);
+        }
+
+        let mut loader = Loader {
+            json,
+            dir,
+            buffers,
+            materials: Vec::new(),
+            images: HashMap::new(),
+        };
+        for material in json.get("materials").as_array() {
+            let material = loader.material(material)?;
+            loader.materials.push(Arc::new(material));
+        }
+
+        Ok(loader)
+    }
+
+    fn node(
+        &mut self,
+        scene: &mut GltfScene,
+        index: usize,
+        parent: Mat4,
+        aspect: f32,
+        depth: usize,
+    ) -> io::Result<()> {
+        let node = self.json.get("nodes").at(index);
+        if node.is_null() {
+            return Err(invalid_data("reference to a missing node"));
+        }
+        if depth > MAX_DEPTH {
+            return Err(invalid_data("node hierarchy is too deep or cyclic"));
+        }
+
+        let local = match floats(node.get("matrix")) {
+            Some(m) if m.len() == 16 => Mat4::from_columns(&to_array(&m)),
+            _ => {
+                let translation = vec3(node.get("translation"), Vec3::zero());
+                let rotation = floats(node.get("rotation"))
+                    .filter(|q| q.len() == 4)
+                    .map_or([0.0, 0.0, 0.0, 1.0], |q| to_array(&q));
+                let scale = vec3(node.get("scale"), Vec3::new(1.0, 1.0, 1.0));
+                Mat4::translate(translation)
+                    * Mat4::rotate_quaternion(rotation)
+                    * Mat4::scale(scale)
+            }
+        };
+        let transform = parent * local;
+
+        if let Some(mesh) = node.get("mesh").as_usize() {
+            let primitives = self.json.get("meshes").at(mesh).get("primitives");
+            for primitive in primitives.as_array() {
+                if let Some(mesh) = self.primitive(primitive, &transform)? {
+                    scene.objects.push(Box::new(mesh));
+                }
+            }
+        }
+
+        if let Some(camera) = node.get("camera").as_usize() {
+            if scene.camera.is_none() {
+                scene.camera = Some(self.camera(camera, &transform, aspect)?);
+            }
+        }
+
+        let light = node
+            .get("extensions")
+            .get("KHR_lights_punctual")
+            .get("light");
+        if let Some(light) = light.as_usize() {
+            scene.lights.push(self.light(light, &transform)?);
+        }
+
+        for child in node.get("children").as_array() {
+            let child = child
+                .as_usize()
+                .ok_or_else(|| invalid_data("invalid child"))?;
+            self.node(scene, child, transform, aspect, depth + 1)?;
+        }
+
+        Ok(())
+    }
+
+    /// Converts a primitive to world space, skipping points and lines.
+    fn primitive(&mut self, primitive: &Json, transform: &Mat4) -> io::Result<Option<Mesh>> {
+        let mode = primitive.get("mode").as_usize().unwrap_or(4);
+        if mode < 4 {
+            return Ok(None);
+        }
+
+        let attributes = primitive.get("attributes");
+        let position = attributes
+            .get("POSITION")
+            .as_usize()
+            .ok_or_else(|| invalid_data("primitive has no positions"))?;
+
+        let mut data = MeshData {
+            positions: self
+                .vectors(position, 3)?
+                .iter()
+                .map(|p| transform.transform_point(Vec3::new(p[0], p[1], p[2])))
+                .collect(),
+            ..MeshData::default()
+        };
+
+        if let Some(normal) = attributes.get("NORMAL").as_usize() {
+            data.normals = self
+                .vectors(normal, 3)?
+                .iter()
+                .map(|n| transform.transform_normal(Vec3::new(n[0], n[1], n[2])))
+                .collect();
+        }
+        if let Some(uv) = attributes.get("TEXCOORD_0").as_usize() {
+            // glTF puts the origin of textures at the top left
+            data.uvs = self
+                .vectors(uv, 2)?
+                .iter()
+                .map(|t| (t[0], 1.0 - t[1]))
+                .collect();
+        }
+        if let Some(color) = attributes.get("COLOR_0").as_usize() {
+            data.colors = self
+                .vectors(color, 3)?
+                .iter()
+                .map(|c| Vec3::new(c[0], c[1], c[2]))
+                .collect();
+        }
+
+        let indices: Vec<u32> = match primitive.get("indices").as_usize() {
+            Some(indices) => self
+                .accessor(indices)?
+                .0
+                .iter()
+                .map(|&i| i as u32)
+                .collect(),
+            None => (0..data.positions.len() as u32).collect(),
+        };
+        let mut triangles: Vec<[u32; 3]> = match mode {
+            4 => indices
+                .chunks_exact(3)
+                .map(|t| [t[0], t[1], t[2]])
+                .collect(),
+            5 => indices
+                .windows(3)
+                .enumerate()
+                .map(|(i, t)| {
+                    if i % 2 == 0 {
+                        [t[0], t[1], t[2]]
+                    } else {
+                        [t[1], t[0], t[2]]
+                    }
+                })
+                .collect(),
+            6 if !indices.is_empty() => indices[1..]
+                .windows(2)
+                .map(|t| [indices[0], t[0], t[1]])
+                .collect(),
+            _ => Vec::new(),
+        };
+        if transform.determinant() < 0.0 {
+            // Mirroring turns counterclockwise triangles clockwise
+            for t in &mut triangles {
+                t.swap(1, 2);
+            }
+        }
+        data.triangles = triangles;
+
+        let material = match primitive.get("material").as_usize() {
+            Some(i) => self
+                .materials
+                .get(i)
+                .cloned()
+                .ok_or_else(|| invalid_data("reference to a missing material"))?,
+            None => Arc::new(Principled::default()),
+        };
+
+        Mesh::new(data, material).map(Some)
+    }
+
+    fn material(&mut self, material: &Json) -> io::Result<Principled> {
+        let pbr = material.get("pbrMetallicRoughness");
+        let extensions = material.get("extensions");
+        let mut result = Principled::default();
+
+        let base_color = self.texture(
+            vec3(pbr.get("baseColorFactor"), Vec3::new(1.0, 1.0, 1.0)),
+            pbr.get("baseColorTexture"),
+            true,
+            None,
+        )?;
+        result.base_color = Arc::new(Product {
+            a: base_color,
+            b: Arc::new(VertexColor {
+                fallback: Arc::new(1.0),
+            }),
+        });
+
+        // Metalness is in the blue channel and roughness in the green one
+        let metallic_roughness = pbr.get("metallicRoughnessTexture");
+        result.metallic = self.texture(
+            scalar(pbr.get("metallicFactor"), 1.0),
+            metallic_roughness,
+            false,
+            Some(2),
+        )?;
+        result.roughness = self.texture(
+            scalar(pbr.get("roughnessFactor"), 1.0),
+            metallic_roughness,
+            false,
+            Some(1),
+        )?;
+
+        result.emission = self.texture(
+            vec3(material.get("emissiveFactor"), Vec3::zero()),
+            material.get("emissiveTexture"),
+            true,
+            None,
+        )?;
+        if let Some(strength) = extensions
+            .get("KHR_materials_emissive_strength")
+            .get("emissiveStrength")
+            .as_f32()
+        {
+            result.emission_strength = strength;
+        }
+
+        if let Some(ior) = extensions.get("KHR_materials_ior").get("ior").as_f32() {
+            result.ior = ior;
+        }
+
+        let transmission = extensions.get("KHR_materials_transmission");
+        result.transmission = self.texture(
+            scalar(transmission.get("transmissionFactor"), 0.0),
+            transmission.get("transmissionTexture"),
+            false,
+            Some(0),
+        )?;
+
+        Ok(result)
+    }
+
+    /// Constant `factor`, multiplied by the texture described by `info` if
+    /// there is one. Scalar parameters read `channel` of the image.
+    fn texture(
+        &mut self,
+        factor: Vec3,
+        info: &Json,
+        srgb: bool,
+        channel: Option<usize>,
+    ) -> io::Result<Arc<dyn Texture>> {
+        let factor: Arc<dyn Texture> = Arc::new(factor);
+        let index = match info.get("index").as_usize() {
+            Some(index) => index,
+            None => return Ok(factor),
+        };
+
+        let source = self
+            .json
+            .get("textures")
+            .at(index)
+            .get("source")
+            .as_usize()
+            .ok_or_else(|| invalid_data("texture has no supported image source"))?;
+        let image: Arc<dyn Texture> = self.image(source, srgb)?;
+        let image = match channel {
+            Some(channel) => Arc::new(Channel {
+                texture: image,
+                channel,
+            }),
+            None => image,
+        };
+
+        Ok(Arc::new(Product {
+            a: factor,
+            b: image,
+        }))
+    }
+
+    fn image(&mut self, index: usize, srgb: bool) -> io::Result<Arc<ImageTexture>> {
+        if let Some(image) = self.images.get(&(index, srgb)) {
+            return Ok(image.clone());
+        }
+
+        let image = self.json.get("images").at(index);
+        let texture = if let Some(view) = image.get("bufferView").as_usize() {
+            if image.get("mimeType").as_str() != Some("image/png") {
+                return Err(invalid_data("only PNG images are supported"));
+            }
+            ImageTexture::from_png(self.buffer_view(view)?, srgb)?
+        } else {
+            let uri = image
+                .get("uri")
+                .as_str()
+                .ok_or_else(|| invalid_data("image has no data"))?;
+            if uri.starts_with("data:") {
+                if !uri.starts_with("data:image/png") {
+                    return Err(invalid_data("only PNG images are supported"));
+                }
+                ImageTexture::from_png(load_uri(self.dir, uri)?.as_slice(), srgb)?
+            } else {
+                let path = self.dir.join(decode_percent(uri));
+                let supported = path.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+                    e.eq_ignore_ascii_case("png") || e.eq_ignore_ascii_case("hdr")
+                });
+                if !supported {
+                    return Err(invalid_data(&format!("unsupported image {}", uri)));
+                }
+                ImageTexture::load(&path, srgb)?
+            }
+        };
+
+        let texture = Arc::new(texture);
+        self.images.insert((index, srgb), texture.clone());
+        Ok(texture)
+    }
+
+    fn camera(&self, index: usize, transform: &Mat4, aspect: f32) -> io::Result<Box<dyn Camera>> {
+        let camera = self.json.get("cameras").at(index);
+
+        // Cameras look down their local -z axis with +y up
+        let origin = transform.transform_point(Vec3::zero());
+        let look_at = origin + transform.transform_vector(Vec3::new(0.0, 0.0, -1.0));
+        let up = transform.transform_vector(Vec3::up());
+
+        match camera.get("type").as_str() {
+            Some("perspective") => {
+                let yfov = camera
+                    .get("perspective")
+                    .get("yfov")
+                    .as_f32()
+                    .ok_or_else(|| invalid_data("perspective camera has no field of view"))?;
+                Ok(Box::new(PerspectiveCamera::new(
+                    origin,
+                    look_at,
+                    up,
+                    yfov.to_degrees(),
+                    aspect,
+                    0.0,
+                    1.0,
+                )))
+            }
+            Some("orthographic") => {
+                let ymag = camera
+                    .get("orthographic")
+                    .get("ymag")
+                    .as_f32()
+                    .ok_or_else(|| invalid_data("orthographic camera has no extent"))?;
+                Ok(Box::new(OrthographicCamera::new(
+                    origin,
+                    look_at,
+                    up,
+                    2.0 * ymag,
+                    aspect,
+                )))
+            }
+            _ => Err(invalid_data("unknown camera type")),
+        }
+    }
+
+    fn light(&self, index: usize, transform: &Mat4) -> io::Result<Box<dyn Light>> {
+        let light = self
+            .json
+            .get("extensions")
+            .get("KHR_lights_punctual")
+            .get("lights")
+            .at(index);
+
+        let color = vec3(light.get("color"), Vec3::new(1.0, 1.0, 1.0));
+        let intensity = color * scalar(light.get("intensity"), 1.0).x();
+
+        // Lights point down their local -z axis
+        let position = transform.transform_point(Vec3::zero());
+        let direction = transform
+            .transform_vector(Vec3::new(0.0, 0.0, -1.0))
+            .normalized();
+
+        match light.get("type").as_str() {
+            Some("point") => Ok(Box::new(PointLight {
+                position,
+                intensity,
+            })),
+            Some("spot") => {
+                let spot = light.get("spot");
+                let inner = spot.get("innerConeAngle").as_f32().unwrap_or(0.0);
+                let outer = spot
+                    .get("outerConeAngle")
+                    .as_f32()
+                    .unwrap_or(std::f32::consts::FRAC_PI_4);
+                Ok(Box::new(SpotLight {
+                    position,
+                    direction,
+                    intensity,
+                    cone_angle: outer.to_degrees(),
+                    falloff_start: inner.to_degrees(),
+                }))
+            }
+            Some("directional") => Ok(Box::new(DirectionalLight {
+                direction,
+                irradiance: intensity,
+            })),
+            _ => Err(invalid_data("unknown light type")),
+        }
+    }
+
+    fn buffer_view(&self, index: usize) -> io::Result<&[u8]> {
+        let view = self.json.get("bufferViews").at(index);
+        let buffer = view
+            .get("buffer")
+            .as_usize()
+            .and_then(|b| self.buffers.get(b))
+            .ok_or_else(|| invalid_data("buffer view refers to a missing buffer"))?;
+        let offset = view.get("byteOffset").as_usize().unwrap_or(0);
+        let length = view.get("byteLength").as_usize().unwrap_or(0);
+
+        offset
+            .checked_add(length)
+            .and_then(|end| buffer.get(offset..end))
+            .ok_or_else(|| invalid_data("buffer view is out of bounds"))
+    }
+
+    /// Reads an accessor as a flat list of numbers, returning them with the
+    /// number of components per element. Normalized integers are mapped to
+    /// [0, 1] or [-1, 1].
+    fn accessor(&self, index: usize) -> io::Result<(Vec<f64>, usize)> {
+        let accessor = self.json.get("accessors").at(index);
+        if !accessor.get("sparse").is_null() {
+            return Err(invalid_data("sparse accessors are not supported"));
+        }
+
+        let count = accessor
+            .get("count")
+            .as_usize()
+            .ok_or_else(|| invalid_data("accessor has no count"))?;
+        let components = match accessor.get("type").as_str() {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") | Some("MAT2") => 4,
+            Some("MAT3") => 9,
+            Some("MAT4") => 16,
+            _ => return Err(invalid_data("unknown accessor type")),
+        };
+        let component_type = accessor.get("componentType").as_usize();
+        let (size, max) = match component_type {
+            Some(5120) => (1, 127.0),
+            Some(5121) => (1, 255.0),
+            Some(5122) => (2, 32767.0),
+            Some(5123) => (2, 65535.0),
+            Some(5125) => (4, 0.0),
+            Some(5126) => (4, 0.0),
+            _ => return Err(invalid_data("unknown accessor component type")),
+        };
+        let normalized = accessor.get("normalized") == &Json::Bool(true);
+
+        // The count is untrusted, so it is checked against the data before
+        // anything is allocated for it
+        let out_of_bounds = || invalid_data("accessor is out of bounds");
+        let element = size * components;
+
+        let view_index = match accessor.get("bufferView").as_usize() {
+            Some(view) => view,
+            // Accessors without data are all zeros, and still cannot have
+            // more elements than the file has bytes
+            None => {
+                let bytes: usize = self.buffers.iter().map(Vec::len).sum();
+                if count > bytes {
+                    return Err(out_of_bounds());
+                }
+                return Ok((vec![0.0; count * components], components));
+            }
+        };
+        let view = self.buffer_view(view_index)?;
+        let stride = self
+            .json
+            .get("bufferViews")
+            .at(view_index)
+            .get("byteStride")
+            .as_usize()
+            .unwrap_or(element);
+        if stride < element {
+            return Err(invalid_data(
+                "buffer view stride is smaller than an element",
+            ));
+        }
+        let offset = accessor.get("byteOffset").as_usize().unwrap_or(0);
+
+        if count > 0 {
+            let end = (count - 1)
+                .checked_mul(stride)
+                .and_then(|n| n.checked_add(offset))
+                .and_then(|n| n.checked_add(element))
+                .ok_or_else(out_of_bounds)?;
+            if end > view.len() {
+                return Err(out_of_bounds());
+            }
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for i in 0..count {
+            for c in 0..components {
+                let b = &view[offset + i * stride + c * size..];
+                let value = match component_type {
+                    Some(5120) => f64::from(b[0] as i8),
+                    Some(5121) => f64::from(b[0]),
+                    Some(5122) => f64::from(i16::from_le_bytes([b[0], b[1]])),
+                    Some(5123) => f64::from(u16::from_le_bytes([b[0], b[1]])),
+                    Some(5125) => f64::from(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                    _ => f64::from(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                };
+                values.push(if normalized && max > 0.0 {
+                    (value / max).max(-1.0)
+                } else {
+                    value
+                });
+            }
+        }
+
+        Ok((values, components))
+    }
+
+    /// Reads the first `n` components of each element of an accessor.
+    fn vectors(&self, index: usize, n: usize) -> io::Result<Vec<Vec<f32>>> {
+        let (values, components) = self.accessor(index)?;
+        if components < n {
+            return Err(invalid_data("accessor has too few components"));
+        }
+
+        Ok(values
+            .chunks_exact(components)
+            .map(|v| v[..n].iter().map(|&x| x as f32).collect())
+            .collect())
+    }
+}
+
+/// Reads a `data:` URI or a file relative to the glTF file.
+fn load_uri(dir: &Path, uri: &str) -> io::Result<Vec<u8>> {
+    if uri.starts_with("data:") {
+        let (header, data) = uri
+            .split_once(',')
+            .ok_or_else(|| invalid_data("malformed data URI"))?;
+        if !header.ends_with(";base64") {
+            return Err(invalid_data("data URI is not base64 encoded"));
+        }
+        decode_base64(data)
+    } else {
+        fs::read(dir.join(decode_percent(uri)))
+    }
+}
+
+fn decode_base64(data: &str) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(data.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut n = 0;
+
+    for c in data.bytes().take_while(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err(invalid_data("invalid base64 data")),
+        };
+        bits = bits << 6 | u32::from(value);
+        n += 6;
+        if n >= 8 {
+            n -= 8;
+            bytes.push((bits >> n) as u8);
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Undoes the percent-encoding of relative URIs, e.g. `%20` for spaces.
+fn decode_percent(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(b) if bytes[i] == b'%' => {
+                decoded.push(b);
+                i += 3;
+            }
+            _ => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn floats(json: &Json) -> Option<Vec<f32>> {
+    json.as_array().iter().map(Json::as_f32).collect()
+}
+
+fn to_array<const N: usize>(values: &[f32]) -> [f32; N] {
+    let mut array = [0.0; N];
+    array.copy_from_slice(values);
+    array
+}
+
+/// The first three numbers of an array, or `default`.
+fn vec3(json: &Json, default: Vec3) -> Vec3 {
+    match floats(json) {
+        Some(v) if v.len() >= 3 => Vec3::new(v[0], v[1], v[2]),
+        _ => default,
+    }
+}
+
+fn scalar(json: &Json, default: f32) -> Vec3 {
+    let s = json.as_f32().unwrap_or(default);
+    Vec3::new(s, s, s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ray::Ray;
+
+    /// A unit triangle in the xy plane, scaled by two and moved five units
+    /// down the -z axis, seen by a camera and lit by a point light.
+    const SCENE: &str = r#"{
+        "asset": {"version": "2.0"},
+        "scene": 0,
+        "scenes": [{"nodes": [0, 2, 3]}],
+        "nodes": [
+            {"translation": [0, 0, -5], "children": [1]},
+            {"scale": [2, 2, 2], "mesh": 0},
+            {"camera": 0},
+            {"translation": [0, 3, 0], "extensions": {"KHR_lights_punctual": {"light": 0}}}
+        ],
+        "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1, "material": 0}]}],
+        "materials": [{"pbrMetallicRoughness": {"baseColorFactor": [0.5, 0.25, 1, 1]}}],
+        "cameras": [{"type": "perspective", "perspective": {"yfov": 0.8, "znear": 0.1}}],
+        "extensions": {"KHR_lights_punctual": {"lights": [{"type": "point", "intensity": 20}]}},
+        "accessors": [
+            {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"},
+            {"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}
+        ],
+        "bufferViews": [
+            {"buffer": 0, "byteOffset": 0, "byteLength": 36},
+            {"buffer": 0, "byteOffset": 36, "byteLength": 6}
+        ],
+        "buffers": [{"byteLength": 44}]
+    }"#;
+
+    fn glb(scene: &str) -> Vec<u8> {
+        let mut json = scene.as_bytes().to_vec();
+        json.resize(json.len().div_ceil(4) * 4, b' ');
+
+        let mut binary = Vec::new();
+        for v in &[0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            binary.extend_from_slice(&v.to_le_bytes());
+        }
+        for i in &[0u16, 1, 2, 0] {
+            binary.extend_from_slice(&i.to_le_bytes());
+        }
+
+        let mut glb = b"glTF".to_vec();
+        let length = 12 + 8 + json.len() + 8 + binary.len();
+        for word in &[2, length as u32, json.len() as u32, 0x4e4f_534a] {
+            glb.extend_from_slice(&word.to_le_bytes());
+        }
+        glb.extend_from_slice(&json);
+        for word in &[binary.len() as u32, 0x004e_4942] {
+            glb.extend_from_slice(&word.to_le_bytes());
+        }
+        glb.extend_from_slice(&binary);
+        glb
+    }
+
+    #[test]
+    fn decodes_binary_scenes_with_node_transforms() {
+        let scene = GltfScene::decode(&glb(SCENE), Path::new(""), 1.0).unwrap();
+        assert_eq!(scene.objects.len(), 1);
+        assert_eq!(scene.lights.len(), 1);
+        assert!(scene.camera.is_some());
+
+        let ray = Ray::new(Vec3::new(1.5, 0.25, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = scene.objects.hit(&ray, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-5);
+
+        let miss = Ray::new(Vec3::new(2.5, 0.25, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(scene.objects.hit(&miss, 0.001, f32::MAX).is_none());
+
+        let light = scene.lights[0]
+            .sample(Vec3::zero(), &mut crate::rng::Pcg32::new(0, 0))
+            .unwrap();
+        assert!((light.radiance - Vec3::new(20.0, 20.0, 20.0) / 9.0).lenght() < 1e-4);
+    }
+
+    #[test]
+    fn rejects_accessors_outside_their_buffers() {
+        let positions = r#"{"bufferView": 0, "componentType": 5126, "count": 3,"#;
+        let indices = r#""byteOffset": 36, "byteLength": 6"#;
+        let huge = "18446744073709551615";
+
+        for (from, to) in &[
+            (positions, positions.replace('3', "4")),
+            (positions, positions.replace('3', huge)),
+            (
+                positions,
+                positions.replace("0,", &format!("0, \"byteOffset\": {},", huge)),
+            ),
+            (
+                positions,
+                positions
+                    .replace(r#""bufferView": 0, "#, "")
+                    .replace('3', huge),
+            ),
+            (indices, indices.replace("36", huge)),
+            (indices, format!("{}, \"byteStride\": 1", indices)),
+        ] {
+            assert!(SCENE.contains(from));
+            let scene = SCENE.replacen(from, to, 1);
+            let result = GltfScene::decode(&glb(&scene), Path::new(""), 1.0);
+            assert!(result.is_err(), "{}", to);
+        }
+    }
+
+    #[test]
+    fn decodes_data_uris() {
+        assert_eq!(decode_base64("aGVsbG8gd29ybGQ=").unwrap(), b"hello world");
+        assert!(decode_base64("not base64!").is_err());
+        assert_eq!(decode_percent("my%20model.bin"), "my model.bin");
+        assert_eq!(
+            load_uri(Path::new(""), "data:application/octet-stream;base64,AAEC").unwrap(),
+            vec![0, 1, 2]
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use std::io::{self, BufRead, Read};
+
+use crate::error::invalid_data;
+use crate::texture::ImageTexture;
+use crate::vec3::Vec3;
+
+/// Decodes a Radiance RGBE (`.hdr`) image.
+///
+/// Only the standard `-Y height +X width` orientation is supported, with
+/// either flat or new-style run length encoded scanlines.
+pub fn decode<R: BufRead>(mut reader: R) -> io::Result<ImageTexture> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid_data("missing Radiance signature"));
+    }
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("unexpected end of header"));
+        }
+
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid_data("unsupported pixel format"));
+        }
+    }
+
+    line.clear();
+    reader.read_line(&mut line)?;
+    let resolution: Vec<&str> = line.split_whitespace().collect();
+    let (height, width) = match resolution.as_slice() {
+        ["-Y", h, "+X", w] => (parse_dimension(h)?, parse_dimension(w)?),
+        _ => return Err(invalid_data("unsupported image orientation")),
+    };
+
+    // The header is untrusted, so check the pixels could actually be
+    // stored in what is left of the file before allocating them
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let size = width.checked_mul(height);
+    let encoded = height.checked_mul(min_scanline_bytes(width));
+    match (size, encoded) {
+        (Some(_), Some(bytes)) if bytes <= data.len() => {}
+        _ => return Err(invalid_data("image dimensions exceed the file size")),
+    }
+
+    let mut data = &data[..];
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut scanline = vec![[0u8; 4]; width];
+
+    for _ in 0..height {
+        read_scanline(&mut data, &mut scanline)?;
+        pixels.extend(scanline.iter().map(|rgbe| rgbe_to_vec3(*rgbe)));
+    }
+
+    Ok(ImageTexture {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+    })
+}
+
+/// Smallest number of bytes a scanline of `width` pixels can take, with
+/// every channel run length encoded in runs of up to 127 pixels.
+fn min_scanline_bytes(width: usize) -> usize {
+    if is_run_length_width(width) {
+        4 + 4 * 2 * width.div_ceil(127)
+    } else {
+        4 * width
+    }
+}
+
+fn is_run_length_width(width: usize) -> bool {
+    (8..0x8000).contains(&width)
+}
+
+fn read_scanline<R: Read>(reader: &mut R, scanline: &mut [[u8; 4]]) -> io::Result<()> {
+    let width = scanline.len();
+    let mut first = [0u8; 4];
+    reader.read_exact(&mut first)?;
+
+    let run_length_encoded = is_run_length_width(width)
+        && first[0] == 2
+        && first[1] == 2
+        && ((first[2] as usize) << 8 | first[3] as usize) == width;
+
+    if !run_length_encoded {
+        scanline[0] = first;
+        for pixel in scanline.iter_mut().skip(1) {
+            reader.read_exact(pixel)?;
+        }
+        return Ok(());
+    }
+
+    // Each channel is stored separately, as a mix of runs and literals
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            reader.read_exact(&mut count)?;
+
+            if count[0] > 128 {
+                let run = (count[0] - 128) as usize;
+                let mut value = [0u8; 1];
+                reader.read_exact(&mut value)?;
+                if x + run > width {
+                    return Err(invalid_data("scanline run overflows the image"));
+                }
+                for pixel in &mut scanline[x..x + run] {
+                    pixel[channel] = value[0];
+                }
+                x += run;
+            } else {
+                let run = count[0] as usize;
+                if run == 0 || x + run > width {
+                    return Err(invalid_data("invalid scanline literal"));
+                }
+                let mut values = vec![0u8; run];
+                reader.read_exact(&mut values)?;
+                for (pixel, value) in scanline[x..x + run].iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
+                x += run;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn rgbe_to_vec3(rgbe: [u8; 4]) -> Vec3 {
+    if rgbe[3] == 0 {
+        return Vec3::zero();
+    }
+
+    let scale = 2f32.powi(i32::from(rgbe[3]) - (128 + 8));
+    Vec3::new(
+        (f32::from(rgbe[0]) + 0.5) * scale,
+        (f32::from(rgbe[1]) + 0.5) * scale,
+        (f32::from(rgbe[2]) + 0.5) * scale,
+    )
+}
+
+fn parse_dimension(s: &str) -> io::Result<usize> {
+    s.parse()
+        .map_err(|_| invalid_data("invalid image dimensions"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_flat_and_run_length_encoded_scanlines() {
+        let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 8\n".to_vec();
+
+        // Flat scanline
+        for _ in 0..8 {
+            data.extend_from_slice(&[128, 64, 0, 129]);
+        }
+
+        // Run length encoded scanline, with a run for r, g and e and
+        // literals for b
+        data.extend_from_slice(&[2, 2, 0, 8]);
+        data.extend_from_slice(&[128 + 8, 255]);
+        data.extend_from_slice(&[128 + 8, 0]);
+        data.extend_from_slice(&[8, 0, 1, 2, 3, 4, 5, 6, 7]);
+        data.extend_from_slice(&[128 + 8, 128]);
+
+        let image = decode(&data[..]).unwrap();
+
+        assert_eq!(image.width, 8);
+        assert_eq!(image.height, 2);
+        assert!((image.pixels[0].r() - 128.5 / 128.0).abs() < 1e-6);
+        assert!((image.pixels[0].g() - 64.5 / 128.0).abs() < 1e-6);
+        assert!((image.pixels[8].r() - 255.5 / 256.0).abs() < 1e-6);
+        assert!((image.pixels[15].b() - 7.5 / 256.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rejects_dimensions_larger_than_the_data() {
+        let mut data = b"#?RADIANCE\n\n-Y 2 +X 8\n".to_vec();
+        data.extend_from_slice(&[128, 64, 0, 129]);
+        assert!(decode(&data[..]).is_err());
+
+        let data = b"#?RADIANCE\n\n-Y 18446744073709551615 +X 2\n\0\0\0\0";
+        assert!(decode(&data[..]).is_err());
+    }
+}
@@ -0,0 +1,283 @@
+use std::io;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::error::invalid_data;
+
+/// Parsed JSON document. Objects keep their members in file order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+static NULL: Json = Json::Null;
+
+/// Deepest nesting of arrays and objects accepted, so that hostile files
+/// cannot overflow the stack of the recursive parser.
+const MAX_DEPTH: usize = 128;
+
+impl Json {
+    pub fn parse(text: &str) -> io::Result<Json> {
+        let mut chars = text.chars().peekable();
+        let value = parse_value(&mut chars, 0)?;
+
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            None => Ok(value),
+            Some(c) => Err(invalid_data(&format!("unexpected {:?} after value", c))),
+        }
+    }
+
+    /// Member of an object, or `Null` if it is missing or this is not an
+    /// object, so lookups can be chained like `json.get("a").get("b")`.
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(k, _)| k == key)
+                .map_or(&NULL, |(_, v)| v),
+            _ => &NULL,
+        }
+    }
+
+    /// Element of an array, or `Null` if it is out of bounds.
+    pub fn at(&self, index: usize) -> &Json {
+        self.as_array().get(index).unwrap_or(&NULL)
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Json::Null
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        self.as_f64().map(|n| n as f32)
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_f64()
+            .filter(|n| *n >= 0.0 && n.fract() == 0.0)
+            .map(|n| n as usize)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Elements of an array, or an empty slice for anything else.
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(elements) => elements,
+            _ => &[],
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+        chars.next();
+    }
+}
+
+fn expect(chars: &mut Peekable<Chars>, literal: &str) -> io::Result<()> {
+    for expected in literal.chars() {
+        if chars.next() != Some(expected) {
+            return Err(invalid_data(&format!("expected {}", literal)));
+        }
+    }
+    Ok(())
+}
+
+fn parse_value(chars: &mut Peekable<Chars>, depth: usize) -> io::Result<Json> {
+    skip_whitespace(chars);
+    if depth >= MAX_DEPTH && matches!(chars.peek(), Some('[') | Some('{')) {
+        return Err(invalid_data("document is nested too deeply"));
+    }
+
+    match chars.peek() {
+        Some('n') => expect(chars, "null").map(|_| Json::Null),
+        Some('t') => expect(chars, "true").map(|_| Json::Bool(true)),
+        Some('f') => expect(chars, "false").map(|_| Json::Bool(false)),
+        Some('"') => parse_string(chars).map(Json::String),
+        Some('[') => {
+            chars.next();
+            let mut elements = Vec::new();
+            if !parse_separator(chars, ']', true)? {
+                loop {
+                    elements.push(parse_value(chars, depth + 1)?);
+                    if parse_separator(chars, ']', false)? {
+                        break;
+                    }
+                }
+            }
+            Ok(Json::Array(elements))
+        }
+        Some('{') => {
+            chars.next();
+            let mut members = Vec::new();
+            if !parse_separator(chars, '}', true)? {
+                loop {
+                    skip_whitespace(chars);
+                    let key = parse_string(chars)?;
+                    skip_whitespace(chars);
+                    expect(chars, ":")?;
+                    members.push((key, parse_value(chars, depth + 1)?));
+                    if parse_separator(chars, '}', false)? {
+                        break;
+                    }
+                }
+            }
+            Ok(Json::Object(members))
+        }
+        Some(c) if *c == '-' || c.is_ascii_digit() => parse_number(chars),
+        Some(c) => Err(invalid_data(&format!("unexpected {:?}", c))),
+        None => Err(invalid_data("unexpected end of document")),
+    }
+}
+
+/// Consumes the comma between two elements or the closing bracket,
+/// returning whether the container ended. Before the first element only
+/// the closing bracket is accepted.
+fn parse_separator(chars: &mut Peekable<Chars>, close: char, first: bool) -> io::Result<bool> {
+    skip_whitespace(chars);
+
+    match chars.peek() {
+        Some(&c) if c == close => {
+            chars.next();
+            Ok(true)
+        }
+        _ if first => Ok(false),
+        Some(',') => {
+            chars.next();
+            Ok(false)
+        }
+        _ => Err(invalid_data(&format!("expected , or {}", close))),
+    }
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> io::Result<Json> {
+    let mut text = String::new();
+    while let Some(&c) = chars.peek() {
+        if !(c.is_ascii_digit() || "+-.eE".contains(c)) {
+            break;
+        }
+        text.push(c);
+        chars.next();
+    }
+
+    text.parse()
+        .map(Json::Number)
+        .map_err(|_| invalid_data(&format!("invalid number {}", text)))
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> io::Result<String> {
+    expect(chars, "\"")?;
+
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => match chars.next() {
+                Some('"') => s.push('"'),
+                Some('\\') => s.push('\\'),
+                Some('/') => s.push('/'),
+                Some('b') => s.push('\u{8}'),
+                Some('f') => s.push('\u{c}'),
+                Some('n') => s.push('\n'),
+                Some('r') => s.push('\r'),
+                Some('t') => s.push('\t'),
+                Some('u') => {
+                    let mut code = parse_hex4(chars)?;
+                    if (0xd800..0xdc00).contains(&code) {
+                        // High surrogate, which must be followed by a low one
+                        expect(chars, "\\u")?;
+                        let low = parse_hex4(chars)?;
+                        if !(0xdc00..0xe000).contains(&low) {
+                            return Err(invalid_data("unpaired surrogate"));
+                        }
+                        code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                    }
+                    s.push(
+                        std::char::from_u32(code)
+                            .ok_or_else(|| invalid_data("invalid unicode escape"))?,
+                    );
+                }
+                _ => return Err(invalid_data("invalid escape sequence")),
+            },
+            Some(c) => s.push(c),
+            None => return Err(invalid_data("unterminated string")),
+        }
+    }
+}
+
+fn parse_hex4(chars: &mut Peekable<Chars>) -> io::Result<u32> {
+    let mut code = 0;
+    for _ in 0..4 {
+        let digit = chars
+            .next()
+            .and_then(|c| c.to_digit(16))
+            .ok_or_else(|| invalid_data("invalid unicode escape"))?;
+        code = code * 16 + digit;
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_documents() {
+        let json = Json::parse(
+            r#" {"asset": {"version": "2.0"}, "nodes": [{"translation": [1, -2.5, 3e2]}, {}],
+                 "empty": [], "flag": true, "name": "a\"bé😀", "none": null} "#,
+        )
+        .unwrap();
+
+        assert_eq!(json.get("asset").get("version").as_str(), Some("2.0"));
+        let translation = json.get("nodes").at(0).get("translation");
+        assert_eq!(translation.at(1).as_f32(), Some(-2.5));
+        assert_eq!(translation.at(2).as_usize(), Some(300));
+        assert_eq!(json.get("nodes").at(1), &Json::Object(Vec::new()));
+        assert!(json.get("empty").as_array().is_empty());
+        assert_eq!(json.get("flag"), &Json::Bool(true));
+        assert_eq!(json.get("name").as_str(), Some("a\"bé😀"));
+        assert!(json.get("none").is_null());
+        assert!(json.get("missing").get("deeper").at(3).is_null());
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        for text in &[
+            "",
+            "[1, 2",
+            "[1 2]",
+            "{\"a\" 1}",
+            "[1,]",
+            "\"abc",
+            "tru",
+            "1 2",
+        ] {
+            assert!(Json::parse(text).is_err(), "{}", text);
+        }
+
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(Json::parse(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(Json::parse(&nested(100_000)).is_err());
+    }
+}
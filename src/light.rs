@@ -0,0 +1,230 @@
+use crate::vec3::Vec3;
+
+/// Illumination arriving at a point from a light.
+#[derive(Clone, Copy, Debug)]
+pub struct LightSample {
+    /// Unit vector from the shaded point towards the light.
+    pub direction: Vec3,
+    /// Distance to the light, used to limit the shadow ray.
+    pub distance: f32,
+    /// Radiance arriving along `direction`, already divided by the density
+    /// of the sample.
+    pub radiance: Vec3,
+}
+
+/// Light source that can only be reached through explicit sampling.
+///
+/// Delta lights have no area, so scattered rays never hit them and their
+/// contribution must be gathered by shadow rays at every bounce.
+pub trait Light: Send + Sync {
+    fn sample(&self, p: Vec3, rng: &mut dyn rand::RngCore) -> Option<LightSample>;
+}
+
+/// Light emitted equally in all directions from a single point.
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub position: Vec3,
+    /// Radiant intensity, i.e. power per unit solid angle.
+    pub intensity: Vec3,
+}
+
+impl Light for PointLight {
+    fn sample(&self, p: Vec3, _: &mut dyn rand::RngCore) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance = to_light.lenght();
+        if distance == 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            radiance: self.intensity / (distance * distance),
+        })
+    }
+}
+
+/// Point light restricted to a cone around `direction`.
+///
+/// The intensity is full inside `falloff_start` degrees from the axis and
+/// smoothly fades out until `cone_angle` degrees.
+#[derive(Clone, Copy, Debug)]
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub intensity: Vec3,
+    pub cone_angle: f32,
+    pub falloff_start: f32,
+}
+
+impl SpotLight {
+    fn falloff(&self, w: Vec3) -> f32 {
+        let cos_theta = w.dot(self.direction.normalized());
+        let cos_total = self.cone_angle.to_radians().cos();
+        let cos_start = self.falloff_start.min(self.cone_angle).to_radians().cos();
+
+        if cos_theta < cos_total {
+            0.0
+        } else if cos_theta >= cos_start {
+            1.0
+        } else {
+            let t = (cos_theta - cos_total) / (cos_start - cos_total);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, p: Vec3, _: &mut dyn rand::RngCore) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance = to_light.lenght();
+        if distance == 0.0 {
+            return None;
+        }
+
+        let direction = to_light / distance;
+        let falloff = self.falloff(-direction);
+        if falloff == 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: falloff * self.intensity / (distance * distance),
+        })
+    }
+}
+
+/// Light arriving from infinitely far away along a single direction.
+#[derive(Clone, Copy, Debug)]
+pub struct DirectionalLight {
+    /// Direction in which the light travels.
+    pub direction: Vec3,
+    /// Irradiance on a surface perpendicular to `direction`.
+    pub irradiance: Vec3,
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _: Vec3, _: &mut dyn rand::RngCore) -> Option<LightSample> {
+        Some(LightSample {
+            direction: -self.direction.normalized(),
+            distance: f32::MAX,
+            radiance: self.irradiance,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::rng::Pcg32;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5 * b.abs().max(1.0), "{} != {}", a, b);
+    }
+
+    fn spot() -> SpotLight {
+        SpotLight {
+            position: Vec3::new(0.0, 4.0, 0.0),
+            direction: Vec3::new(0.0, -2.0, 0.0),
+            intensity: Vec3::new(8.0, 8.0, 8.0),
+            cone_angle: 40.0,
+            falloff_start: 20.0,
+        }
+    }
+
+    /// Falloff towards a point on the floor `angle` degrees off the axis.
+    fn spot_falloff(light: &SpotLight, angle: f32) -> f32 {
+        let p = Vec3::new(4.0 * angle.to_radians().tan(), 0.0, 0.0);
+        let distance = (light.position - p).lenght();
+        match light.sample(p, &mut Pcg32::new(0, 0)) {
+            Some(sample) => sample.radiance.r() * distance * distance / 8.0,
+            None => 0.0,
+        }
+    }
+
+    #[test]
+    fn point_lights_fall_off_with_the_square_of_distance() {
+        let light = PointLight {
+            position: Vec3::new(1.0, 2.0, 3.0),
+            intensity: Vec3::new(4.0, 8.0, 12.0),
+        };
+        let mut rng = Pcg32::new(1, 1);
+
+        let near = light.sample(Vec3::new(1.0, 0.0, 3.0), &mut rng).unwrap();
+        assert_eq!(near.direction, Vec3::up());
+        assert_close(near.distance, 2.0);
+        assert_close(near.radiance.g(), 2.0);
+
+        let far = light.sample(Vec3::new(1.0, 2.0, -3.0), &mut rng).unwrap();
+        assert_close(far.distance, 6.0);
+        assert_close(far.radiance.b(), 12.0 / 36.0);
+
+        assert!(light.sample(light.position, &mut rng).is_none());
+    }
+
+    #[test]
+    fn spot_lights_fade_out_between_the_angles() {
+        let light = spot();
+
+        // Full intensity inside the inner cone and up to its edge
+        assert_close(spot_falloff(&light, 0.0), 1.0);
+        assert_close(spot_falloff(&light, 10.0), 1.0);
+        assert_close(spot_falloff(&light, 19.9), 1.0);
+
+        // Smoothly decreasing in the transition band
+        let band: Vec<f32> = [22.0, 30.0, 38.0]
+            .iter()
+            .map(|&a| spot_falloff(&light, a))
+            .collect();
+        assert!(band[0] < 1.0 && band[0] > band[1] && band[1] > band[2] && band[2] > 0.0);
+
+        // Nothing at all outside the cone
+        assert_eq!(spot_falloff(&light, 40.1), 0.0);
+        assert_eq!(spot_falloff(&light, 60.0), 0.0);
+        assert!(light
+            .sample(Vec3::new(0.0, 8.0, 0.0), &mut Pcg32::new(0, 0))
+            .is_none());
+
+        // Without a transition band the cone has a hard edge
+        let hard = SpotLight {
+            falloff_start: 50.0,
+            ..light
+        };
+        assert_close(spot_falloff(&hard, 39.9), 1.0);
+        assert_eq!(spot_falloff(&hard, 40.1), 0.0);
+    }
+
+    #[test]
+    fn delta_lights_are_sampled_with_unit_density() {
+        // Each light has a single direction, so every sample is the same and
+        // carries the full unweighted contribution
+        let lights: [Box<dyn Light>; 3] = [
+            Box::new(PointLight {
+                position: Vec3::new(0.0, 4.0, 0.0),
+                intensity: Vec3::new(8.0, 8.0, 8.0),
+            }),
+            Box::new(spot()),
+            Box::new(DirectionalLight {
+                direction: Vec3::new(0.0, -3.0, 0.0),
+                irradiance: Vec3::new(2.0, 2.0, 2.0),
+            }),
+        ];
+        let expected = [0.5, 0.5, 2.0];
+
+        let mut rng = Pcg32::new(5, 9);
+        for (light, &expected) in lights.iter().zip(expected.iter()) {
+            let first = light.sample(Vec3::zero(), &mut rng).unwrap();
+            assert_eq!(first.direction, Vec3::up());
+            assert_close(first.radiance.r(), expected);
+
+            for _ in 0..10 {
+                let sample = light.sample(Vec3::zero(), &mut rng).unwrap();
+                assert_eq!(sample.direction, first.direction);
+                assert_eq!(sample.radiance, first.radiance);
+            }
+        }
+    }
+}
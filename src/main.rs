@@ -1,136 +1,739 @@
 #![allow(dead_code)]
 
-use std::fs::File;
-use std::io::BufWriter;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use png::HasParameters;
 use rand::prelude::*;
 
+mod aperture;
+mod bvh;
 mod camera;
+mod checkpoint;
+mod csg;
+mod distribution;
+mod environment;
+mod error;
+mod exposure;
+mod film;
 mod geometry;
+mod gltf;
+mod hdr;
+mod json;
+mod light;
 mod material;
+mod mesh;
+mod metadata;
+mod microfacet;
+mod options;
+mod pbrt;
+mod ply;
+mod polynomial;
+mod principled;
+mod progress;
 mod ray;
+mod rng;
+mod sampling;
+mod scene;
+mod shapes;
+mod sky;
+mod stats;
+mod stereo;
+mod stl;
+#[cfg(test)]
+mod test_util;
+mod texture;
+mod transform;
 mod vec3;
 
-use crate::camera::Camera;
-use crate::geometry::{HitInfo, Hitable, Sphere};
-use crate::material::Material::*;
+use crate::aperture::Aperture;
+use crate::camera::{
+    Camera, EquirectangularCamera, FisheyeCamera, OrthographicCamera, PerspectiveCamera,
+};
+use crate::checkpoint::{Checkpoint, Checkpointer};
+use crate::environment::{Environment, EnvironmentMap, Gradient};
+use crate::exposure::{auto_ev100, ev100_to_scale};
+use crate::film::{CropWindow, Film};
+use crate::geometry::{Hitable, Sphere};
+use crate::gltf::GltfScene;
+use crate::material::{Dielectric, Lambertian, Metal};
+use crate::mesh::Mesh;
+use crate::metadata::RenderMetadata;
+use crate::options::{Options, ProgressMode, Projection, USAGE};
+use crate::pbrt::PbrtScene;
+use crate::principled::Principled;
+use crate::progress::{LineProgress, Progress, ProgressBar, Quiet};
 use crate::ray::Ray;
+use crate::rng::Pcg32;
+use crate::sampling::{power_heuristic, AdaptiveSampling};
+use crate::scene::Scene;
+use crate::shapes::Plane;
+use crate::sky::PhysicalSky;
+use crate::stats::RenderStats;
+use crate::stereo::StereoCamera;
+use crate::texture::VertexColor;
 use crate::vec3::Vec3;
 
 fn main() {
     const WIDTH: u32 = 800;
     const HEIGHT: u32 = 600;
-    const NUMBER_OF_STEPS: u32 = 100;
+    const MIN_SAMPLES: u32 = 16;
+    const MAX_SAMPLES: u32 = 512;
+    const ERROR_THRESHOLD: f32 = 0.01;
+
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+    if options.help {
+        print!("{}", USAGE);
+        return;
+    }
+
+    let exposure = options.exposure.map_or(1.0, |e| e.scale());
+
+    if !options.merge.is_empty() {
+        let film = merge_films(&options.merge).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+        let metadata = RenderMetadata::default();
+        write_output(&options, &film, &metadata, exposure);
+        return;
+    }
+
+    let scene_start = Instant::now();
 
     let look_from = Vec3::new(11.0, 2.0, 2.5);
     let look_at = Vec3::new(0.0, 0.0, 0.0);
-    let apertune = 0.05;
+    let fov = options.fov.unwrap_or(match options.camera {
+        Projection::Fisheye => 180.0,
+        _ => 25.0,
+    });
+    let apertune = match options.exposure {
+        Some(exposure) => exposure.aperture(fov),
+        None => 0.05,
+    };
     let dist_to_focus = (look_from - look_at).lenght();
 
-    let camera = Camera::new(
-        look_from,
-        look_at,
-        Vec3::up(),
-        25.0,
-        WIDTH as f32 / HEIGHT as f32,
-        apertune,
-        dist_to_focus,
-    );
+    let mut pbrt = options.pbrt.as_ref().map(|path| {
+        let scene = PbrtScene::load(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        });
+        for warning in &scene.warnings {
+            eprintln!("{}: warning: {}", path, warning);
+        }
+        scene
+    });
 
-    let mut rng = rand::thread_rng();
+    let (width, height) = pbrt
+        .as_ref()
+        .map_or((WIDTH, HEIGHT), |s| (s.width, s.height));
+    if let Some(window) = options.crop {
+        if window.clamped(width, height).is_empty() {
+            eprintln!(
+                "crop window {},{},{},{} is outside the {}x{} frame",
+                window.x, window.y, window.width, window.height, width, height
+            );
+            process::exit(1);
+        }
+    }
+    let scene_samples = pbrt.as_ref().and_then(|s| s.samples);
+    let aspect = width as f32 / height as f32;
 
-    let world = random_scene(&mut rng);
+    let mut gltf = options.gltf.as_ref().map(|path| {
+        GltfScene::load(Path::new(path), aspect).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        })
+    });
 
-    let data = raytrace(&world, &camera, WIDTH, HEIGHT, NUMBER_OF_STEPS, &mut rng);
+    // Decoded once, since stereo cameras build an eye at a time
+    let aperture = match &options.aperture_mask {
+        Some(path) => Aperture::load_mask(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }),
+        None => match options.aperture_blades {
+            Some(blades) => Aperture::Polygon {
+                blades,
+                rotation: options.aperture_rotation,
+            },
+            None => Aperture::Circular,
+        },
+    };
 
-    write_image("test.png", WIDTH, HEIGHT, &data);
-}
+    let eye = |look_from: Vec3, look_at: Vec3, aspect: f32| {
+        let mut camera = PerspectiveCamera::new(
+            look_from,
+            look_at,
+            Vec3::up(),
+            fov,
+            aspect,
+            apertune,
+            dist_to_focus,
+        );
 
-fn raytrace(
-    world: &[Box<Hitable>],
-    camera: &Camera,
-    width: u32,
-    height: u32,
-    samples: u32,
-    rng: &mut rand::RngCore,
-) -> Vec<u8> {
-    let mut data = Vec::<u8>::with_capacity((4 * width * height) as usize);
-
-    for y in (0..height).rev() {
-        for x in 0..width {
-            let mut col = Vec3::zero();
-
-            for _ in 0..samples {
-                let jitter_x: f32 = rng.gen();
-                let jitter_y: f32 = rng.gen();
-                let u = ((x as f32) + jitter_x) / width as f32;
-                let v = ((y as f32) + jitter_y) / height as f32;
-
-                let ray = camera.get_ray(u, v, rng);
-
-                col += color(&ray, &world, rng, 0);
+        camera.aperture = aperture.clone();
+        camera.cat_eye = options.cat_eye;
+        camera
+    };
+
+    // Scenes that bring their own camera are rendered through it
+    let scene_camera = gltf
+        .as_mut()
+        .and_then(|scene| scene.camera.take())
+        .or_else(|| pbrt.as_mut().and_then(|scene| scene.camera.take()));
+    let camera: Box<dyn Camera> = match (scene_camera, options.stereo) {
+        (Some(camera), _) => camera,
+        (None, Some(layout)) if options.camera == Projection::Equirectangular => {
+            Box::new(StereoCamera::ods(
+                look_from,
+                look_at,
+                Vec3::up(),
+                options.interocular,
+                dist_to_focus,
+                layout,
+            ))
+        }
+        (None, Some(layout)) => Box::new(StereoCamera::perspective(
+            look_from,
+            look_at,
+            Vec3::up(),
+            options.interocular,
+            dist_to_focus,
+            layout,
+            |from, at| eye(from, at, layout.eye_aspect(aspect)),
+        )),
+        (None, None) => match options.camera {
+            Projection::Perspective => Box::new(eye(look_from, look_at, aspect)),
+            Projection::Orthographic => Box::new(OrthographicCamera::new(
+                look_from,
+                look_at,
+                Vec3::up(),
+                // Frames the focus plane like the perspective camera would
+                2.0 * dist_to_focus * (fov.to_radians() / 2.0).tan(),
+                aspect,
+            )),
+            Projection::Fisheye => Box::new(FisheyeCamera::new(
+                look_from,
+                look_at,
+                Vec3::up(),
+                fov,
+                aspect,
+            )),
+            Projection::Equirectangular => {
+                Box::new(EquirectangularCamera::new(look_from, look_at, Vec3::up()))
             }
+        },
+    };
 
-            col /= samples as f32;
-            data.push((255.0 * col.r().sqrt()) as u8);
-            data.push((255.0 * col.g().sqrt()) as u8);
-            data.push((255.0 * col.b().sqrt()) as u8);
-            data.push(255);
+    let progressive = options.progressive || options.time_limit.is_some();
+    let resume = options.resume.as_ref().map(|path| {
+        let checkpoint = Checkpoint::load(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        });
+        if (checkpoint.film.width, checkpoint.film.height) != (width, height) {
+            eprintln!("{}: checkpoint is for a different frame size", path);
+            process::exit(1);
+        }
+        if checkpoint.progressive != progressive {
+            let mode = |progressive| {
+                if progressive {
+                    "progressive"
+                } else {
+                    "row by row"
+                }
+            };
+            eprintln!(
+                "{}: checkpoint is from a {} render, not {}",
+                path,
+                mode(checkpoint.progressive),
+                mode(progressive)
+            );
+            process::exit(1);
+        }
+        checkpoint
+    });
+    let seed = resume.as_ref().map_or(options.seed, |c| c.seed);
 
-            print!("\r");
-            print!(
-                "{:.2}% Completed                                   ",
-                (data.len() * 25) as f32 / (width * height) as f32
+    let scene_environment = pbrt.as_mut().and_then(|scene| scene.environment.take());
+    let environment: Box<dyn Environment> = match (&options.environment, scene_environment) {
+        (Some(path), _) => Box::new(
+            EnvironmentMap::load(
+                Path::new(path),
+                options.environment_rotation,
+                options.environment_intensity,
+            )
+            .unwrap_or_else(|e| {
+                eprintln!("{}: {}", path, e);
+                process::exit(1);
+            }),
+        ),
+        (None, Some(environment)) => environment,
+        (None, None) if options.sky => {
+            let mut sky = PhysicalSky::new(
+                options.sun_elevation,
+                options.sun_azimuth,
+                options.turbidity,
             );
+            if options.exposure.is_some() {
+                // Radiance in cd/m^2 instead of kcd/m^2 scaled for display
+                sky.intensity = 1000.0;
+            }
+            Box::new(sky)
         }
+        (None, None) => Box::new(Gradient::sky()),
+    };
+
+    let (mut world, lights) = match (gltf, pbrt) {
+        (Some(scene), _) => (scene.objects, scene.lights),
+        (None, Some(scene)) => (scene.objects, scene.lights),
+        (None, None) => (random_scene(&mut Pcg32::new(seed, 0)), Vec::new()),
+    };
+
+    if let Some(path) = &options.mesh {
+        let mesh = Mesh::load(Path::new(path), Arc::new(mesh_material())).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        });
+        world.push(Box::new(mesh));
     }
 
-    data
+    let scene = Scene {
+        world,
+        environment,
+        lights,
+    };
+
+    let mut sampling = match scene_samples {
+        // Match the sample count of the reference renderer
+        Some(samples) => AdaptiveSampling::fixed(samples),
+        None => AdaptiveSampling::new(MIN_SAMPLES, MAX_SAMPLES, ERROR_THRESHOLD),
+    };
+    sampling.exposure = exposure;
+    if options.time_limit.is_some() {
+        // Keep sampling for as long as the budget allows, rather than
+        // until the pixels look converged
+        sampling.max_samples = u32::MAX;
+        sampling.threshold = 0.0;
+    }
+    // The budget covers loading the scene too
+    let deadline = options.time_limit.map(|limit| scene_start + limit);
+
+    let (mut film, start_row, passes, mut rng) = match resume {
+        Some(checkpoint) => (
+            checkpoint.film,
+            checkpoint.next_row,
+            checkpoint.passes,
+            checkpoint.rng,
+        ),
+        None => {
+            let window = options
+                .crop
+                .unwrap_or_else(|| CropWindow::full(width, height));
+            let film = Film::cropped(width, height, window);
+            let start_row = film.window.y;
+            (film, start_row, 0, Pcg32::new(seed, 1))
+        }
+    };
+
+    let mut checkpointer = options.checkpoint.as_ref().map(|path| {
+        Checkpointer::new(
+            PathBuf::from(path),
+            options.checkpoint_interval,
+            seed,
+            progressive,
+        )
+    });
+
+    let scene_time = scene_start.elapsed();
+    let start = Instant::now();
+    let mut metadata = RenderMetadata {
+        seed: Some(seed),
+        time_limit: options.time_limit,
+        ..RenderMetadata::default()
+    };
+
+    let mut progress: Box<dyn Progress> = match options.progress {
+        ProgressMode::Bar => Box::new(ProgressBar::new()),
+        ProgressMode::Lines => Box::new(LineProgress::new(io::stdout(), Duration::from_secs(1))),
+        ProgressMode::Quiet => Box::new(Quiet),
+    };
+
+    let renderer = Renderer {
+        scene: &scene,
+        camera: camera.as_ref(),
+        sampling,
+    };
+
+    if progressive {
+        let mut last_snapshot = Instant::now();
+
+        renderer.raytrace_progressive(
+            &mut film,
+            &mut rng,
+            passes,
+            checkpointer.as_mut(),
+            deadline,
+            &mut |film, pass| {
+                metadata.passes = Some(pass);
+                metadata.elapsed = Some(start.elapsed());
+
+                // The render ends when the last pixel is done, unless there
+                // is a time limit, in which case that decides
+                match options.time_limit {
+                    Some(limit) => progress.update(
+                        scene_start.elapsed().as_millis() as u64,
+                        limit.as_millis() as u64,
+                    ),
+                    None => {
+                        let total = film.pixels.len();
+                        let active = film
+                            .pixels
+                            .iter()
+                            .filter(|p| renderer.sampling.next_batch(p) > 0)
+                            .count();
+                        progress.update((total - active) as u64, total as u64)
+                    }
+                }
+
+                let due = match (options.snapshot_passes, options.snapshot_interval) {
+                    (None, None) => options.progressive,
+                    (passes, interval) => {
+                        passes.is_some_and(|n| pass % n == 0)
+                            || interval.is_some_and(|t| last_snapshot.elapsed() >= t)
+                    }
+                };
+
+                if due {
+                    write_output(&options, film, &metadata, exposure);
+                    last_snapshot = Instant::now();
+                }
+
+                deadline.is_none_or(|deadline| Instant::now() < deadline)
+            },
+        );
+    } else {
+        let end_row = film.window.y + film.window.height;
+        renderer.raytrace(
+            &mut film,
+            start_row..end_row,
+            &mut rng,
+            checkpointer.as_mut(),
+            progress.as_mut(),
+        );
+    }
+
+    progress.finish();
+
+    let render_time = start.elapsed();
+    metadata.elapsed = Some(render_time);
+    let output_start = Instant::now();
+
+    if let Some(path) = &options.save_film {
+        if let Err(e) = film.save(Path::new(path)) {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        }
+    }
+
+    write_output(&options, &film, &metadata, exposure);
+
+    let stats = RenderStats::collect(scene_time, render_time, output_start.elapsed());
+    if options.progress != ProgressMode::Quiet {
+        // Progress lines may be going to stdout
+        eprintln!("{}", stats.summary());
+    }
+
+    if let Some(path) = &options.stats_json {
+        if let Err(e) = fs::write(path, stats.to_json()) {
+            eprintln!("{}: {}", path, e);
+        }
+    }
+}
+
+/// Combines films saved by separate runs into a single frame.
+fn merge_films(paths: &[String]) -> io::Result<Film> {
+    let mut frame: Option<Film> = None;
+
+    for path in paths {
+        let film = Film::load(Path::new(path))
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+        let frame = frame.get_or_insert_with(|| Film::new(film.width, film.height));
+        frame
+            .merge(&film)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+    }
+
+    Ok(frame.unwrap())
+}
+
+/// Writes the image with its metadata, and the sample heatmap if one was
+/// asked for.
+fn write_output(options: &Options, film: &Film, metadata: &RenderMetadata, exposure: f32) {
+    let exposure = if options.auto_exposure {
+        ev100_to_scale(auto_ev100(film))
+    } else {
+        exposure
+    };
+
+    let (width, height) = (film.window.width, film.window.height);
+    if let Err(e) = write_image(&options.output, width, height, &film.to_rgba8(exposure)) {
+        eprintln!("{}: {}", options.output, e);
+    }
+
+    if let Err(e) = metadata.save(film, Path::new(&options.output)) {
+        eprintln!("Failed to write metadata: {}", e);
+    }
+
+    if let Some(path) = &options.heatmap {
+        if let Err(e) = write_image(path, width, height, &film.sample_heatmap()) {
+            eprintln!("{}: {}", path, e);
+        }
+    }
+}
+
+/// What is rendered and how densely each pixel is sampled.
+struct Renderer<'a> {
+    scene: &'a Scene,
+    camera: &'a dyn Camera,
+    sampling: AdaptiveSampling,
 }
 
-fn color(ray: &Ray, world: &[Box<Hitable>], rng: &mut rand::RngCore, depth: i32) -> Vec3 {
-    if let Some(hit) = world.hit(ray, 0.001, std::f32::MAX) {
-        let scatter_data = hit.material.scatter(ray, &hit, rng);
-        if depth < 50 && scatter_data.is_some() {
-            let (scatter, attenuation) = scatter_data.unwrap();
-            return attenuation * color(&scatter, world, rng, depth + 1);
-        } else {
-            return Vec3::zero();
+impl<'a> Renderer<'a> {
+    /// Renders the pixels in `rows` of the film's window, counted from the
+    /// top. After every row the state is handed to `checkpointer`, so that
+    /// an interrupted render can be continued with identical results.
+    fn raytrace(
+        &self,
+        film: &mut Film,
+        rows: Range<u32>,
+        rng: &mut Pcg32,
+        mut checkpointer: Option<&mut Checkpointer>,
+        progress: &mut dyn Progress,
+    ) {
+        let (width, height, window) = (film.width, film.height, film.window);
+        let total = u64::from(window.width * window.height);
+
+        for row in rows {
+            let y = height - 1 - row;
+            for x in window.x..window.x + window.width {
+                let pixel = film.pixel_mut(x, y);
+
+                loop {
+                    let batch = self.sampling.next_batch(pixel);
+                    if batch == 0 {
+                        break;
+                    }
+
+                    for _ in 0..batch {
+                        pixel.add_sample(self.sample(x, y, width, height, rng));
+                    }
+                }
+            }
+
+            if let Some(checkpointer) = checkpointer.as_mut() {
+                if let Err(e) = checkpointer.update(film, row + 1, 0, rng) {
+                    eprintln!("\nFailed to save checkpoint: {}", e);
+                }
+            }
+
+            progress.update(u64::from((row + 1 - window.y) * window.width), total);
+        }
+    }
+
+    /// Renders the film's window in passes that add one sample to every
+    /// pixel still needing more, until all of them are done. After each
+    /// pass the state is handed to `checkpointer` and `on_pass` is called
+    /// with the number of passes so far, counting the `passes` a resumed
+    /// render had already done, so the image can be watched while
+    /// it refines. Rendering stops early once `on_pass` returns false, or
+    /// after the first row that finishes past `deadline`.
+    fn raytrace_progressive(
+        &self,
+        film: &mut Film,
+        rng: &mut Pcg32,
+        passes: u32,
+        mut checkpointer: Option<&mut Checkpointer>,
+        deadline: Option<Instant>,
+        on_pass: &mut dyn FnMut(&Film, u32) -> bool,
+    ) {
+        let (width, height, window) = (film.width, film.height, film.window);
+
+        for pass in passes + 1.. {
+            let mut active = false;
+
+            for row in window.y..window.y + window.height {
+                let y = height - 1 - row;
+                for x in window.x..window.x + window.width {
+                    let pixel = film.pixel_mut(x, y);
+
+                    if self.sampling.next_batch(pixel) > 0 {
+                        pixel.add_sample(self.sample(x, y, width, height, rng));
+                        active = true;
+                    }
+                }
+
+                // Passes over large images can take longer than the budget
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return;
+                }
+            }
+
+            if !active {
+                break;
+            }
+
+            if let Some(checkpointer) = checkpointer.as_mut() {
+                if let Err(e) = checkpointer.update(film, window.y, pass, rng) {
+                    eprintln!("\nFailed to save checkpoint: {}", e);
+                }
+            }
+
+            if !on_pass(film, pass) {
+                break;
+            }
+        }
+    }
+
+    /// Traces one sample through a random position in pixel `(x, y)` of a
+    /// `width` by `height` frame.
+    fn sample(&self, x: u32, y: u32, width: u32, height: u32, rng: &mut Pcg32) -> Vec3 {
+        let jitter_x: f32 = rng.gen();
+        let jitter_y: f32 = rng.gen();
+        let u = ((x as f32) + jitter_x) / width as f32;
+        let v = ((y as f32) + jitter_y) / height as f32;
+
+        match self.camera.get_ray(u, v, rng) {
+            Some(ray) => {
+                stats::CAMERA_RAYS.add(1);
+                color(&ray, self.scene, rng)
+            }
+            None => Vec3::zero(),
         }
     }
+}
+
+/// Estimates the radiance arriving along `ray` with a path tracer that
+/// samples the environment and every light at each bounce. Environment
+/// samples are combined with scattered rays through multiple importance
+/// sampling, while delta lights can only be reached by sampling them.
+fn color(ray: &Ray, scene: &Scene, rng: &mut dyn rand::RngCore) -> Vec3 {
+    let mut radiance = Vec3::zero();
+    let mut throughput = Vec3::new(1.0, 1.0, 1.0);
+    let mut ray = *ray;
 
-    let dir = ray.direction;
+    // Density of the last scattered direction, or None when it could not
+    // have been found by sampling the environment
+    let mut scatter_pdf: Option<f32> = None;
 
-    // Puts t in the range 0..1
-    let t = 0.5 * (dir.y() + 1.0);
+    for depth in 0.. {
+        let hit = match scene.hit(&ray, 0.001, f32::MAX) {
+            Some(hit) => hit,
+            None => {
+                let direction = ray.direction.normalized();
+                let weight = match scatter_pdf {
+                    Some(pdf) => power_heuristic(pdf, scene.environment.pdf(direction)),
+                    None => 1.0,
+                };
 
-    // Gradient from blue to white
-    (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
+                radiance += weight * throughput * scene.environment.radiance(direction);
+                break;
+            }
+        };
+
+        radiance += throughput * hit.material.emitted(&hit);
+
+        if depth >= 50 {
+            break;
+        }
+
+        let wo = -ray.direction.normalized();
+
+        if let Some((wi, light, light_pdf)) = scene.environment.sample(rng) {
+            let bsdf = hit.material.eval(&hit, wo, wi);
+
+            if bsdf != Vec3::zero() && !scene.occluded(hit.p, wi, f32::MAX) {
+                let weight = power_heuristic(light_pdf, hit.material.pdf(&hit, wo, wi));
+                radiance += weight * throughput * bsdf * light / light_pdf;
+            }
+        }
+
+        for light in &scene.lights {
+            if let Some(sample) = light.sample(hit.p, rng) {
+                let bsdf = hit.material.eval(&hit, wo, sample.direction);
+
+                if bsdf != Vec3::zero()
+                    && !scene.occluded(hit.p, sample.direction, sample.distance * 0.999)
+                {
+                    radiance += throughput * bsdf * sample.radiance;
+                }
+            }
+        }
+
+        match hit.material.scatter(&ray, &hit, rng) {
+            Some((scatter, attenuation)) => {
+                let pdf = hit.material.pdf(&hit, wo, scatter.direction.normalized());
+                scatter_pdf = if pdf > 0.0 { Some(pdf) } else { None };
+                throughput *= attenuation;
+                ray = scatter;
+                stats::BOUNCE_RAYS.add(1);
+            }
+            None => break,
+        }
+    }
+
+    radiance
 }
 
-fn write_image(path: &str, width: u32, height: u32, data: &[u8]) {
-    let path = Path::new(path);
-    let file = File::create(path).unwrap();
-    let w = &mut BufWriter::new(file);
+/// Writes the image next to `path` first and then moves it in place, so
+/// that a viewer watching a progressive render never sees half a file.
+fn write_image(path: &str, width: u32, height: u32, data: &[u8]) -> io::Result<()> {
+    let mut tmp = PathBuf::from(path).into_os_string();
+    tmp.push(".tmp");
 
-    let mut encoder = png::Encoder::new(w, width, height);
-    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
-    let mut writer = encoder.write_header().unwrap();
+    {
+        let w = &mut BufWriter::new(File::create(&tmp)?);
+        let mut encoder = png::Encoder::new(w, width, height);
+        encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(data)?;
+    }
 
-    writer.write_image_data(data).unwrap();
+    fs::rename(tmp, path)
 }
 
-fn random_scene(rng: &mut rand::RngCore) -> Vec<Box<Hitable>> {
-    let mut world = Vec::<Box<Hitable>>::new();
-    world.push(Box::new(Sphere::new(
-        Vec3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Lambertian {
+/// Material for meshes added with `--mesh`, whose formats have no
+/// materials: a plastic-like dielectric in the vertex colors scans bring,
+/// or a light gray for other models.
+fn mesh_material() -> Principled {
+    Principled {
+        base_color: Arc::new(VertexColor {
+            fallback: Arc::new(Vec3::new(0.8, 0.8, 0.8)),
+        }),
+        metallic: Arc::new(0.0),
+        roughness: Arc::new(0.5),
+        ..Principled::default()
+    }
+}
+
+fn random_scene(rng: &mut dyn rand::RngCore) -> Vec<Box<dyn Hitable>> {
+    let mut world = Vec::<Box<dyn Hitable>>::new();
+    world.push(Box::new(Plane {
+        point: Vec3::zero(),
+        normal: Vec3::up(),
+        material: Arc::new(Lambertian {
             albedo: Vec3::new(0.5, 0.5, 0.5),
-        },
-    )));
+        }),
+    }));
 
     for a in -11..11 {
         for b in -11..11 {
@@ -146,32 +749,32 @@ fn random_scene(rng: &mut rand::RngCore) -> Vec<Box<Hitable>> {
                     world.push(Box::new(Sphere::new(
                         center,
                         0.2,
-                        Lambertian {
+                        Arc::new(Lambertian {
                             albedo: Vec3::new(
                                 rng.gen::<f32>() * rng.gen::<f32>(),
                                 rng.gen::<f32>() * rng.gen::<f32>(),
                                 rng.gen::<f32>() * rng.gen::<f32>(),
                             ),
-                        },
+                        }),
                     )));
                 } else if mat_choice < 0.95 {
                     world.push(Box::new(Sphere::new(
                         center,
                         0.2,
-                        Metal {
+                        Arc::new(Metal {
                             albedo: Vec3::new(
                                 0.5 * (1.0 + rng.gen::<f32>()),
                                 0.5 * (1.0 + rng.gen::<f32>()),
                                 0.5 * (1.0 + rng.gen::<f32>()),
                             ),
                             fuzz: 0.5 * rng.gen::<f32>(),
-                        },
+                        }),
                     )));
                 } else {
                     world.push(Box::new(Sphere::new(
                         center,
                         0.2,
-                        Dielectric { ref_idx: 1.5 },
+                        Arc::new(Dielectric::new(1.5)),
                     )));
                 }
             }
@@ -181,23 +784,313 @@ fn random_scene(rng: &mut rand::RngCore) -> Vec<Box<Hitable>> {
     world.push(Box::new(Sphere::new(
         Vec3::new(0.0, 1.0, 0.0),
         1.0,
-        Dielectric { ref_idx: 1.5 },
+        Arc::new(Dielectric::new(1.5)),
     )));
     world.push(Box::new(Sphere::new(
         Vec3::new(-4.0, 1.0, 0.0),
         1.0,
-        Lambertian {
+        Arc::new(Lambertian {
             albedo: Vec3::new(0.1, 0.2, 0.4),
-        },
+        }),
     )));
     world.push(Box::new(Sphere::new(
         Vec3::new(4.0, 1.0, 0.0),
         1.0,
-        Metal {
+        Arc::new(Metal {
             albedo: Vec3::new(0.7, 0.6, 0.5),
             fuzz: 0.0,
-        },
+        }),
     )));
 
     world
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+
+    use crate::geometry::HitInfo;
+    use crate::material::Material;
+
+    const WIDTH: u32 = 8;
+    const HEIGHT: u32 = 6;
+
+    fn scene() -> Scene {
+        let mut rng = Pcg32::new(1, 1);
+        let mut world: Vec<Box<dyn Hitable>> = random_scene(&mut rng);
+        world.truncate(20);
+
+        Scene {
+            world,
+            environment: Box::new(Gradient::sky()),
+            lights: Vec::new(),
+        }
+    }
+
+    fn camera() -> PerspectiveCamera {
+        PerspectiveCamera::new(
+            Vec3::new(13.0, 2.0, 3.0),
+            Vec3::zero(),
+            Vec3::up(),
+            20.0,
+            WIDTH as f32 / HEIGHT as f32,
+            0.1,
+            10.0,
+        )
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("raytracer-{}-{}", process::id(), name))
+    }
+
+    /// Saved form of the film, which has every bit of every pixel.
+    fn film_bytes(film: &Film) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        film.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn resumed_renders_are_identical() {
+        let (scene, camera) = (scene(), camera());
+        let renderer = Renderer {
+            scene: &scene,
+            camera: &camera,
+            sampling: AdaptiveSampling::new(4, 32, 0.05),
+        };
+        let rows = 0..HEIGHT;
+
+        let mut expected = Film::new(WIDTH, HEIGHT);
+        let mut rng = Pcg32::new(3, 1);
+        renderer.raytrace(&mut expected, rows.clone(), &mut rng, None, &mut Quiet);
+
+        // Stop halfway, leaving a checkpoint behind
+        let path = temp_path("rows.ckpt");
+        let mut checkpointer = Checkpointer::new(path.clone(), Duration::from_secs(0), 3, false);
+        let mut film = Film::new(WIDTH, HEIGHT);
+        let mut rng = Pcg32::new(3, 1);
+        renderer.raytrace(
+            &mut film,
+            0..3,
+            &mut rng,
+            Some(&mut checkpointer),
+            &mut Quiet,
+        );
+
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(checkpoint.next_row, 3);
+        assert!(!checkpoint.progressive);
+
+        let (mut film, mut rng) = (checkpoint.film, checkpoint.rng);
+        renderer.raytrace(&mut film, 3..rows.end, &mut rng, None, &mut Quiet);
+        assert!(film_bytes(&film) == film_bytes(&expected));
+    }
+
+    #[test]
+    fn progressive_passes_add_one_sample_per_pixel() {
+        let (scene, camera) = (scene(), camera());
+        let renderer = Renderer {
+            scene: &scene,
+            camera: &camera,
+            sampling: AdaptiveSampling::fixed(5),
+        };
+
+        let mut film = Film::new(WIDTH, HEIGHT);
+        let mut passes = Vec::new();
+        renderer.raytrace_progressive(
+            &mut film,
+            &mut Pcg32::new(3, 1),
+            0,
+            None,
+            None,
+            &mut |film, pass| {
+                assert!(film.pixels.iter().all(|p| p.samples == pass));
+                passes.push(pass);
+                true
+            },
+        );
+        assert_eq!(passes, [1, 2, 3, 4, 5]);
+
+        // Stopping early leaves every pixel with the same number of samples
+        let mut film = Film::new(WIDTH, HEIGHT);
+        renderer.raytrace_progressive(
+            &mut film,
+            &mut Pcg32::new(3, 1),
+            0,
+            None,
+            None,
+            &mut |_, pass| pass < 2,
+        );
+        assert!(film.pixels.iter().all(|p| p.samples == 2));
+    }
+
+    #[test]
+    fn progressive_renders_stop_once_pixels_converge() {
+        let (scene, camera) = (scene(), camera());
+        let renderer = Renderer {
+            scene: &scene,
+            camera: &camera,
+            sampling: AdaptiveSampling::new(4, 64, 0.05),
+        };
+
+        let mut film = Film::new(WIDTH, HEIGHT);
+        let mut last = 0;
+        renderer.raytrace_progressive(
+            &mut film,
+            &mut Pcg32::new(3, 1),
+            0,
+            None,
+            None,
+            &mut |_, pass| {
+                last = pass;
+                true
+            },
+        );
+
+        let most = film.pixels.iter().map(|p| p.samples).max().unwrap();
+        assert_eq!(last, most);
+        assert!(film.pixels.iter().all(|p| p.samples >= 4));
+        assert!(film
+            .pixels
+            .iter()
+            .all(|p| renderer.sampling.next_batch(p) == 0));
+    }
+
+    #[test]
+    fn progressive_renders_stop_at_the_deadline() {
+        let (scene, camera) = (scene(), camera());
+        let renderer = Renderer {
+            scene: &scene,
+            camera: &camera,
+            sampling: AdaptiveSampling::new(4, u32::MAX, 0.0),
+        };
+
+        // Far too short to finish even a single pass
+        let mut film = Film::new(WIDTH, HEIGHT);
+        let mut passes = 0;
+        renderer.raytrace_progressive(
+            &mut film,
+            &mut Pcg32::new(3, 1),
+            0,
+            None,
+            Some(Instant::now()),
+            &mut |_, pass| {
+                passes = pass;
+                true
+            },
+        );
+
+        assert_eq!(passes, 0);
+        let rendered = film.pixels.iter().filter(|p| p.samples > 0).count();
+        assert_eq!(rendered, WIDTH as usize);
+        assert!(film.pixels.iter().all(|p| p.samples <= 1));
+    }
+
+    #[test]
+    fn images_are_replaced_in_one_step() {
+        let path = temp_path("image.png");
+        let path = path.to_str().unwrap();
+
+        write_image(path, 2, 1, &[255; 8]).unwrap();
+        write_image(path, 2, 1, &[0; 8]).unwrap();
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+
+        let decoder = png::Decoder::new(File::open(path).unwrap());
+        let (info, mut reader) = decoder.read_info().unwrap();
+        let mut data = vec![1; info.buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(data, [0; 8]);
+    }
+
+    #[test]
+    fn resumed_progressive_renders_are_identical() {
+        let (scene, camera) = (scene(), camera());
+        let renderer = Renderer {
+            scene: &scene,
+            camera: &camera,
+            sampling: AdaptiveSampling::new(4, 32, 0.05),
+        };
+
+        let mut expected = Film::new(WIDTH, HEIGHT);
+        let mut rng = Pcg32::new(3, 1);
+        renderer.raytrace_progressive(&mut expected, &mut rng, 0, None, None, &mut |_, _| true);
+
+        let path = temp_path("passes.ckpt");
+        let mut checkpointer = Checkpointer::new(path.clone(), Duration::from_secs(0), 3, true);
+        let mut film = Film::new(WIDTH, HEIGHT);
+        let mut rng = Pcg32::new(3, 1);
+        renderer.raytrace_progressive(
+            &mut film,
+            &mut rng,
+            0,
+            Some(&mut checkpointer),
+            None,
+            &mut |_, pass| pass < 6,
+        );
+
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(checkpoint.progressive);
+        assert_eq!(checkpoint.passes, 6);
+        assert!(film_bytes(&checkpoint.film) != film_bytes(&expected));
+
+        // Pass numbers carry on from where the first run stopped
+        let (mut film, mut rng) = (checkpoint.film, checkpoint.rng);
+        let mut passes = Vec::new();
+        renderer.raytrace_progressive(
+            &mut film,
+            &mut rng,
+            checkpoint.passes,
+            None,
+            None,
+            &mut |_, pass| {
+                passes.push(pass);
+                true
+            },
+        );
+        assert!(film_bytes(&film) == film_bytes(&expected));
+        assert_eq!(passes.first(), Some(&7));
+        assert_eq!(
+            passes.last(),
+            film.pixels.iter().map(|p| p.samples).max().as_ref()
+        );
+    }
+
+    #[test]
+    fn meshes_scatter_their_vertex_colors_diffusely() {
+        let material = mesh_material();
+        let color = Vec3::new(0.9, 0.5, 0.1);
+        let hit = HitInfo {
+            t: 1.0,
+            p: Vec3::zero(),
+            normal: Vec3::up(),
+            u: 0.0,
+            v: 0.0,
+            color: Some(color),
+            material: &material,
+        };
+        let ray = Ray::new(Vec3::up(), Vec3::down());
+        let mut rng = Pcg32::new(3, 1);
+
+        let samples = 100_000;
+        let (mut albedo, mut cosine) = (Vec3::zero(), 0.0);
+        for _ in 0..samples {
+            if let Some((scattered, weight)) = material.scatter(&ray, &hit, &mut rng) {
+                albedo += weight;
+                cosine += scattered.direction.normalized().y();
+            }
+        }
+        let (albedo, cosine) = (albedo / samples as f32, cosine / samples as f32);
+
+        // A metal would tint its reflections and keep them near the mirror
+        // direction, while cosine weighted bounces average 2/3
+        for i in 0..3 {
+            assert!((albedo.e[i] - color.e[i]).abs() < 0.06, "{:?}", albedo);
+        }
+        assert!((cosine - 2.0 / 3.0).abs() < 0.05, "{}", cosine);
+    }
+}
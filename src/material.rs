@@ -1,105 +1,475 @@
+use std::f32::consts::PI;
+
 use crate::geometry::HitInfo;
+use crate::microfacet::{self, Ggx};
 use crate::ray::Ray;
-use crate::vec3::Vec3;
+use crate::vec3::{Onb, Vec3};
 
 use rand::prelude::*;
 
+/// Describes how light interacts with a surface.
+///
+/// Directions passed to `eval` and `pdf` are unit vectors pointing away
+/// from the surface: `wo` towards the viewer and `wi` towards the light.
+pub trait Material: Send + Sync {
+    /// Samples an incoming direction, returning the scattered ray and the
+    /// throughput weight of the sample, i.e. `eval / pdf`.
+    fn scatter(&self, ray: &Ray, hit: &HitInfo, rng: &mut dyn rand::RngCore)
+        -> Option<(Ray, Vec3)>;
+
+    /// BSDF value for light arriving from `wi` and leaving towards `wo`,
+    /// already multiplied by the cosine of `wi` with the normal.
+    ///
+    /// Materials that can only be sampled through `scatter`, such as perfect
+    /// mirrors, return zero.
+    fn eval(&self, _hit: &HitInfo, _wo: Vec3, _wi: Vec3) -> Vec3 {
+        Vec3::zero()
+    }
+
+    /// Solid angle density with which `scatter` picks `wi` given `wo`.
+    fn pdf(&self, _hit: &HitInfo, _wo: Vec3, _wi: Vec3) -> f32 {
+        0.0
+    }
+
+    /// Light emitted by the surface towards the viewer.
+    fn emitted(&self, _hit: &HitInfo) -> Vec3 {
+        Vec3::zero()
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
-pub enum Material {
-    Lambertian { albedo: Vec3 },
-    Metal { albedo: Vec3, fuzz: f32 },
-    Dielectric { ref_idx: f32 },
+pub struct Lambertian {
+    pub albedo: Vec3,
 }
 
-impl Material {
-    fn scatter_lambertian(
-        albedo: Vec3,
-        _: &Ray,
+impl Material for Lambertian {
+    fn scatter(
+        &self,
+        ray: &Ray,
         hit: &HitInfo,
-        rng: &mut rand::RngCore,
+        rng: &mut dyn rand::RngCore,
     ) -> Option<(Ray, Vec3)> {
-        let target = hit.p + hit.normal + Vec3::random_in_unit_sphere(rng);
-        let scatter = Ray::new(hit.p, target - hit.p);
-        let attenuation = albedo;
+        let onb = Onb::from_w(facing_normal(hit.normal, -ray.direction));
+        let direction = onb.to_world(Vec3::random_cosine_direction(rng));
+
+        // The cosine in the BSDF cancels out with the sampling density
+        Some((Ray::new(hit.p, direction), self.albedo))
+    }
+
+    fn eval(&self, hit: &HitInfo, wo: Vec3, wi: Vec3) -> Vec3 {
+        let cosine = wi.dot(facing_normal(hit.normal, wo));
+
+        if cosine > 0.0 {
+            self.albedo * cosine / PI
+        } else {
+            Vec3::zero()
+        }
+    }
 
-        Some((scatter, attenuation))
+    fn pdf(&self, hit: &HitInfo, wo: Vec3, wi: Vec3) -> f32 {
+        wi.dot(facing_normal(hit.normal, wo)).max(0.0) / PI
     }
+}
 
-    fn scatter_metal(
-        albedo: Vec3,
+#[derive(Clone, Copy, Debug)]
+pub struct Metal {
+    pub albedo: Vec3,
+    pub fuzz: f32,
+}
+
+impl Material for Metal {
+    fn scatter(
+        &self,
         ray: &Ray,
         hit: &HitInfo,
-        fuzz: f32,
-        rng: &mut rand::RngCore,
+        rng: &mut dyn rand::RngCore,
     ) -> Option<(Ray, Vec3)> {
         let reflected = ray.direction.normalized().reflect(hit.normal);
-        let scatter = Ray::new(hit.p, reflected + (fuzz * Vec3::random_in_unit_sphere(rng)));
-        let attenuation = albedo;
+        let scatter = Ray::new(
+            hit.p,
+            reflected + (self.fuzz * Vec3::random_in_unit_sphere(rng)),
+        );
+        let attenuation = self.albedo;
         if scatter.direction.dot(hit.normal) > 0.0 {
             Some((scatter, attenuation))
         } else {
             None
         }
     }
+}
+
+/// Smooth interface between the medium outside the object, of index
+/// `outside_idx`, and the object's interior, of index `ref_idx`.
+///
+/// Light travelling inside the object is attenuated following the
+/// Beer-Lambert law with the per-unit-distance `absorption` coefficients.
+#[derive(Clone, Copy, Debug)]
+pub struct Dielectric {
+    pub ref_idx: f32,
+    pub outside_idx: f32,
+    pub absorption: Vec3,
+}
+
+impl Dielectric {
+    /// Clear object surrounded by air.
+    pub fn new(ref_idx: f32) -> Dielectric {
+        Dielectric {
+            ref_idx,
+            outside_idx: 1.0,
+            absorption: Vec3::zero(),
+        }
+    }
 
-    fn scatter_dielectric(
+    /// Tinted object surrounded by air, which lets through `color` of the
+    /// light after it travels `distance` units inside it.
+    pub fn tinted(ref_idx: f32, color: Vec3, distance: f32) -> Dielectric {
+        let absorption = |c: f32| -c.max(1e-6).ln() / distance;
+
+        Dielectric {
+            ref_idx,
+            outside_idx: 1.0,
+            absorption: Vec3::new(
+                absorption(color.r()),
+                absorption(color.g()),
+                absorption(color.b()),
+            ),
+        }
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(
+        &self,
         ray: &Ray,
         hit: &HitInfo,
-        ref_idx: f32,
-        rng: &mut rand::RngCore,
+        rng: &mut dyn rand::RngCore,
     ) -> Option<(Ray, Vec3)> {
-        let reflected = ray.direction.normalized().reflect(hit.normal);
-        let attenuation = Vec3::new(1.0, 1.0, 1.0);
-
-        let outward_normal: Vec3;
-        let cosine: f32;
-        let ni_over_nt: f32;
+        let wo = -ray.direction.normalized();
+        let entering = wo.dot(hit.normal) >= 0.0;
 
-        if ray.direction.dot(hit.normal) > 0.0 {
-            outward_normal = -hit.normal;
-            ni_over_nt = ref_idx;
-            cosine = ref_idx * ray.direction.dot(hit.normal) / ray.direction.lenght();
+        let (normal, eta, attenuation) = if entering {
+            (
+                hit.normal,
+                self.ref_idx / self.outside_idx,
+                Vec3::new(1.0, 1.0, 1.0),
+            )
         } else {
-            outward_normal = hit.normal;
-            ni_over_nt = 1.0 / ref_idx;
-            cosine = -ray.direction.dot(hit.normal) / ray.direction.lenght();
-        }
+            // The ray travelled through the interior to get here
+            let distance = hit.t * ray.direction.lenght();
+            let transmittance = Vec3::new(
+                (-self.absorption.r() * distance).exp(),
+                (-self.absorption.g() * distance).exp(),
+                (-self.absorption.b() * distance).exp(),
+            );
 
-        let reflect_prob: f32;
+            (-hit.normal, self.outside_idx / self.ref_idx, transmittance)
+        };
 
-        if let Some(refracted) = ray.direction.refract(&outward_normal, ni_over_nt) {
-            reflect_prob = schlick(cosine, ref_idx);
+        let reflect_prob = microfacet::fresnel_dielectric(wo.dot(normal), eta);
 
-            if reflect_prob < rng.gen() {
-                Some((Ray::new(hit.p, refracted), attenuation))
-            } else {
-                Some((Ray::new(hit.p, reflected), attenuation))
+        if rng.gen::<f32>() >= reflect_prob {
+            if let Some(refracted) = microfacet::refract(wo, normal, eta) {
+                return Some((Ray::new(hit.p, refracted), attenuation));
             }
-        } else {
-            Some((Ray::new(hit.p, reflected), attenuation))
         }
+
+        Some((Ray::new(hit.p, (-wo).reflect(normal)), attenuation))
+    }
+}
+
+/// GGX microfacet conductor, with the reflectance at normal incidence given
+/// by `albedo`.
+#[derive(Clone, Copy, Debug)]
+pub struct RoughConductor {
+    pub albedo: Vec3,
+    pub roughness: f32,
+}
+
+impl RoughConductor {
+    fn eval_pdf(&self, hit: &HitInfo, wo: Vec3, wi: Vec3) -> (Vec3, f32) {
+        let frame = Onb::from_w(facing_normal(hit.normal, wo));
+        let ggx = Ggx::from_roughness(self.roughness);
+        let wo = frame.to_local(wo);
+
+        let (value, pdf, m) = microfacet::rough_reflection(&ggx, wo, frame.to_local(wi));
+
+        (
+            microfacet::fresnel_schlick(wo.dot(m), self.albedo) * value,
+            pdf,
+        )
+    }
+}
+
+impl Material for RoughConductor {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitInfo,
+        rng: &mut dyn rand::RngCore,
+    ) -> Option<(Ray, Vec3)> {
+        let wo = -ray.direction.normalized();
+        let frame = Onb::from_w(facing_normal(hit.normal, wo));
+        let wo = frame.to_local(wo);
+        let ggx = Ggx::from_roughness(self.roughness);
+
+        let m = ggx.sample_visible_normal(wo, rng.gen(), rng.gen());
+        let wi = (-wo).reflect(m);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+
+        let attenuation =
+            microfacet::fresnel_schlick(wo.dot(m), self.albedo) * ggx.g(wo, wi) / ggx.g1(wo);
+
+        Some((Ray::new(hit.p, frame.to_world(wi)), attenuation))
     }
 
-    pub fn scatter(
+    fn eval(&self, hit: &HitInfo, wo: Vec3, wi: Vec3) -> Vec3 {
+        self.eval_pdf(hit, wo, wi).0
+    }
+
+    fn pdf(&self, hit: &HitInfo, wo: Vec3, wi: Vec3) -> f32 {
+        self.eval_pdf(hit, wo, wi).1
+    }
+}
+
+/// GGX microfacet interface between air and a dielectric of index
+/// `ref_idx`, both reflecting and refracting.
+#[derive(Clone, Copy, Debug)]
+pub struct RoughDielectric {
+    pub ref_idx: f32,
+    pub roughness: f32,
+}
+
+impl RoughDielectric {
+    fn eval_pdf(&self, hit: &HitInfo, wo: Vec3, wi: Vec3) -> (Vec3, f32) {
+        let (normal, eta) = dielectric_side(hit.normal, wo, self.ref_idx);
+        let frame = Onb::from_w(normal);
+        let ggx = Ggx::from_roughness(self.roughness);
+
+        let (value, pdf) =
+            microfacet::rough_dielectric(&ggx, frame.to_local(wo), frame.to_local(wi), eta);
+
+        (Vec3::new(value, value, value), pdf)
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
         &self,
         ray: &Ray,
         hit: &HitInfo,
-        rng: &mut rand::RngCore,
+        rng: &mut dyn rand::RngCore,
     ) -> Option<(Ray, Vec3)> {
-        match *self {
-            Material::Lambertian { albedo } => Material::scatter_lambertian(albedo, ray, hit, rng),
-            Material::Metal { albedo, fuzz } => {
-                Material::scatter_metal(albedo, ray, hit, fuzz, rng)
+        let wo = -ray.direction.normalized();
+        let (normal, eta) = dielectric_side(hit.normal, wo, self.ref_idx);
+        let frame = Onb::from_w(normal);
+        let wo = frame.to_local(wo);
+        let ggx = Ggx::from_roughness(self.roughness);
+
+        let wi = microfacet::sample_rough_dielectric(&ggx, wo, eta, rng)?;
+        let attenuation = Vec3::new(1.0, 1.0, 1.0) * ggx.g(wo, wi) / ggx.g1(wo);
+
+        Some((Ray::new(hit.p, frame.to_world(wi)), attenuation))
+    }
+
+    fn eval(&self, hit: &HitInfo, wo: Vec3, wi: Vec3) -> Vec3 {
+        self.eval_pdf(hit, wo, wi).0
+    }
+
+    fn pdf(&self, hit: &HitInfo, wo: Vec3, wi: Vec3) -> f32 {
+        self.eval_pdf(hit, wo, wi).1
+    }
+}
+
+/// Flips `normal` so it lies on the same side as `wo`.
+fn facing_normal(normal: Vec3, wo: Vec3) -> Vec3 {
+    if wo.dot(normal) < 0.0 {
+        -normal
+    } else {
+        normal
+    }
+}
+
+/// Normal on the side of `wo` and the relative index of refraction of the
+/// other side, for a dielectric whose outward normal is `normal`.
+fn dielectric_side(normal: Vec3, wo: Vec3, ref_idx: f32) -> (Vec3, f32) {
+    if wo.dot(normal) >= 0.0 {
+        (normal, ref_idx)
+    } else {
+        (-normal, 1.0 / ref_idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::rng::Pcg32;
+
+    const SAMPLES: usize = 100_000;
+
+    fn hit(material: &dyn Material) -> HitInfo<'_> {
+        HitInfo {
+            t: 1.0,
+            p: Vec3::zero(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            u: 0.0,
+            v: 0.0,
+            color: None,
+            material,
+        }
+    }
+
+    fn rough_materials() -> Vec<Box<dyn Material>> {
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        vec![
+            Box::new(RoughConductor {
+                albedo: white,
+                roughness: 0.5,
+            }),
+            Box::new(RoughConductor {
+                albedo: white,
+                roughness: 0.8,
+            }),
+            Box::new(RoughDielectric {
+                ref_idx: 1.5,
+                roughness: 0.5,
+            }),
+            Box::new(RoughDielectric {
+                ref_idx: 1.5,
+                roughness: 0.8,
+            }),
+        ]
+    }
+
+    fn incoming(cos: f32) -> Ray {
+        let wo = Vec3::new((1.0 - cos * cos).sqrt(), 0.0, cos);
+        Ray::new(wo, -wo)
+    }
+
+    #[test]
+    fn rough_materials_pass_the_white_furnace() {
+        let mut rng = Pcg32::new(1, 0);
+
+        for material in rough_materials() {
+            for &cos in &[1.0f32, 0.5, 0.1] {
+                let ray = incoming(cos);
+                let albedo = (0..SAMPLES)
+                    .filter_map(|_| material.scatter(&ray, &hit(&*material), &mut rng))
+                    .fold(Vec3::zero(), |sum, (_, weight)| sum + weight)
+                    / SAMPLES as f32;
+
+                // Single scattering loses energy on rough surfaces but must
+                // never create any
+                assert!(albedo.x() <= 1.0 + 1e-3, "{} at {}", albedo.x(), cos);
+                assert!(albedo.x() > 0.5, "{} at {}", albedo.x(), cos);
+            }
+        }
+    }
+
+    #[test]
+    fn rough_materials_pdf_matches_scatter_density() {
+        let mut rng = Pcg32::new(2, 0);
+
+        for material in rough_materials() {
+            let ray = incoming(0.6);
+            let hit = hit(&*material);
+            let wo = -ray.direction;
+
+            let mut sampled = 0.0;
+            for _ in 0..SAMPLES {
+                if let Some((scattered, weight)) = material.scatter(&ray, &hit, &mut rng) {
+                    let wi = scattered.direction;
+                    let expected = material.eval(&hit, wo, wi) / material.pdf(&hit, wo, wi);
+
+                    assert!(
+                        (weight.x() - expected.x()).abs() < 1e-2 * expected.x().max(1.0),
+                        "{} != {}",
+                        weight.x(),
+                        expected.x()
+                    );
+                    sampled += weight.x();
+                }
             }
-            Material::Dielectric { ref_idx } => {
-                Material::scatter_dielectric(ray, hit, ref_idx, rng)
+            let sampled = sampled / SAMPLES as f32;
+
+            // Integrating `eval` over uniformly chosen directions only
+            // agrees with the importance sampled estimate if `pdf` really
+            // is the density `scatter` draws from
+            let uniform = (0..SAMPLES)
+                .map(|_| {
+                    let wi = Vec3::random_in_unit_sphere(&mut rng).normalized();
+                    material.eval(&hit, wo, wi).x() * 4.0 * PI
+                })
+                .sum::<f32>()
+                / SAMPLES as f32;
+
+            assert!(
+                (sampled - uniform).abs() < 0.05,
+                "{} != {}",
+                sampled,
+                uniform
+            );
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_splits_energy_between_reflection_and_transmission() {
+        let glass = RoughDielectric {
+            ref_idx: 1.5,
+            roughness: 0.3,
+        };
+        let mut rng = Pcg32::new(3, 0);
+
+        for &cos in &[1.0f32, -1.0, 0.5, -0.5] {
+            let ray = incoming(cos);
+            let (mut reflected, mut transmitted) = (0.0, 0.0);
+            for _ in 0..SAMPLES {
+                if let Some((scattered, weight)) = glass.scatter(&ray, &hit(&glass), &mut rng) {
+                    if scattered.direction.z() * cos > 0.0 {
+                        reflected += weight.x();
+                    } else {
+                        transmitted += weight.x();
+                    }
+                }
             }
+            let (reflected, transmitted) =
+                (reflected / SAMPLES as f32, transmitted / SAMPLES as f32);
+
+            assert!(transmitted > 0.0 && reflected > 0.0, "at {}", cos);
+            assert!(reflected + transmitted <= 1.0 + 1e-3, "at {}", cos);
+            assert!(reflected + transmitted > 0.9, "at {}", cos);
         }
     }
-}
 
-fn schlick(cosine: f32, ref_idx: f32) -> f32 {
-    let r0 = (1.0 - ref_idx) / (1.0 + ref_idx).powf(2.0);
+    #[test]
+    fn dielectric_absorbs_along_interior_path() {
+        let glass = Dielectric::tinted(1.5, Vec3::new(0.5, 0.25, 1.0), 2.0);
+        let mut rng = rand::thread_rng();
+
+        // Exits the unit sphere after travelling 4 units along its interior
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 2.0));
+        let hit = HitInfo {
+            t: 2.0,
+            p: Vec3::new(0.0, 0.0, 4.0),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            u: 0.0,
+            v: 0.0,
+            color: None,
+            material: &glass,
+        };
+
+        let (_, attenuation) = glass.scatter(&ray, &hit, &mut rng).unwrap();
 
-    r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
+        assert!((attenuation.r() - 0.25).abs() < 1e-5);
+        assert!((attenuation.g() - 0.0625).abs() < 1e-5);
+        assert!((attenuation.b() - 1.0).abs() < 1e-5);
+
+        // No absorption happens on the way in
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 8.0), Vec3::new(0.0, 0.0, -1.0));
+        let (_, attenuation) = glass.scatter(&ray, &hit, &mut rng).unwrap();
+
+        assert_eq!(attenuation, Vec3::new(1.0, 1.0, 1.0));
+    }
 }
@@ -0,0 +1,341 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::bvh::{Aabb, Bvh};
+use crate::error::invalid_data;
+use crate::geometry::{HitInfo, Hitable, Interval};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+use crate::{ply, stl};
+
+/// Triangles sharing a list of vertices, as read from a model file.
+///
+/// `normals`, `uvs` and `colors` are either empty or have one entry per
+/// vertex.
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f32, f32)>,
+    pub colors: Vec<Vec3>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl MeshData {
+    /// Loads a PLY or STL file, depending on its extension.
+    pub fn load(path: &Path) -> io::Result<MeshData> {
+        let file = BufReader::new(File::open(path)?);
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("ply") => ply::decode(file),
+            Some(e) if e.eq_ignore_ascii_case("stl") => stl::decode(file),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported mesh format {}", path.display()),
+            )),
+        }
+    }
+
+    /// Checks that all the indices and per-vertex attributes line up.
+    pub fn validate(&self) -> io::Result<()> {
+        let n = self.positions.len();
+        let invalid = |message: &str| Err(invalid_data(message));
+
+        if !self.normals.is_empty() && self.normals.len() != n {
+            return invalid("number of normals does not match the vertices");
+        }
+        if !self.uvs.is_empty() && self.uvs.len() != n {
+            return invalid("number of texture coordinates does not match the vertices");
+        }
+        if !self.colors.is_empty() && self.colors.len() != n {
+            return invalid("number of colors does not match the vertices");
+        }
+        if self.triangles.iter().flatten().any(|&i| i as usize >= n) {
+            return invalid("triangle refers to a missing vertex");
+        }
+
+        Ok(())
+    }
+}
+
+/// Triangle mesh with a bounding volume hierarchy for fast intersection.
+///
+/// Normals are interpolated across triangles when the mesh has them, and
+/// vertex colors are passed to textures through `HitInfo::color`. Without
+/// texture coordinates, `u` and `v` are the barycentric coordinates of the
+/// hit within its triangle.
+pub struct Mesh {
+    pub data: MeshData,
+    pub material: Arc<dyn Material>,
+    bvh: Bvh,
+}
+
+impl Mesh {
+    pub fn new(data: MeshData, material: Arc<dyn Material>) -> io::Result<Mesh> {
+        data.validate()?;
+
+        let bounds: Vec<Aabb> = data
+            .triangles
+            .iter()
+            .map(|t| Aabb::from_points(&t.map(|i| data.positions[i as usize])))
+            .collect();
+
+        Ok(Mesh {
+            bvh: Bvh::build(&bounds),
+            data,
+            material,
+        })
+    }
+
+    pub fn load(path: &Path, material: Arc<dyn Material>) -> io::Result<Mesh> {
+        Mesh::new(MeshData::load(path)?, material)
+    }
+
+    fn vertices(&self, triangle: usize) -> [Vec3; 3] {
+        self.data.triangles[triangle].map(|i| self.data.positions[i as usize])
+    }
+
+    fn hit_info(&self, ray: &Ray, t: f32, triangle: usize, b1: f32, b2: f32) -> HitInfo<'_> {
+        let [i0, i1, i2] = self.data.triangles[triangle].map(|i| i as usize);
+        let b0 = 1.0 - b1 - b2;
+        let interpolate = |a: &[Vec3]| b0 * a[i0] + b1 * a[i1] + b2 * a[i2];
+
+        let normal = if self.data.normals.is_empty() {
+            let [p0, p1, p2] = self.vertices(triangle);
+            (p1 - p0).cross(p2 - p0)
+        } else {
+            interpolate(&self.data.normals)
+        };
+
+        let (u, v) = if self.data.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let uv = |i: usize| self.data.uvs[i];
+            (
+                b0 * uv(i0).0 + b1 * uv(i1).0 + b2 * uv(i2).0,
+                b0 * uv(i0).1 + b1 * uv(i1).1 + b2 * uv(i2).1,
+            )
+        };
+
+        HitInfo {
+            t,
+            p: ray.point_at_parameter(t),
+            normal: normal.normalized(),
+            u,
+            v,
+            color: if self.data.colors.is_empty() {
+                None
+            } else {
+                Some(interpolate(&self.data.colors))
+            },
+            material: &*self.material,
+        }
+    }
+}
+
+impl Hitable for Mesh {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo<'_>> {
+        let mut closest = None;
+
+        self.bvh.traverse(ray, t_min, t_max, |triangle, t_max| {
+            let (t, b1, b2) = intersect_triangle(ray, self.vertices(triangle))?;
+            if t <= t_min || t >= t_max {
+                return None;
+            }
+
+            closest = Some((t, triangle, b1, b2));
+            Some(t)
+        });
+
+        closest.map(|(t, triangle, b1, b2)| self.hit_info(ray, t, triangle, b1, b2))
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    /// Only closed meshes whose triangles wind counterclockwise seen from
+    /// outside enclose a solid. The ray goes in where it meets the front
+    /// of a triangle and out at the back, and crossings that do not
+    /// alternate, like both triangles at a shared edge, count once.
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        let mut crossings = Vec::new();
+        self.bvh.traverse(ray, f32::MIN, f32::MAX, |triangle, _| {
+            if let Some((t, b1, b2)) = intersect_triangle(ray, self.vertices(triangle)) {
+                crossings.push((t, triangle, b1, b2));
+            }
+            None
+        });
+        crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut intervals = Vec::new();
+        let mut enter = None;
+        for (t, triangle, b1, b2) in crossings {
+            let [p0, p1, p2] = self.vertices(triangle);
+            let entering = (p1 - p0).cross(p2 - p0).dot(ray.direction) < 0.0;
+            let hit = self.hit_info(ray, t, triangle, b1, b2);
+
+            if entering {
+                enter.get_or_insert(hit);
+            } else if let Some(enter) = enter.take() {
+                intervals.push(Interval { enter, exit: hit });
+            }
+        }
+
+        intervals
+    }
+}
+
+/// Möller-Trumbore ray-triangle intersection, returning the ray parameter
+/// and the barycentric coordinates of the second and third vertex.
+pub fn intersect_triangle(ray: &Ray, [p0, p1, p2]: [Vec3; 3]) -> Option<(f32, f32, f32)> {
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let pvec = ray.direction.cross(edge2);
+    let det = edge1.dot(pvec);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let tvec = ray.origin - p0;
+    let b1 = tvec.dot(pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = tvec.cross(edge1);
+    let b2 = ray.direction.dot(qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    Some((edge2.dot(qvec) * inv_det, b1, b2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::csg::Csg;
+    use crate::geometry::Sphere;
+    use crate::material::Lambertian;
+    use crate::test_util::material;
+
+    fn quad() -> MeshData {
+        MeshData {
+            positions: vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            colors: vec![
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(0.0, 0.0, 1.0),
+            ],
+            triangles: vec![[0, 1, 2], [0, 2, 3]],
+            ..MeshData::default()
+        }
+    }
+
+    #[test]
+    fn hits_interpolate_vertex_attributes() {
+        let material = Arc::new(Lambertian {
+            albedo: Vec3::new(0.5, 0.5, 0.5),
+        });
+        let mesh = Mesh::new(quad(), material).unwrap();
+
+        let ray = Ray::new(Vec3::new(0.75, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = mesh.hit(&ray, 0.001, f32::MAX).unwrap();
+
+        assert!((hit.t - 1.0).abs() < 1e-6);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).lenght() < 1e-6);
+
+        // Halfway between the first two vertices, a quarter of the way up
+        let color = hit.color.unwrap();
+        assert!((color - Vec3::new(0.25, 0.5, 0.25)).lenght() < 1e-5);
+
+        let outside = Ray::new(Vec3::new(1.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(mesh.hit(&outside, 0.001, f32::MAX).is_none());
+        assert!(mesh.hit(&ray, 0.001, 0.5).is_none());
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        let mut data = quad();
+        data.triangles.push([1, 2, 4]);
+        assert!(data.validate().is_err());
+    }
+    /// The cube from -1 to 1, with each face wound to face outwards.
+    fn cube() -> MeshData {
+        let mut data = MeshData::default();
+        for axis in 0..3 {
+            for &side in &[-1.0, 1.0] {
+                let corner = |a: f32, b: f32| {
+                    let mut e = [0.0; 3];
+                    e[axis] = side;
+                    e[(axis + 1) % 3] = a;
+                    e[(axis + 2) % 3] = b;
+                    Vec3::new(e[0], e[1], e[2])
+                };
+                let (a, b, c, d) = (
+                    corner(-1.0, -1.0),
+                    corner(1.0, -1.0),
+                    corner(1.0, 1.0),
+                    corner(-1.0, 1.0),
+                );
+                let first = data.positions.len() as u32;
+                if (b - a).cross(c - a)[axis] * side > 0.0 {
+                    data.positions.extend([a, b, c, d]);
+                } else {
+                    data.positions.extend([a, d, c, b]);
+                }
+                data.triangles.push([first, first + 1, first + 2]);
+                data.triangles.push([first, first + 2, first + 3]);
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn closed_meshes_are_solid() {
+        let cube = Mesh::new(cube(), material()).unwrap();
+
+        let ray = Ray::new(Vec3::new(-3.0, 0.2, -0.4), Vec3::new(1.0, 0.0, 0.0));
+        let intervals = cube.intervals(&ray);
+        assert_eq!(intervals.len(), 1);
+        assert!((intervals[0].enter.t - 2.0).abs() < 1e-5);
+        assert!((intervals[0].exit.t - 4.0).abs() < 1e-5);
+
+        // Through the diagonal edge shared by two triangles of each face
+        let edge = Ray::new(Vec3::new(0.5, 0.5, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(cube.intervals(&edge).len(), 1);
+
+        // Open meshes have nothing inside them
+        let quad = Mesh::new(quad(), material()).unwrap();
+        let through = Ray::new(Vec3::new(0.75, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(quad.intervals(&through).is_empty());
+
+        // A ball with the cube cut out of it, leaving a thin shell in
+        // front of the cube's face
+        let carved = Csg::difference(
+            Box::new(Sphere::new(Vec3::zero(), 1.5, material())),
+            Box::new(cube),
+        );
+        let sphere = carved.hit(&ray, 0.001, f32::MAX).unwrap();
+        assert!(
+            (sphere.t - (3.0 - 2.05f32.sqrt())).abs() < 1e-4,
+            "{}",
+            sphere.t
+        );
+        let face = carved.hit(&ray, sphere.t + 0.01, f32::MAX).unwrap();
+        assert!((face.t - 2.0).abs() < 1e-5, "{}", face.t);
+        assert!((face.normal - Vec3::new(1.0, 0.0, 0.0)).lenght() < 1e-5);
+    }
+}
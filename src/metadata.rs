@@ -0,0 +1,93 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::film::Film;
+
+/// Description of how an image was rendered, written next to it as JSON.
+#[derive(Clone, Debug, Default)]
+pub struct RenderMetadata {
+    pub seed: Option<u64>,
+    /// Number of progressive passes, if the image was rendered in passes.
+    pub passes: Option<u32>,
+    pub elapsed: Option<Duration>,
+    pub time_limit: Option<Duration>,
+}
+
+impl RenderMetadata {
+    pub fn to_json(&self, film: &Film) -> String {
+        let samples = film.pixels.iter().map(|p| p.samples);
+        let min = samples.clone().min().unwrap_or(0);
+        let max = samples.clone().max().unwrap_or(0);
+        let total: u64 = samples.map(u64::from).sum();
+        let mean = total as f64 / film.pixels.len().max(1) as f64;
+
+        let mut fields = vec![
+            format!("\"width\": {}", film.width),
+            format!("\"height\": {}", film.height),
+            format!(
+                "\"window\": {{\"x\": {}, \"y\": {}, \"width\": {}, \"height\": {}}}",
+                film.window.x, film.window.y, film.window.width, film.window.height
+            ),
+        ];
+
+        if let Some(seed) = self.seed {
+            fields.push(format!("\"seed\": {}", seed));
+        }
+        if let Some(passes) = self.passes {
+            fields.push(format!("\"passes\": {}", passes));
+        }
+        if let Some(elapsed) = self.elapsed {
+            fields.push(format!("\"elapsed_seconds\": {:.3}", elapsed.as_secs_f64()));
+        }
+        if let Some(limit) = self.time_limit {
+            fields.push(format!(
+                "\"time_limit_seconds\": {:.3}",
+                limit.as_secs_f64()
+            ));
+        }
+
+        fields.push(format!("\"total_samples\": {}", total));
+        fields.push(format!(
+            "\"samples_per_pixel\": {{\"min\": {}, \"max\": {}, \"mean\": {:.3}}}",
+            min, max, mean
+        ));
+
+        format!("{{\n  {}\n}}\n", fields.join(",\n  "))
+    }
+
+    /// Writes the metadata for the image at `image_path` to the same path
+    /// with a `.json` extension.
+    pub fn save(&self, film: &Film, image_path: &Path) -> io::Result<()> {
+        fs::write(image_path.with_extension("json"), self.to_json(film))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn reports_samples_per_pixel() {
+        let mut film = Film::new(2, 1);
+        for _ in 0..3 {
+            film.pixel_mut(0, 0).add_sample(Vec3::zero());
+        }
+        film.pixel_mut(1, 0).add_sample(Vec3::zero());
+
+        let metadata = RenderMetadata {
+            passes: Some(3),
+            time_limit: Some(Duration::from_secs(2)),
+            ..RenderMetadata::default()
+        };
+        let json = metadata.to_json(&film);
+
+        assert!(json.contains("\"passes\": 3"));
+        assert!(json.contains("\"time_limit_seconds\": 2.000"));
+        assert!(json.contains("\"samples_per_pixel\": {\"min\": 1, \"max\": 3, \"mean\": 2.000}"));
+        assert!(!json.contains("seed"));
+    }
+}
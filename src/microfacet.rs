@@ -0,0 +1,321 @@
+use std::f32::consts::PI;
+
+use crate::vec3::Vec3;
+
+use rand::prelude::*;
+
+/// Isotropic GGX (Trowbridge-Reitz) microfacet distribution with the Smith
+/// height-correlated shadowing-masking term.
+///
+/// All directions are expressed in the local shading frame, where the
+/// macrosurface normal is `+z`.
+#[derive(Clone, Copy, Debug)]
+pub struct Ggx {
+    pub alpha: f32,
+}
+
+impl Ggx {
+    /// Uses the perceptual `alpha = roughness^2` remapping found in most
+    /// PBR tools.
+    pub fn from_roughness(roughness: f32) -> Ggx {
+        Ggx {
+            alpha: (roughness * roughness).max(1e-3),
+        }
+    }
+
+    /// Normal distribution function.
+    pub fn d(&self, m: Vec3) -> f32 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let a2 = self.alpha * self.alpha;
+        let cos2 = m.z() * m.z();
+        let denom = cos2 * (a2 - 1.0) + 1.0;
+
+        a2 / (PI * denom * denom)
+    }
+
+    pub fn lambda(&self, w: Vec3) -> f32 {
+        let cos2 = w.z() * w.z();
+        if cos2 >= 1.0 {
+            return 0.0;
+        }
+
+        let tan2 = (1.0 - cos2) / cos2;
+        0.5 * (-1.0 + (1.0 + self.alpha * self.alpha * tan2).sqrt())
+    }
+
+    /// Masking of a single direction.
+    pub fn g1(&self, w: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Joint shadowing-masking of the two directions.
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Distribution of normals visible from `wo`.
+    pub fn d_visible(&self, wo: Vec3, m: Vec3) -> f32 {
+        let cos_o = wo.z().abs();
+        if cos_o == 0.0 {
+            return 0.0;
+        }
+
+        self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / cos_o
+    }
+
+    /// Samples a microfacet normal from the distribution of normals visible
+    /// from `wo`, following Heitz's "Sampling the GGX Distribution of
+    /// Visible Normals" (2018). `wo` must be in the upper hemisphere.
+    pub fn sample_visible_normal(&self, wo: Vec3, u1: f32, u2: f32) -> Vec3 {
+        let vh = Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()).normalized();
+
+        let lensq = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if lensq > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / lensq.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(t1);
+
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        Vec3::new(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(1e-6)).normalized()
+    }
+}
+
+/// Schlick's approximation for conductors, parameterized directly by the
+/// reflectance at normal incidence.
+pub fn fresnel_schlick(cos: f32, f0: Vec3) -> Vec3 {
+    f0 + (Vec3::new(1.0, 1.0, 1.0) - f0) * (1.0 - cos).max(0.0).powi(5)
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric interface.
+///
+/// `cos_i` is the cosine between the incident direction and the normal on
+/// the incident side, and `eta` is the ratio of the index of refraction on
+/// the transmitted side over the one on the incident side.
+pub fn fresnel_dielectric(cos_i: f32, eta: f32) -> f32 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_s = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let r_p = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+
+    0.5 * (r_s * r_s + r_p * r_p)
+}
+
+/// Refracts `wo` through the microfacet with normal `m`, where `eta` is the
+/// relative index of refraction on the far side. Both `wo` and the result
+/// point away from the surface.
+pub fn refract(wo: Vec3, m: Vec3, eta: f32) -> Option<Vec3> {
+    let cos_o = wo.dot(m);
+    let sin2_t = (1.0 - cos_o * cos_o) / (eta * eta);
+
+    if sin2_t >= 1.0 {
+        return None;
+    }
+
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-wo / eta + (cos_o / eta - cos_t) * m)
+}
+
+/// Half vector of a refraction from `wo` to `wi` together with the Jacobian
+/// `|dwi / dm|` of the mapping, or `None` when the pair can't be connected
+/// by a microfacet facing `wo`.
+pub fn refraction_half_vector(wo: Vec3, wi: Vec3, eta: f32) -> Option<(Vec3, f32)> {
+    // Generalized half vector for refraction (Walter et al. 2007)
+    let mut m = (wo + eta * wi).normalized();
+    if m.z() < 0.0 {
+        m = -m;
+    }
+
+    let cos_o = wo.dot(m);
+    let cos_i = wi.dot(m);
+    if cos_o <= 0.0 || cos_i >= 0.0 {
+        return None;
+    }
+
+    let denom = cos_o + eta * cos_i;
+    Some((m, eta * eta * cos_i.abs() / (denom * denom)))
+}
+
+/// Samples the transmission through a rough dielectric interface, ignoring
+/// the reflected part. `wo` must be in the upper hemisphere.
+pub fn sample_rough_transmission(ggx: &Ggx, wo: Vec3, eta: f32, u1: f32, u2: f32) -> Option<Vec3> {
+    let m = ggx.sample_visible_normal(wo, u1, u2);
+
+    match refract(wo, m, eta) {
+        Some(refracted) if refracted.z() < 0.0 => Some(refracted),
+        _ => None,
+    }
+}
+
+/// Cosine weighted value and pdf of the transmitted lobe of a rough
+/// dielectric, as sampled by `sample_rough_transmission`.
+pub fn rough_transmission(ggx: &Ggx, wo: Vec3, wi: Vec3, eta: f32) -> (f32, f32) {
+    if wo.z() <= 0.0 || wi.z() >= 0.0 {
+        return (0.0, 0.0);
+    }
+
+    match refraction_half_vector(wo, wi, eta) {
+        Some((m, jacobian)) => {
+            let cos_o = wo.dot(m);
+            let f = fresnel_dielectric(cos_o, eta);
+            let value = (1.0 - f) * ggx.d(m) * ggx.g(wo, wi) * cos_o * jacobian / wo.z();
+            let pdf = ggx.d_visible(wo, m) * jacobian;
+
+            (value, pdf)
+        }
+        None => (0.0, 0.0),
+    }
+}
+
+/// Cosine weighted value and pdf of the reflected lobe of a GGX surface,
+/// without the Fresnel term, as sampled by reflecting `wo` around a visible
+/// normal. Also returns the half vector to evaluate the Fresnel term with.
+pub fn rough_reflection(ggx: &Ggx, wo: Vec3, wi: Vec3) -> (f32, f32, Vec3) {
+    if wo.z() <= 0.0 || wi.z() <= 0.0 {
+        return (0.0, 0.0, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    let m = (wo + wi).normalized();
+    let value = ggx.d(m) * ggx.g(wo, wi) / (4.0 * wo.z());
+    let pdf = ggx.d_visible(wo, m) / (4.0 * wo.dot(m));
+
+    (value, pdf, m)
+}
+
+/// Samples a rough dielectric interface, choosing between reflection and
+/// refraction by the Fresnel term of the sampled microfacet.
+pub fn sample_rough_dielectric(
+    ggx: &Ggx,
+    wo: Vec3,
+    eta: f32,
+    rng: &mut dyn rand::RngCore,
+) -> Option<Vec3> {
+    let m = ggx.sample_visible_normal(wo, rng.gen(), rng.gen());
+    let reflect_prob = fresnel_dielectric(wo.dot(m), eta);
+
+    if rng.gen::<f32>() < reflect_prob {
+        let reflected = (-wo).reflect(m);
+        if reflected.z() <= 0.0 {
+            return None;
+        }
+        Some(reflected)
+    } else {
+        match refract(wo, m, eta) {
+            Some(refracted) if refracted.z() < 0.0 => Some(refracted),
+            _ => None,
+        }
+    }
+}
+
+/// Cosine weighted value and pdf of a rough dielectric interface, as
+/// sampled by `sample_rough_dielectric`.
+pub fn rough_dielectric(ggx: &Ggx, wo: Vec3, wi: Vec3, eta: f32) -> (f32, f32) {
+    if wi.z() > 0.0 {
+        let (value, pdf, m) = rough_reflection(ggx, wo, wi);
+        let f = fresnel_dielectric(wo.dot(m), eta);
+
+        (f * value, f * pdf)
+    } else {
+        let (value, pdf) = rough_transmission(ggx, wo, wi, eta);
+        if value == 0.0 {
+            return (0.0, 0.0);
+        }
+
+        // The value already carries the (1 - F) of the transmission, which
+        // is also the probability of having picked it
+        let m = refraction_half_vector(wo, wi, eta).unwrap().0;
+        let f = fresnel_dielectric(wo.dot(m), eta);
+
+        (value, (1.0 - f) * pdf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ggx_projected_area_is_normalized() {
+        // Integrates D(m) * cos(m) over the hemisphere in spherical
+        // coordinates, which must be one for any roughness
+        for &roughness in [0.1, 0.5, 1.0].iter() {
+            let ggx = Ggx::from_roughness(roughness);
+            let steps = 20000;
+            let mut integral = 0.0;
+
+            for i in 0..steps {
+                let theta = (i as f32 + 0.5) / steps as f32 * PI / 2.0;
+                let m = Vec3::new(theta.sin(), 0.0, theta.cos());
+                integral += ggx.d(m) * theta.cos() * theta.sin() * (PI / 2.0) / steps as f32;
+            }
+            integral *= 2.0 * PI;
+
+            assert!((integral - 1.0).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn visible_normals_face_the_viewer() {
+        let ggx = Ggx::from_roughness(0.7);
+        let wo = Vec3::new(0.8, 0.0, 0.6);
+
+        for i in 0..32 {
+            for j in 0..32 {
+                let m = ggx.sample_visible_normal(wo, i as f32 / 32.0, j as f32 / 32.0);
+
+                assert!((m.lenght() - 1.0).abs() < 1e-4);
+                assert!(m.z() > 0.0);
+                assert!(wo.dot(m) >= -1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn fresnel_at_normal_incidence() {
+        // ((n1 - n2) / (n1 + n2))^2
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-6);
+        assert!((fresnel_dielectric(1.0, 1.333) - 0.020_373).abs() < 1e-5);
+        assert!((fresnel_dielectric(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-6);
+
+        // Glass submerged in water
+        assert!((fresnel_dielectric(1.0, 1.5 / 1.333) - 0.003_475).abs() < 1e-5);
+    }
+
+    #[test]
+    fn fresnel_at_brewster_angle() {
+        // Only the s-polarized half is reflected at Brewster's angle
+        let theta = 1.5f32.atan();
+        let r = fresnel_dielectric(theta.cos(), 1.5);
+
+        assert!((r - 0.147_929 / 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn fresnel_grazing_and_total_internal_reflection() {
+        assert!((fresnel_dielectric(0.0, 1.5) - 1.0).abs() < 1e-6);
+
+        // Critical angle leaving glass is asin(1 / 1.5), about 41.8 degrees
+        let inside = 45f32.to_radians().cos();
+        assert!((fresnel_dielectric(inside, 1.0 / 1.5) - 1.0).abs() < f32::EPSILON);
+
+        let below_critical = 30f32.to_radians().cos();
+        assert!(fresnel_dielectric(below_critical, 1.0 / 1.5) < 0.1);
+    }
+}
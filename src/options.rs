@@ -0,0 +1,543 @@
+use std::io::{self, IsTerminal};
+use std::time::Duration;
+
+use crate::exposure::Exposure;
+use crate::film::CropWindow;
+use crate::stereo::StereoLayout;
+
+pub const USAGE: &str = "\
+Usage: raytracer [options]
+
+Options:
+  -o, --output <file>     Image to write, test.png by default
+  --crop <x,y,w,h>        Only render this region of the frame, in pixels
+                          from the top left corner
+  --save-film <file>      Also save the raw film so it can be merged later
+  --heatmap <file>        Also write an image of the samples per pixel
+  --env <file>            Light the scene with an .hdr or .png environment
+                          map
+  --env-rotation <degrees>
+                          Rotate the environment map about the up axis
+  --env-intensity <scale> Scale the environment map, 1 by default
+  --sky                   Light the scene with a physical daylight sky
+  --sun-elevation <degrees>
+                          Height of the sun above the horizon, 35 by
+                          default
+  --sun-azimuth <degrees> Direction of the sun from +x towards +z, 60 by
+                          default
+  --turbidity <t>         Haziness of the sky from 1 to 10, 3 by default
+  --camera <projection>   Projection to render with: perspective,
+                          orthographic, fisheye or equirectangular.
+                          Scenes with their own camera ignore it
+  --fov <degrees>         Vertical field of view, 25 by default or 180 for
+                          fisheye. Sets the height of orthographic views
+                          at the focus distance
+  --stereo <layout>       Render both eyes into one frame, side-by-side or
+                          top-bottom. Needs the perspective or
+                          equirectangular camera
+  --interocular <distance>
+                          Distance between the eyes, 0.065 by default
+  --aperture-blades <n>   Give the lens a polygonal aperture with n blades
+  --aperture-rotation <degrees>
+                          Rotate the polygonal aperture
+  --aperture-mask <file>  Shape the aperture with an image, where brighter
+                          pixels let more light through
+  --cat-eye <offset>      Clip the aperture towards the frame edges, by
+                          this many lens radii at the top and bottom
+  --exposure <f,shutter,iso>
+                          Expose physically like a camera with these
+                          settings, e.g. 16,1/100,100, treating radiance
+                          as cd/m^2. Also sets the lens aperture
+  --auto-exposure         Pick the exposure from the average brightness of
+                          the image
+  --mesh <file>           Add a .ply or .stl mesh to the scene
+  --gltf <file>           Render a .gltf or .glb scene instead of the
+                          built-in one, through its camera if it has one
+  --pbrt <file>           Render a pbrt-v3 scene instead of the built-in
+                          one, with its camera, film size and sample count
+  --merge <file>...       Merge saved films into one image instead of
+                          rendering
+  --seed <n>              Seed for the scene and the samples, 0 by default
+  --checkpoint <file>     Periodically save the render state to this file
+  --checkpoint-interval <seconds>
+                          Time between checkpoints, 60 by default
+  --resume <file>         Continue the render saved in a checkpoint
+  --progressive           Render the whole frame in passes of one sample
+                          per pixel, updating the output as it goes
+  --snapshot-passes <n>   Only update the output every n passes
+  --snapshot-interval <seconds>
+                          Only update the output this often
+  --time-limit <seconds>  Render progressively until the time is up,
+                          ignoring the sample limit
+  --progress <mode>       How to report progress: bar, lines or quiet.
+                          Defaults to bar on terminals and lines otherwise
+  -q, --quiet             Same as --progress quiet
+  --stats-json <file>     Write render statistics as JSON
+  -h, --help              Show this message
+";
+
+/// How progress is reported while rendering.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProgressMode {
+    /// Progress bar redrawn in place on standard error.
+    Bar,
+    /// Machine readable lines on standard output.
+    Lines,
+    Quiet,
+}
+
+/// Projection of the built-in camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Perspective,
+    Orthographic,
+    Fisheye,
+    Equirectangular,
+}
+
+/// Settings for a single run, parsed from the command line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub output: String,
+    pub crop: Option<CropWindow>,
+    pub save_film: Option<String>,
+    pub heatmap: Option<String>,
+    pub environment: Option<String>,
+    pub environment_rotation: f32,
+    pub environment_intensity: f32,
+    pub sky: bool,
+    pub sun_elevation: f32,
+    pub sun_azimuth: f32,
+    pub turbidity: f32,
+    pub camera: Projection,
+    pub fov: Option<f32>,
+    pub stereo: Option<StereoLayout>,
+    pub interocular: f32,
+    pub aperture_blades: Option<u32>,
+    pub aperture_rotation: f32,
+    pub aperture_mask: Option<String>,
+    pub cat_eye: f32,
+    pub exposure: Option<Exposure>,
+    pub auto_exposure: bool,
+    pub mesh: Option<String>,
+    pub gltf: Option<String>,
+    pub pbrt: Option<String>,
+    pub merge: Vec<String>,
+    pub seed: u64,
+    pub checkpoint: Option<String>,
+    pub checkpoint_interval: Duration,
+    pub resume: Option<String>,
+    pub progressive: bool,
+    pub snapshot_passes: Option<u32>,
+    pub snapshot_interval: Option<Duration>,
+    pub time_limit: Option<Duration>,
+    pub stats_json: Option<String>,
+    pub progress: ProgressMode,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            output: String::from("test.png"),
+            crop: None,
+            save_film: None,
+            heatmap: None,
+            environment: None,
+            environment_rotation: 0.0,
+            environment_intensity: 1.0,
+            sky: false,
+            sun_elevation: 35.0,
+            sun_azimuth: 60.0,
+            turbidity: 3.0,
+            camera: Projection::Perspective,
+            fov: None,
+            stereo: None,
+            interocular: 0.065,
+            aperture_blades: None,
+            aperture_rotation: 0.0,
+            aperture_mask: None,
+            cat_eye: 0.0,
+            exposure: None,
+            auto_exposure: false,
+            mesh: None,
+            gltf: None,
+            pbrt: None,
+            merge: Vec::new(),
+            seed: 0,
+            checkpoint: None,
+            checkpoint_interval: Duration::from_secs(60),
+            resume: None,
+            progressive: false,
+            snapshot_passes: None,
+            snapshot_interval: None,
+            time_limit: None,
+            stats_json: None,
+            progress: if io::stderr().is_terminal() {
+                ProgressMode::Bar
+            } else {
+                ProgressMode::Lines
+            },
+            help: false,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the arguments following the program name.
+    pub fn parse<I>(args: I) -> Result<Options, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut options = Options::default();
+        let mut args = args.into_iter().peekable();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {}", arg))
+            };
+
+            match arg.as_str() {
+                "-o" | "--output" => options.output = value()?,
+                "--crop" => options.crop = Some(parse_crop(&value()?)?),
+                "--save-film" => options.save_film = Some(value()?),
+                "--heatmap" => options.heatmap = Some(value()?),
+                "--env" => options.environment = Some(value()?),
+                "--env-rotation" => options.environment_rotation = parse_float(&arg, &value()?)?,
+                "--env-intensity" => {
+                    options.environment_intensity = parse_non_negative(&arg, &value()?)?
+                }
+                "--sky" => options.sky = true,
+                "--sun-elevation" => {
+                    options.sun_elevation = parse_float(&arg, &value()?)?;
+                    if options.sun_elevation.abs() > 90.0 {
+                        return Err(format!("{} must be between -90 and 90", arg));
+                    }
+                }
+                "--sun-azimuth" => options.sun_azimuth = parse_float(&arg, &value()?)?,
+                "--turbidity" => {
+                    options.turbidity = parse_float(&arg, &value()?)?;
+                    if !(1.0..=10.0).contains(&options.turbidity) {
+                        return Err(format!("{} must be between 1 and 10", arg));
+                    }
+                }
+                "--camera" => {
+                    options.camera = match value()?.as_str() {
+                        "perspective" => Projection::Perspective,
+                        "orthographic" => Projection::Orthographic,
+                        "fisheye" => Projection::Fisheye,
+                        "equirectangular" => Projection::Equirectangular,
+                        camera => return Err(format!("unknown camera {}", camera)),
+                    }
+                }
+                "--fov" => {
+                    let fov = parse_float(&arg, &value()?)?;
+                    if !(fov > 0.0 && fov <= 360.0) {
+                        return Err(format!("{} must be between 0 and 360", arg));
+                    }
+                    options.fov = Some(fov);
+                }
+                "--stereo" => {
+                    options.stereo = match value()?.as_str() {
+                        "side-by-side" => Some(StereoLayout::SideBySide),
+                        "top-bottom" => Some(StereoLayout::TopBottom),
+                        layout => return Err(format!("unknown stereo layout {}", layout)),
+                    }
+                }
+                "--interocular" => options.interocular = parse_non_negative(&arg, &value()?)?,
+                "--aperture-blades" => {
+                    let blades = parse_number(&arg, &value()?)?;
+                    if blades < 3 {
+                        return Err(format!("{} must be at least 3", arg));
+                    }
+                    options.aperture_blades = Some(blades);
+                }
+                "--aperture-rotation" => options.aperture_rotation = parse_float(&arg, &value()?)?,
+                "--aperture-mask" => options.aperture_mask = Some(value()?),
+                "--cat-eye" => options.cat_eye = parse_non_negative(&arg, &value()?)?,
+                "--exposure" => options.exposure = Some(parse_exposure(&value()?)?),
+                "--auto-exposure" => options.auto_exposure = true,
+                "--mesh" => options.mesh = Some(value()?),
+                "--gltf" => options.gltf = Some(value()?),
+                "--pbrt" => options.pbrt = Some(value()?),
+                "--merge" => {
+                    options.merge.push(value()?);
+                    while let Some(path) = args.next_if(|a| !a.starts_with('-')) {
+                        options.merge.push(path);
+                    }
+                }
+                "--seed" => options.seed = parse_number(&arg, &value()?)?,
+                "--checkpoint" => options.checkpoint = Some(value()?),
+                "--checkpoint-interval" => {
+                    options.checkpoint_interval = parse_duration(&arg, &value()?)?
+                }
+                "--resume" => options.resume = Some(value()?),
+                "--progressive" => options.progressive = true,
+                "--snapshot-passes" => {
+                    let passes = parse_number(&arg, &value()?)?;
+                    if passes == 0 {
+                        return Err(format!("invalid value 0 for {}", arg));
+                    }
+                    options.snapshot_passes = Some(passes);
+                }
+                "--snapshot-interval" => {
+                    options.snapshot_interval = Some(parse_duration(&arg, &value()?)?)
+                }
+                "--time-limit" => options.time_limit = Some(parse_duration(&arg, &value()?)?),
+                "--stats-json" => options.stats_json = Some(value()?),
+                "--progress" => {
+                    options.progress = match value()?.as_str() {
+                        "bar" => ProgressMode::Bar,
+                        "lines" => ProgressMode::Lines,
+                        "quiet" => ProgressMode::Quiet,
+                        mode => return Err(format!("unknown progress mode {}", mode)),
+                    }
+                }
+                "-q" | "--quiet" => options.progress = ProgressMode::Quiet,
+                "-h" | "--help" => options.help = true,
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+
+        // Only the fisheye projection can see past the sides
+        if let Some(fov) = options.fov {
+            if fov >= 180.0 && options.camera != Projection::Fisheye {
+                return Err(format!("--fov {} needs --camera fisheye", fov));
+            }
+        }
+
+        if options.gltf.is_some() && options.pbrt.is_some() {
+            return Err(String::from("--gltf and --pbrt cannot be combined"));
+        }
+
+        if options.stereo.is_some()
+            && !matches!(
+                options.camera,
+                Projection::Perspective | Projection::Equirectangular
+            )
+        {
+            return Err(String::from(
+                "--stereo needs the perspective or equirectangular camera",
+            ));
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_number<T>(option: &str, value: &str) -> Result<T, String>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| format!("invalid value {} for {}: {}", value, option, e))
+}
+
+fn parse_float(option: &str, value: &str) -> Result<f32, String> {
+    let number: f32 = parse_number(option, value)?;
+    if !number.is_finite() {
+        return Err(format!("invalid value {} for {}", value, option));
+    }
+
+    Ok(number)
+}
+
+fn parse_non_negative(option: &str, value: &str) -> Result<f32, String> {
+    let number = parse_float(option, value)?;
+    if number < 0.0 {
+        return Err(format!("invalid value {} for {}", value, option));
+    }
+
+    Ok(number)
+}
+
+fn parse_duration(option: &str, value: &str) -> Result<Duration, String> {
+    let seconds: f64 = parse_number(option, value)?;
+    if !(seconds >= 0.0 && seconds.is_finite()) {
+        return Err(format!("invalid value {} for {}", value, option));
+    }
+
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+fn parse_crop(value: &str) -> Result<CropWindow, String> {
+    let numbers = value
+        .split(',')
+        .map(|n| n.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid crop window {}: {}", value, e))?;
+
+    match numbers[..] {
+        [x, y, width, height] if width > 0 && height > 0 => Ok(CropWindow {
+            x,
+            y,
+            width,
+            height,
+        }),
+        _ => Err(format!(
+            "invalid crop window {}, expected x,y,width,height",
+            value
+        )),
+    }
+}
+
+fn parse_exposure(value: &str) -> Result<Exposure, String> {
+    let invalid = || format!("invalid exposure {}, expected f-number,shutter,iso", value);
+
+    // Shutter times are usually written as fractions of a second
+    let number = |n: &str| -> Result<f32, String> {
+        let n = n.trim();
+        let number = match n.split_once('/') {
+            Some((numerator, denominator)) => {
+                numerator.trim().parse::<f32>().map_err(|_| invalid())?
+                    / denominator.trim().parse::<f32>().map_err(|_| invalid())?
+            }
+            None => n.parse().map_err(|_| invalid())?,
+        };
+        if !(number > 0.0 && number.is_finite()) {
+            return Err(invalid());
+        }
+        Ok(number)
+    };
+
+    match value.split(',').collect::<Vec<_>>()[..] {
+        [f_number, shutter, iso] => Ok(Exposure {
+            f_number: number(f_number)?,
+            shutter: number(shutter)?,
+            iso: number(iso)?,
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn parses_render_and_merge_options() {
+        let options = parse(&["--crop", "10,20,30,40", "-o", "a.png"]).unwrap();
+        assert_eq!(options.output, "a.png");
+        assert_eq!(options.heatmap, None);
+        assert_eq!(
+            options.crop,
+            Some(CropWindow {
+                x: 10,
+                y: 20,
+                width: 30,
+                height: 40
+            })
+        );
+
+        let options = parse(&["--merge", "a.film", "b.film", "-o", "c.png"]).unwrap();
+        assert_eq!(options.merge, vec!["a.film", "b.film"]);
+        assert_eq!(options.output, "c.png");
+
+        let options = parse(&["--resume", "a.ckpt", "--checkpoint-interval", "0.5"]).unwrap();
+        assert_eq!(options.resume.as_deref(), Some("a.ckpt"));
+        assert_eq!(options.checkpoint_interval, Duration::from_millis(500));
+
+        let options = parse(&["--heatmap", "samples.png"]).unwrap();
+        assert_eq!(options.heatmap.as_deref(), Some("samples.png"));
+
+        let options = parse(&[
+            "--env",
+            "sky.hdr",
+            "--env-rotation",
+            "-90",
+            "--env-intensity",
+            "2.5",
+        ])
+        .unwrap();
+        assert_eq!(options.environment.as_deref(), Some("sky.hdr"));
+        assert_eq!(options.environment_rotation, -90.0);
+        assert_eq!(options.environment_intensity, 2.5);
+
+        let options = parse(&["--sky", "--sun-elevation", "10", "--turbidity", "6"]).unwrap();
+        assert!(options.sky);
+        assert_eq!(options.sun_elevation, 10.0);
+        assert_eq!(options.sun_azimuth, 60.0);
+        assert_eq!(options.turbidity, 6.0);
+
+        let options = parse(&["--camera", "fisheye", "--fov", "220"]).unwrap();
+        assert_eq!(options.camera, Projection::Fisheye);
+        assert_eq!(options.fov, Some(220.0));
+        assert_eq!(parse(&[]).unwrap().camera, Projection::Perspective);
+
+        let options = parse(&["--stereo", "top-bottom", "--camera", "equirectangular"]).unwrap();
+        assert_eq!(options.stereo, Some(StereoLayout::TopBottom));
+        assert_eq!(options.interocular, 0.065);
+
+        let options = parse(&[
+            "--aperture-blades",
+            "6",
+            "--aperture-rotation",
+            "15",
+            "--cat-eye",
+            "0.5",
+        ])
+        .unwrap();
+        assert_eq!(options.aperture_blades, Some(6));
+        assert_eq!(options.aperture_rotation, 15.0);
+        assert_eq!(options.aperture_mask, None);
+        assert_eq!(options.cat_eye, 0.5);
+
+        let options = parse(&["--exposure", "16,1/100,100", "--auto-exposure"]).unwrap();
+        assert_eq!(
+            options.exposure,
+            Some(Exposure {
+                f_number: 16.0,
+                shutter: 0.01,
+                iso: 100.0
+            })
+        );
+        assert!(options.auto_exposure);
+
+        let options = parse(&["--mesh", "bunny.ply", "--gltf", "room.glb"]).unwrap();
+        assert_eq!(options.mesh.as_deref(), Some("bunny.ply"));
+        assert_eq!(options.gltf.as_deref(), Some("room.glb"));
+
+        let options = parse(&["--pbrt", "scene.pbrt"]).unwrap();
+        assert_eq!(options.pbrt.as_deref(), Some("scene.pbrt"));
+
+        let options = parse(&["--time-limit", "90"]).unwrap();
+        assert_eq!(options.time_limit, Some(Duration::from_secs(90)));
+
+        assert_eq!(parse(&["-q"]).unwrap().progress, ProgressMode::Quiet);
+        let options = parse(&["--progress", "lines"]).unwrap();
+        assert_eq!(options.progress, ProgressMode::Lines);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse(&["--crop", "1,2,3"]).is_err());
+        assert!(parse(&["--crop", "1,2,0,4"]).is_err());
+        assert!(parse(&["--output"]).is_err());
+        assert!(parse(&["--fast"]).is_err());
+        assert!(parse(&["--seed", "-1"]).is_err());
+        assert!(parse(&["--checkpoint-interval", "-1"]).is_err());
+        assert!(parse(&["--snapshot-passes", "0"]).is_err());
+        assert!(parse(&["--progress", "loud"]).is_err());
+        assert!(parse(&["--env-rotation", "NaN"]).is_err());
+        assert!(parse(&["--env-intensity", "-1"]).is_err());
+        assert!(parse(&["--sun-elevation", "91"]).is_err());
+        assert!(parse(&["--turbidity", "0.5"]).is_err());
+        assert!(parse(&["--camera", "pinhole"]).is_err());
+        assert!(parse(&["--fov", "0"]).is_err());
+        assert!(parse(&["--fov", "180"]).is_err());
+        assert!(parse(&["--stereo", "anaglyph"]).is_err());
+        assert!(parse(&["--aperture-blades", "2"]).is_err());
+        assert!(parse(&["--cat-eye", "-0.5"]).is_err());
+        assert!(parse(&["--exposure", "16,1/100"]).is_err());
+        assert!(parse(&["--gltf", "a.glb", "--pbrt", "b.pbrt"]).is_err());
+        assert!(parse(&["--exposure", "16,1/0,100"]).is_err());
+        assert!(parse(&["--stereo", "side-by-side", "--camera", "fisheye"]).is_err());
+    }
+}
@@ -0,0 +1,925 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::camera::{Camera, PerspectiveCamera};
+use crate::environment::{Environment, Gradient};
+use crate::error::invalid_data;
+use crate::geometry::{Hitable, Sphere};
+use crate::light::{DirectionalLight, Light, PointLight, SpotLight};
+use crate::material::{Dielectric, Lambertian, Material, RoughConductor, RoughDielectric};
+use crate::mesh::{Mesh, MeshData};
+use crate::transform::Mat4;
+use crate::vec3::Vec3;
+
+/// Include files nested deeper than this are taken to be a loop of files
+/// including each other.
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// Scene described by a pbrt-v3 file, restricted to what the renderer can
+/// represent.
+///
+/// Supported are `LookAt` and the other transform directives, perspective
+/// `Camera`s, the resolution from `Film`, the sample count from `Sampler`,
+/// `sphere`, `trianglemesh` and `plymesh` shapes, `matte`, `metal` and
+/// `glass` materials (also as named materials), point, spot, distant and
+/// constant infinite lights, and attribute blocks. Everything else is
+/// skipped with an entry in `warnings`.
+pub struct PbrtScene {
+    pub objects: Vec<Box<dyn Hitable>>,
+    pub lights: Vec<Box<dyn Light>>,
+    pub environment: Option<Box<dyn Environment>>,
+    /// Always present after parsing, taken by the renderer.
+    pub camera: Option<Box<dyn Camera>>,
+    pub width: u32,
+    pub height: u32,
+    /// Samples per pixel requested by the `Sampler`.
+    pub samples: Option<u32>,
+    pub warnings: Vec<String>,
+}
+
+impl PbrtScene {
+    pub fn load(path: &Path) -> io::Result<PbrtScene> {
+        let mut parser = Parser::new();
+        parser.parse_file(path, 0)?;
+        Ok(parser.finish())
+    }
+
+    /// Parses a scene description, resolving `Include` and mesh file names
+    /// relative to `dir`.
+    pub fn parse(text: &str, dir: &Path) -> io::Result<PbrtScene> {
+        let mut parser = Parser::new();
+        parser.parse(text, dir, 0)?;
+        Ok(parser.finish())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Number(f64),
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> io::Result<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\n' => {
+                line += 1;
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '#' => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            '[' | ']' => {
+                chars.next();
+                tokens.push((if c == '[' { Token::Open } else { Token::Close }, line));
+            }
+            '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\n') | None => {
+                            return Err(syntax_error(line, "unterminated string"));
+                        }
+                        Some(c) => s.push(c),
+                    }
+                }
+                tokens.push((Token::Str(s), line));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "[]\"#".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+
+                let token = if c.is_ascii_alphabetic() {
+                    Token::Word(word)
+                } else {
+                    Token::Number(
+                        word.parse()
+                            .map_err(|_| syntax_error(line, &format!("invalid number {}", word)))?,
+                    )
+                };
+                tokens.push((token, line));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parameter of a directive, such as `"float radius" [ 2 ]`.
+struct Param {
+    ty: String,
+    name: String,
+    values: Vec<Token>,
+}
+
+struct Params {
+    line: usize,
+    list: Vec<Param>,
+}
+
+impl Params {
+    fn find(&self, name: &str) -> Option<&Param> {
+        self.list.iter().find(|p| p.name == name)
+    }
+
+    fn numbers(&self, name: &str) -> Option<Vec<f32>> {
+        self.find(name)?
+            .values
+            .iter()
+            .map(|v| match v {
+                Token::Number(n) => Some(*n as f32),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn float(&self, name: &str, default: f32) -> f32 {
+        self.numbers(name)
+            .and_then(|v| v.first().copied())
+            .unwrap_or(default)
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        match self.find(name)?.values.first() {
+            Some(Token::Str(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn bool(&self, name: &str, default: bool) -> bool {
+        match self.find(name).and_then(|p| p.values.first()) {
+            Some(Token::Str(s)) | Some(Token::Word(s)) => s == "true",
+            _ => default,
+        }
+    }
+
+    /// Triples of numbers, for points, normals and colors.
+    fn vectors(&self, name: &str) -> Option<Vec<Vec3>> {
+        let values = self.numbers(name)?;
+        Some(
+            values
+                .chunks_exact(3)
+                .map(|v| Vec3::new(v[0], v[1], v[2]))
+                .collect(),
+        )
+    }
+
+    fn point(&self, name: &str, default: Vec3) -> Vec3 {
+        self.vectors(name)
+            .and_then(|v| v.first().copied())
+            .unwrap_or(default)
+    }
+
+    /// RGB color, warning about spectral and blackbody colors, which fall
+    /// back to `default` like textures do.
+    fn color(&self, name: &str, default: Vec3, warnings: &mut Vec<String>) -> Vec3 {
+        let param = match self.find(name) {
+            Some(param) => param,
+            None => return default,
+        };
+
+        match param.ty.as_str() {
+            "rgb" | "color" => self.point(name, default),
+            // Already reported along with other texture parameters
+            "texture" => default,
+            _ => {
+                warnings.push(format!(
+                    "line {}: unsupported {} parameter \"{}\"",
+                    self.line, param.ty, name
+                ));
+                default
+            }
+        }
+    }
+}
+
+/// Attributes saved and restored by `AttributeBegin` and `AttributeEnd`.
+#[derive(Clone)]
+struct GraphicsState {
+    transform: Mat4,
+    material: Arc<dyn Material>,
+}
+
+/// What is known about the camera, which can only be built once the
+/// resolution is known.
+struct CameraSettings {
+    /// Transformation from camera to world space.
+    world_from_camera: Mat4,
+    fov: f32,
+    lens_radius: f32,
+    focal_distance: f32,
+}
+
+struct Parser {
+    state: GraphicsState,
+    attributes: Vec<GraphicsState>,
+    transforms: Vec<Mat4>,
+    named_materials: HashMap<String, Arc<dyn Material>>,
+    coordinate_systems: HashMap<String, Mat4>,
+    camera: CameraSettings,
+    scene: PbrtScene,
+}
+
+impl Parser {
+    fn new() -> Parser {
+        Parser {
+            state: GraphicsState {
+                transform: Mat4::identity(),
+                material: Arc::new(Lambertian {
+                    albedo: Vec3::new(0.5, 0.5, 0.5),
+                }),
+            },
+            attributes: Vec::new(),
+            transforms: Vec::new(),
+            named_materials: HashMap::new(),
+            coordinate_systems: HashMap::new(),
+            camera: CameraSettings {
+                world_from_camera: Mat4::identity(),
+                fov: 90.0,
+                lens_radius: 0.0,
+                focal_distance: 1e6,
+            },
+            scene: PbrtScene {
+                objects: Vec::new(),
+                lights: Vec::new(),
+                environment: None,
+                camera: None,
+                width: 1280,
+                height: 720,
+                samples: None,
+                warnings: Vec::new(),
+            },
+        }
+    }
+
+    fn parse_file(&mut self, path: &Path, depth: usize) -> io::Result<()> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(invalid_data("includes are nested too deep"));
+        }
+
+        let text = fs::read_to_string(path)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        self.parse(&text, dir, depth)
+    }
+
+    fn parse(&mut self, text: &str, dir: &Path, depth: usize) -> io::Result<()> {
+        let tokens = tokenize(text)?;
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let (directive, line) = match &tokens[i] {
+                (Token::Word(word), line) => (word.as_str(), *line),
+                (_, line) => return Err(syntax_error(*line, "expected a directive")),
+            };
+            i += 1;
+
+            // Transforms take bare numbers, everything else an optional
+            // type name and a parameter list
+            let count = match directive {
+                "Translate" | "Scale" => 3,
+                "Rotate" => 4,
+                "LookAt" => 9,
+                "Transform" | "ConcatTransform" => 16,
+                _ => 0,
+            };
+            if count > 0 {
+                let numbers = read_numbers(&tokens, &mut i, count, line)?;
+                self.transform(directive, &numbers);
+                continue;
+            }
+
+            // Unlike parameter declarations such as "float radius", type
+            // names have no spaces. Only `Texture` takes more than one.
+            let mut names = Vec::new();
+            while let Some((Token::Str(s), _)) = tokens.get(i) {
+                if s.contains(char::is_whitespace) {
+                    break;
+                }
+                names.push(s.clone());
+                i += 1;
+            }
+            let name = names.into_iter().next();
+            let params = read_params(&tokens, &mut i, line)?;
+            self.directive(directive, name, &params, dir, depth)?;
+        }
+
+        Ok(())
+    }
+
+    fn transform(&mut self, directive: &str, n: &[f32]) {
+        let v = |i: usize| Vec3::new(n[i], n[i + 1], n[i + 2]);
+        let matrix = match directive {
+            "Translate" => Mat4::translate(v(0)),
+            "Scale" => Mat4::scale(v(0)),
+            "Rotate" => Mat4::rotate(n[0], v(1)),
+            "LookAt" => look_at(v(0), v(3), v(6)),
+            _ => {
+                let mut elements = [0.0; 16];
+                elements.copy_from_slice(n);
+                let matrix = Mat4::from_columns(&elements);
+                if directive == "Transform" {
+                    self.state.transform = matrix;
+                    return;
+                }
+                matrix
+            }
+        };
+
+        self.state.transform = self.state.transform * matrix;
+    }
+
+    fn directive(
+        &mut self,
+        directive: &str,
+        name: Option<String>,
+        params: &Params,
+        dir: &Path,
+        depth: usize,
+    ) -> io::Result<()> {
+        let line = params.line;
+        let name = name.unwrap_or_default();
+
+        match directive {
+            "Identity" => self.state.transform = Mat4::identity(),
+            "CoordinateSystem" => {
+                self.coordinate_systems.insert(name, self.state.transform);
+            }
+            "CoordSysTransform" => match self.coordinate_systems.get(&name) {
+                Some(transform) => self.state.transform = *transform,
+                None => self.warn(line, &format!("unknown coordinate system \"{}\"", name)),
+            },
+            "AttributeBegin" => self.attributes.push(self.state.clone()),
+            "AttributeEnd" => match self.attributes.pop() {
+                Some(state) => self.state = state,
+                None => return Err(syntax_error(line, "unmatched AttributeEnd")),
+            },
+            "TransformBegin" => self.transforms.push(self.state.transform),
+            "TransformEnd" => match self.transforms.pop() {
+                Some(transform) => self.state.transform = transform,
+                None => return Err(syntax_error(line, "unmatched TransformEnd")),
+            },
+            "WorldBegin" => {
+                self.state.transform = Mat4::identity();
+                self.coordinate_systems
+                    .insert("world".to_string(), Mat4::identity());
+            }
+            "WorldEnd" => {}
+            "Camera" => self.camera(&name, params),
+            "Film" => {
+                let resolution = |name: &str, default: u32| {
+                    let value = params.float(name, default as f32);
+                    if value >= 1.0 {
+                        value as u32
+                    } else {
+                        default
+                    }
+                };
+                self.scene.width = resolution("xresolution", 1280);
+                self.scene.height = resolution("yresolution", 720);
+            }
+            "Sampler" => {
+                self.scene.samples = Some(params.float("pixelsamples", 16.0).max(1.0) as u32);
+            }
+            "Material" => {
+                if let Some(material) = self.material(&name, params) {
+                    self.state.material = material;
+                }
+            }
+            "MakeNamedMaterial" => {
+                let ty = params.string("type").unwrap_or("matte").to_string();
+                if let Some(material) = self.material(&ty, params) {
+                    self.named_materials.insert(name, material);
+                }
+            }
+            "NamedMaterial" => match self.named_materials.get(&name) {
+                Some(material) => self.state.material = material.clone(),
+                None => self.warn(line, &format!("unknown material \"{}\"", name)),
+            },
+            "Shape" => self.shape(&name, params, dir)?,
+            "LightSource" => self.light(&name, params),
+            "Include" => {
+                let path = dir.join(&name);
+                self.parse_file(&path, depth + 1)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            }
+            _ => self.warn(line, &format!("unsupported directive {}", directive)),
+        }
+
+        Ok(())
+    }
+
+    fn camera(&mut self, ty: &str, params: &Params) {
+        if ty != "perspective" {
+            self.warn(
+                params.line,
+                &format!("unsupported {} camera, using a perspective one", ty),
+            );
+        }
+
+        let world_from_camera = self.state.transform.inverse();
+        self.coordinate_systems
+            .insert("camera".to_string(), world_from_camera);
+        self.camera = CameraSettings {
+            world_from_camera,
+            fov: params.float("fov", 90.0),
+            lens_radius: params.float("lensradius", 0.0),
+            focal_distance: params.float("focaldistance", 1e6),
+        };
+    }
+
+    fn material(&mut self, ty: &str, params: &Params) -> Option<Arc<dyn Material>> {
+        for param in &params.list {
+            if param.ty == "texture" {
+                self.warn(
+                    params.line,
+                    &format!("textures are not supported, ignoring \"{}\"", param.name),
+                );
+            }
+        }
+
+        let mut warnings = Vec::new();
+        let material: Arc<dyn Material> = match ty {
+            "matte" => Arc::new(Lambertian {
+                albedo: params.color("Kd", Vec3::new(0.5, 0.5, 0.5), &mut warnings),
+            }),
+            "metal" => {
+                // Copper, the default in pbrt
+                let eta = params.color("eta", Vec3::new(0.2004, 0.9240, 1.1022), &mut warnings);
+                let k = params.color("k", Vec3::new(3.9129, 2.4528, 2.1422), &mut warnings);
+                let reflectance = |eta: f32, k: f32| {
+                    ((eta - 1.0).powi(2) + k * k) / ((eta + 1.0).powi(2) + k * k)
+                };
+
+                Arc::new(RoughConductor {
+                    albedo: Vec3::new(
+                        reflectance(eta.x(), k.x()),
+                        reflectance(eta.y(), k.y()),
+                        reflectance(eta.z(), k.z()),
+                    ),
+                    roughness: ggx_roughness(params, roughness(params, 0.01)),
+                })
+            }
+            "glass" => {
+                let eta = params.float("eta", params.float("index", 1.5));
+                // Only nonzero roughness is remapped, zero stays smooth
+                let roughness = roughness(params, 0.0);
+                if roughness > 0.0 {
+                    Arc::new(RoughDielectric {
+                        ref_idx: eta,
+                        roughness: ggx_roughness(params, roughness),
+                    })
+                } else {
+                    Arc::new(Dielectric::new(eta))
+                }
+            }
+            "" | "none" => {
+                self.warn(params.line, "missing material type");
+                return None;
+            }
+            _ => {
+                self.warn(params.line, &format!("unsupported {} material", ty));
+                return None;
+            }
+        };
+
+        self.scene.warnings.append(&mut warnings);
+        Some(material)
+    }
+
+    fn shape(&mut self, ty: &str, params: &Params, dir: &Path) -> io::Result<()> {
+        let transform = self.state.transform;
+        let material = self.state.material.clone();
+
+        let mut data = match ty {
+            "sphere" => {
+                // Uniformly scaled spheres stay spheres
+                let scale = transform.determinant().abs().cbrt();
+                self.scene.objects.push(Box::new(Sphere::new(
+                    transform.transform_point(Vec3::zero()),
+                    params.float("radius", 1.0) * scale,
+                    material,
+                )));
+                return Ok(());
+            }
+            "trianglemesh" => {
+                let positions = params
+                    .vectors("P")
+                    .ok_or_else(|| syntax_error(params.line, "triangle mesh has no \"P\""))?;
+                let indices: Vec<u32> = match params.numbers("indices") {
+                    Some(indices) => indices.iter().map(|&i| i as u32).collect(),
+                    None if positions.len() == 3 => vec![0, 1, 2],
+                    None => return Err(syntax_error(params.line, "triangle mesh has no indices")),
+                };
+                let uvs = params
+                    .numbers("uv")
+                    .or_else(|| params.numbers("st"))
+                    .unwrap_or_default();
+
+                MeshData {
+                    normals: params.vectors("N").unwrap_or_default(),
+                    uvs: uvs.chunks_exact(2).map(|uv| (uv[0], uv[1])).collect(),
+                    triangles: indices
+                        .chunks_exact(3)
+                        .map(|t| [t[0], t[1], t[2]])
+                        .collect(),
+                    positions,
+                    ..MeshData::default()
+                }
+            }
+            "plymesh" => {
+                let filename = params
+                    .string("filename")
+                    .ok_or_else(|| syntax_error(params.line, "PLY mesh has no filename"))?;
+                let path: PathBuf = dir.join(filename);
+                MeshData::load(&path)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?
+            }
+            _ => {
+                self.warn(params.line, &format!("unsupported {} shape", ty));
+                return Ok(());
+            }
+        };
+
+        for p in &mut data.positions {
+            *p = transform.transform_point(*p);
+        }
+        for n in &mut data.normals {
+            *n = transform.transform_normal(*n);
+        }
+        if transform.determinant() < 0.0 {
+            for t in &mut data.triangles {
+                t.swap(1, 2);
+            }
+        }
+
+        let mesh =
+            Mesh::new(data, material).map_err(|e| syntax_error(params.line, &e.to_string()))?;
+        self.scene.objects.push(Box::new(mesh));
+        Ok(())
+    }
+
+    fn light(&mut self, ty: &str, params: &Params) {
+        let transform = self.state.transform;
+        let mut warnings = Vec::new();
+        let scale = params.color("scale", Vec3::new(1.0, 1.0, 1.0), &mut warnings);
+        let from = params.point("from", Vec3::zero());
+        let to = params.point("to", Vec3::new(0.0, 0.0, 1.0));
+
+        let light: Box<dyn Light> = match ty {
+            "point" => Box::new(PointLight {
+                position: transform.transform_point(from),
+                intensity: scale * params.color("I", Vec3::new(1.0, 1.0, 1.0), &mut warnings),
+            }),
+            "spot" => {
+                let cone_angle = params.float("coneangle", 30.0);
+                Box::new(SpotLight {
+                    position: transform.transform_point(from),
+                    direction: transform.transform_vector(to - from).normalized(),
+                    intensity: scale * params.color("I", Vec3::new(1.0, 1.0, 1.0), &mut warnings),
+                    cone_angle,
+                    falloff_start: cone_angle - params.float("conedelta", 5.0),
+                })
+            }
+            "distant" => Box::new(DirectionalLight {
+                direction: transform.transform_vector(to - from).normalized(),
+                irradiance: scale * params.color("L", Vec3::new(1.0, 1.0, 1.0), &mut warnings),
+            }),
+            "infinite" => {
+                if params.string("mapname").is_some() {
+                    self.warn(
+                        params.line,
+                        "environment maps are not supported, using a constant",
+                    );
+                }
+                let radiance = scale * params.color("L", Vec3::new(1.0, 1.0, 1.0), &mut warnings);
+                self.scene.environment = Some(Box::new(Gradient {
+                    horizon: radiance,
+                    zenith: radiance,
+                }));
+                self.scene.warnings.append(&mut warnings);
+                return;
+            }
+            _ => {
+                self.warn(params.line, &format!("unsupported {} light", ty));
+                return;
+            }
+        };
+
+        self.scene.warnings.append(&mut warnings);
+        self.scene.lights.push(light);
+    }
+
+    fn warn(&mut self, line: usize, message: &str) {
+        self.scene
+            .warnings
+            .push(format!("line {}: {}", line, message));
+    }
+
+    fn finish(mut self) -> PbrtScene {
+        let CameraSettings {
+            world_from_camera,
+            fov,
+            lens_radius,
+            focal_distance,
+        } = self.camera;
+        let aspect = self.scene.width as f32 / self.scene.height as f32;
+
+        // The field of view spans the shorter side of the image
+        let fov = if aspect < 1.0 {
+            (2.0 * ((fov.to_radians() / 2.0).tan() / aspect).atan()).to_degrees()
+        } else {
+            fov
+        };
+
+        let origin = world_from_camera.transform_point(Vec3::zero());
+        let forward = world_from_camera.transform_vector(Vec3::new(0.0, 0.0, 1.0));
+        let up = world_from_camera.transform_vector(Vec3::up());
+        // A pinhole is in focus everywhere, and the default focal distance
+        // would make ray directions too long for the intersection epsilon
+        let focal_distance = if lens_radius > 0.0 {
+            focal_distance
+        } else {
+            1.0
+        };
+        let mut camera = PerspectiveCamera::new(
+            origin,
+            origin + forward,
+            up,
+            fov,
+            aspect,
+            2.0 * lens_radius,
+            focal_distance,
+        );
+
+        // pbrt is left-handed, so unless the scene mirrors it the image
+        // would come out flipped horizontally
+        let right = world_from_camera.transform_vector(Vec3::new(1.0, 0.0, 0.0));
+        if right.dot(camera.horizontal) < 0.0 {
+            camera.lower_left_corner += camera.horizontal;
+            camera.horizontal = -camera.horizontal;
+        }
+
+        self.scene.camera = Some(Box::new(camera));
+        self.scene
+    }
+}
+
+/// The `LookAt` transformation from world to camera space, with the camera
+/// looking down its +z axis.
+fn look_at(eye: Vec3, at: Vec3, up: Vec3) -> Mat4 {
+    let dir = (at - eye).normalized();
+    let right = up.normalized().cross(dir).normalized();
+    let up = dir.cross(right);
+
+    let mut camera_to_world = Mat4::identity();
+    for (j, column) in [right, up, dir, eye].iter().enumerate() {
+        for i in 0..3 {
+            camera_to_world.m[i][j] = column[i];
+        }
+    }
+    camera_to_world.inverse()
+}
+
+/// Roughness parameter of the `metal` and `glass` materials, averaged over
+/// the two directions since only isotropic roughness is supported.
+fn roughness(params: &Params, default: f32) -> f32 {
+    let roughness = params.float("roughness", default);
+    (params.float("uroughness", roughness) + params.float("vroughness", roughness)) / 2.0
+}
+
+/// Converts a pbrt roughness to the perceptual roughness `Ggx` squares.
+/// pbrt takes the value as the GGX alpha directly when `remaproughness` is
+/// off, and otherwise maps it with `roughness_to_alpha` first.
+fn ggx_roughness(params: &Params, roughness: f32) -> f32 {
+    let alpha = if params.bool("remaproughness", true) {
+        roughness_to_alpha(roughness)
+    } else {
+        roughness
+    };
+
+    alpha.sqrt()
+}
+
+/// pbrt-v3's `TrowbridgeReitzDistribution::RoughnessToAlpha`, a polynomial
+/// fit in the logarithm of the roughness.
+fn roughness_to_alpha(roughness: f32) -> f32 {
+    let x = roughness.max(1e-3).ln();
+    1.62142 + 0.819955 * x + 0.1734 * x * x + 0.0171201 * x * x * x + 0.000640711 * x * x * x * x
+}
+
+fn read_numbers(
+    tokens: &[(Token, usize)],
+    i: &mut usize,
+    count: usize,
+    line: usize,
+) -> io::Result<Vec<f32>> {
+    let bracketed = tokens.get(*i).is_some_and(|(t, _)| *t == Token::Open);
+    if bracketed {
+        *i += 1;
+    }
+
+    let mut numbers = Vec::with_capacity(count);
+    for _ in 0..count {
+        match tokens.get(*i) {
+            Some((Token::Number(n), _)) => numbers.push(*n as f32),
+            _ => return Err(syntax_error(line, &format!("expected {} numbers", count))),
+        }
+        *i += 1;
+    }
+
+    if bracketed {
+        match tokens.get(*i) {
+            Some((Token::Close, _)) => *i += 1,
+            _ => return Err(syntax_error(line, "expected ]")),
+        }
+    }
+
+    Ok(numbers)
+}
+
+fn read_params(tokens: &[(Token, usize)], i: &mut usize, line: usize) -> io::Result<Params> {
+    let mut list = Vec::new();
+
+    while let Some((Token::Str(declaration), _)) = tokens.get(*i) {
+        let mut words = declaration.split_whitespace();
+        let (ty, name) = match (words.next(), words.next(), words.next()) {
+            (Some(ty), Some(name), None) => (ty.to_string(), name.to_string()),
+            _ => {
+                return Err(syntax_error(
+                    line,
+                    &format!("invalid parameter \"{}\"", declaration),
+                ))
+            }
+        };
+        *i += 1;
+
+        let mut values = Vec::new();
+        match tokens.get(*i) {
+            Some((Token::Open, _)) => {
+                *i += 1;
+                loop {
+                    match tokens.get(*i) {
+                        Some((Token::Close, _)) => break,
+                        Some((Token::Open, _)) | None => {
+                            return Err(syntax_error(line, "unterminated parameter list"))
+                        }
+                        Some((value, _)) => values.push(value.clone()),
+                    }
+                    *i += 1;
+                }
+                *i += 1;
+            }
+            Some((value @ Token::Number(_), _)) | Some((value @ Token::Str(_), _)) => {
+                values.push(value.clone());
+                *i += 1;
+            }
+            _ => {
+                return Err(syntax_error(
+                    line,
+                    &format!("parameter \"{}\" has no value", name),
+                ))
+            }
+        }
+
+        list.push(Param { ty, name, values });
+    }
+
+    Ok(Params { line, list })
+}
+
+fn syntax_error(line: usize, message: &str) -> io::Error {
+    invalid_data(&format!("line {}: {}", line, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ray::Ray;
+
+    const SCENE: &str = r#"
+        LookAt 0 0 -5  0 0 0  0 1 0 # looking down +z
+        Camera "perspective" "float fov" [ 40 ]
+        Film "image" "integer xresolution" [ 200 ] "integer yresolution" 100
+            "string filename" "out.exr"
+        Sampler "halton" "integer pixelsamples" 64
+        Integrator "path" "integer maxdepth" [ 5 ]
+
+        WorldBegin
+        LightSource "point" "rgb I" [ 10 10 10 ] "point from" [ 0 4 0 ]
+        LightSource "infinite" "rgb L" [ .1 .2 .3 ]
+
+        MakeNamedMaterial "gold" "string type" "metal" "float roughness" 0.2
+        AttributeBegin
+            Translate 2 0 0
+            Scale 0.5 0.5 0.5
+            NamedMaterial "gold"
+            Shape "sphere" "float radius" 2
+        AttributeEnd
+
+        Material "matte" "texture Kd" "checks"
+        Shape "trianglemesh" "integer indices" [ 0 1 2 ]
+            "point P" [ -1 -1 3  1 -1 3  0 1 3 ]
+        Shape "disk"
+        WorldEnd
+    "#;
+
+    #[test]
+    fn parses_the_supported_subset() {
+        let mut scene = PbrtScene::parse(SCENE, Path::new("")).unwrap();
+
+        assert_eq!((scene.width, scene.height), (200, 100));
+        assert_eq!(scene.samples, Some(64));
+        assert_eq!(scene.lights.len(), 1);
+        assert!(scene.environment.is_some());
+        assert_eq!(scene.objects.len(), 2);
+
+        // The unit sphere at x = 2, and the triangle behind it
+        let ray = Ray::new(Vec3::new(2.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = scene.objects.hit(&ray, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-4);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = scene.objects.hit(&ray, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 8.0).abs() < 1e-4);
+
+        assert_eq!(scene.warnings.len(), 3, "{:?}", scene.warnings);
+        assert!(scene.warnings[0].starts_with("line 7: unsupported directive Integrator"));
+
+        // pbrt's left-handed camera puts +x on the right when looking down +z
+        let camera = scene.camera.take().unwrap();
+        let mut rng = crate::rng::Pcg32::new(0, 0);
+        let right = camera.get_ray(1.0, 0.5, &mut rng).unwrap();
+        assert!(right.direction.x() > 0.0);
+        assert!(right.direction.y().abs() < 1e-4);
+    }
+
+    #[test]
+    fn remaps_roughness_like_pbrt() {
+        // Values from pbrt-v3's RoughnessToAlpha
+        assert!((roughness_to_alpha(0.2) - 0.6838).abs() < 1e-3);
+        assert!((roughness_to_alpha(0.01) - 0.1389).abs() < 1e-3);
+        assert_eq!(roughness_to_alpha(0.0), roughness_to_alpha(1e-3));
+
+        let params = |remap: &str| Params {
+            line: 1,
+            list: vec![
+                Param {
+                    ty: "float".to_string(),
+                    name: "roughness".to_string(),
+                    values: vec![Token::Number(0.2)],
+                },
+                Param {
+                    ty: "bool".to_string(),
+                    name: "remaproughness".to_string(),
+                    values: vec![Token::Str(remap.to_string())],
+                },
+            ],
+        };
+
+        // Ggx squares the result to get back to alpha
+        let alpha = |params: &Params| ggx_roughness(params, roughness(params, 0.0)).powi(2);
+        assert!((alpha(&params("true")) - 0.6838).abs() < 1e-3);
+        assert!((alpha(&params("false")) - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn films_default_to_pbrt_resolution() {
+        let scene =
+            PbrtScene::parse("Film \"image\"\nWorldBegin\nWorldEnd\n", Path::new("")).unwrap();
+        assert_eq!((scene.width, scene.height), (1280, 720));
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_line_numbers() {
+        let error = PbrtScene::parse("WorldBegin\nTranslate 1 2\n", Path::new(""))
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "line 2: expected 3 numbers");
+
+        assert!(PbrtScene::parse("Shape \"sphere\" \"float radius\" [ 1", Path::new("")).is_err());
+        assert!(PbrtScene::parse("AttributeEnd", Path::new("")).is_err());
+    }
+}
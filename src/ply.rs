@@ -0,0 +1,407 @@
+use std::io::{self, BufRead, Read};
+use std::str::SplitAsciiWhitespace;
+
+use crate::error::invalid_data;
+use crate::mesh::MeshData;
+use crate::texture::srgb_to_linear;
+use crate::vec3::Vec3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> io::Result<Scalar> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(invalid_data(&format!("unknown property type {}", name))),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Property {
+    name: String,
+    ty: Scalar,
+    /// Type of the length prefix for list properties.
+    count: Option<Scalar>,
+}
+
+#[derive(Clone, Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Source of property values in either of the encodings.
+enum Values<'a, R> {
+    Ascii(SplitAsciiWhitespace<'a>),
+    Binary { reader: R, big_endian: bool },
+}
+
+impl<'a, R: Read> Values<'a, R> {
+    fn read(&mut self, ty: Scalar) -> io::Result<f64> {
+        match self {
+            Values::Ascii(tokens) => {
+                let token = tokens
+                    .next()
+                    .ok_or_else(|| invalid_data("unexpected end of data"))?;
+                token
+                    .parse()
+                    .map_err(|_| invalid_data(&format!("invalid number {}", token)))
+            }
+            Values::Binary { reader, big_endian } => {
+                let mut buf = [0; 8];
+                let bytes = &mut buf[..ty.size()];
+                reader.read_exact(bytes)?;
+                if !*big_endian {
+                    bytes.reverse();
+                }
+
+                // The bytes are now big endian, at the start of the buffer
+                let b4 = [buf[0], buf[1], buf[2], buf[3]];
+                Ok(match ty {
+                    Scalar::I8 => f64::from(buf[0] as i8),
+                    Scalar::U8 => f64::from(buf[0]),
+                    Scalar::I16 => f64::from(i16::from_be_bytes([buf[0], buf[1]])),
+                    Scalar::U16 => f64::from(u16::from_be_bytes([buf[0], buf[1]])),
+                    Scalar::I32 => f64::from(i32::from_be_bytes(b4)),
+                    Scalar::U32 => f64::from(u32::from_be_bytes(b4)),
+                    Scalar::F32 => f64::from(f32::from_be_bytes(b4)),
+                    Scalar::F64 => f64::from_be_bytes(buf),
+                })
+            }
+        }
+    }
+}
+
+/// Decodes a Stanford PLY mesh in any of its three encodings.
+///
+/// Vertex positions, normals and colors are read from the `vertex`
+/// element and polygons from the `vertex_indices` list of the `face`
+/// element, which are split into triangle fans. Integer colors are taken
+/// to be sRGB encoded and floating point ones to be linear. Other elements
+/// and properties are skipped.
+pub fn decode<R: BufRead>(mut reader: R) -> io::Result<MeshData> {
+    let (format, elements) = read_header(&mut reader)?;
+
+    let mut body = String::new();
+    let mut values = match format {
+        Format::Ascii => {
+            reader.read_to_string(&mut body)?;
+            Values::Ascii(body.split_ascii_whitespace())
+        }
+        Format::BinaryLittleEndian | Format::BinaryBigEndian => Values::Binary {
+            reader,
+            big_endian: format == Format::BinaryBigEndian,
+        },
+    };
+
+    let mut mesh = MeshData::default();
+
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => read_vertices(&mut values, element, &mut mesh)?,
+            "face" => read_faces(&mut values, element, &mut mesh)?,
+            _ => {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        read_property(&mut values, property)?;
+                    }
+                }
+            }
+        }
+    }
+
+    mesh.validate()?;
+    Ok(mesh)
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> io::Result<(Format, Vec<Element>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim_end() != "ply" {
+        return Err(invalid_data("missing PLY signature"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("unexpected end of header"));
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["format", f, "1.0"] => {
+                format = Some(match *f {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(invalid_data(&format!("unknown format {}", f))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid_data("invalid element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, ty, name] => {
+                let property = Property {
+                    name: name.to_string(),
+                    ty: Scalar::parse(ty)?,
+                    count: Some(Scalar::parse(count)?),
+                };
+                add_property(&mut elements, property)?;
+            }
+            ["property", ty, name] => {
+                let property = Property {
+                    name: name.to_string(),
+                    ty: Scalar::parse(ty)?,
+                    count: None,
+                };
+                add_property(&mut elements, property)?;
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => {
+                return Err(invalid_data(&format!(
+                    "invalid header line {}",
+                    line.trim()
+                )))
+            }
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid_data("missing format"))?;
+    Ok((format, elements))
+}
+
+fn add_property(elements: &mut [Element], property: Property) -> io::Result<()> {
+    elements
+        .last_mut()
+        .ok_or_else(|| invalid_data("property outside of an element"))?
+        .properties
+        .push(property);
+    Ok(())
+}
+
+/// Reads a property, returning all of its values for lists.
+fn read_property<R: Read>(values: &mut Values<R>, property: &Property) -> io::Result<Vec<f64>> {
+    match property.count {
+        None => Ok(vec![values.read(property.ty)?]),
+        Some(count_type) => {
+            let count = values.read(count_type)?;
+            if count < 0.0 {
+                return Err(invalid_data("negative list length"));
+            }
+            (0..count as usize)
+                .map(|_| values.read(property.ty))
+                .collect()
+        }
+    }
+}
+
+fn read_vertices<R: Read>(
+    values: &mut Values<R>,
+    element: &Element,
+    mesh: &mut MeshData,
+) -> io::Result<()> {
+    let find = |names: &[&str]| {
+        element
+            .properties
+            .iter()
+            .position(|p| p.count.is_none() && names.contains(&p.name.as_str()))
+    };
+
+    let position = [find(&["x"]), find(&["y"]), find(&["z"])];
+    let normal = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+    let color = [
+        find(&["red", "r", "diffuse_red"]),
+        find(&["green", "g", "diffuse_green"]),
+        find(&["blue", "b", "diffuse_blue"]),
+    ];
+
+    if position.iter().any(Option::is_none) {
+        return Err(invalid_data("vertices without positions"));
+    }
+    let has_normals = normal.iter().all(Option::is_some);
+    let has_colors = color.iter().all(Option::is_some);
+
+    let decode_color = |ty: Scalar, c: f64| match ty {
+        Scalar::U8 => srgb_to_linear(c as f32 / 255.0),
+        Scalar::U16 => srgb_to_linear(c as f32 / 65535.0),
+        _ => c as f32,
+    };
+
+    let mut row = vec![0.0; element.properties.len()];
+    for _ in 0..element.count {
+        for (value, property) in row.iter_mut().zip(&element.properties) {
+            // Lists are skipped, as nothing in them is used
+            *value = read_property(values, property)?
+                .first()
+                .copied()
+                .unwrap_or(0.0);
+        }
+
+        let vector = |indices: [Option<usize>; 3], f: &dyn Fn(usize, f64) -> f32| {
+            let [a, b, c] = indices.map(|i| i.unwrap());
+            Vec3::new(f(a, row[a]), f(b, row[b]), f(c, row[c]))
+        };
+
+        mesh.positions.push(vector(position, &|_, v| v as f32));
+        if has_normals {
+            mesh.normals.push(vector(normal, &|_, v| v as f32));
+        }
+        if has_colors {
+            mesh.colors.push(vector(color, &|i, v| {
+                decode_color(element.properties[i].ty, v)
+            }));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_faces<R: Read>(
+    values: &mut Values<R>,
+    element: &Element,
+    mesh: &mut MeshData,
+) -> io::Result<()> {
+    let indices = element
+        .properties
+        .iter()
+        .position(|p| p.count.is_some() && (p.name == "vertex_indices" || p.name == "vertex_index"))
+        .ok_or_else(|| invalid_data("faces without vertex indices"))?;
+
+    for _ in 0..element.count {
+        for (i, property) in element.properties.iter().enumerate() {
+            let list = read_property(values, property)?;
+            if i != indices {
+                continue;
+            }
+
+            if list.iter().any(|&v| v < 0.0) {
+                return Err(invalid_data("negative vertex index"));
+            }
+            for k in 1..list.len().saturating_sub(1) {
+                mesh.triangles
+                    .push([list[0] as u32, list[k] as u32, list[k + 1] as u32]);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "ply\n\
+        format {} 1.0\n\
+        comment made by hand\n\
+        element vertex 4\n\
+        property float x\n\
+        property float y\n\
+        property float z\n\
+        property uchar red\n\
+        property uchar green\n\
+        property uchar blue\n\
+        element face 1\n\
+        property list uchar int vertex_indices\n\
+        end_header\n";
+
+    fn check(mesh: &MeshData) {
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.positions[2], Vec3::new(1.0, 1.0, 0.0));
+        assert!(mesh.normals.is_empty());
+        assert_eq!(mesh.colors[1], Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn decodes_ascii() {
+        let data = HEADER.replace("{}", "ascii")
+            + "0 0 0 0 0 0\n1 0 0 255 0 0\n1 1 0 0 255 0\n0 1 0 0 0 255\n4 0 1 2 3\n";
+        check(&decode(data.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn decodes_binary() {
+        let vertices = [
+            ([0.0f32, 0.0, 0.0], [0u8, 0, 0]),
+            ([1.0, 0.0, 0.0], [255, 0, 0]),
+            ([1.0, 1.0, 0.0], [0, 255, 0]),
+            ([0.0, 1.0, 0.0], [0, 0, 255]),
+        ];
+
+        for &(format, big_endian) in &[("binary_little_endian", false), ("binary_big_endian", true)]
+        {
+            let mut data = HEADER.replace("{}", format).into_bytes();
+            for (p, c) in &vertices {
+                for x in p {
+                    data.extend(if big_endian {
+                        x.to_be_bytes()
+                    } else {
+                        x.to_le_bytes()
+                    });
+                }
+                data.extend(c);
+            }
+            data.push(4);
+            for i in 0..4i32 {
+                data.extend(if big_endian {
+                    i.to_be_bytes()
+                } else {
+                    i.to_le_bytes()
+                });
+            }
+
+            check(&decode(&data[..]).unwrap());
+        }
+    }
+
+    #[test]
+    fn rejects_bad_indices() {
+        let data = HEADER.replace("{}", "ascii")
+            + "0 0 0 0 0 0\n1 0 0 255 0 0\n1 1 0 0 255 0\n0 1 0 0 0 255\n3 0 1 7\n";
+        assert!(decode(data.as_bytes()).is_err());
+    }
+}
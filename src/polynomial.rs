@@ -0,0 +1,210 @@
+use std::ops::Deref;
+
+/// Up to four real roots of a polynomial, in increasing order.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Roots {
+    values: [f64; 4],
+    len: usize,
+}
+
+impl Roots {
+    fn push(&mut self, x: f64) {
+        self.values[self.len] = x;
+        self.len += 1;
+    }
+
+    fn sorted(mut self) -> Roots {
+        self.values[..self.len].sort_by(|a, b| a.total_cmp(b));
+        self
+    }
+}
+
+impl Deref for Roots {
+    type Target = [f64];
+
+    fn deref(&self) -> &[f64] {
+        &self.values[..self.len]
+    }
+}
+
+/// Roots of `a t^2 + 2 half_b t + c`, in increasing order.
+pub fn solve_quadratic(a: f32, half_b: f32, c: f32) -> Option<[f32; 2]> {
+    if a == 0.0 {
+        if half_b == 0.0 {
+            return None;
+        }
+        let t = -c / (2.0 * half_b);
+        return Some([t, t]);
+    }
+
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    // Avoids cancellation between half_b and the square root
+    let q = -(half_b + discriminant.sqrt().copysign(half_b));
+    let (t0, t1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    Some(if t0 <= t1 { [t0, t1] } else { [t1, t0] })
+}
+
+/// Real roots of `x^2 + b x + c`, computed in double precision.
+fn monic_quadratic(b: f64, c: f64, roots: &mut Roots) {
+    let discriminant = b * b / 4.0 - c;
+    if discriminant < 0.0 {
+        return;
+    }
+
+    let q = -(b / 2.0 + discriminant.sqrt().copysign(b));
+    if q == 0.0 {
+        roots.push(0.0);
+        roots.push(0.0);
+    } else {
+        roots.push(q);
+        roots.push(c / q);
+    }
+}
+
+/// Real roots of `c[0] x^3 + c[1] x^2 + c[2] x + c[3]`.
+pub fn solve_cubic(c: [f64; 4]) -> Roots {
+    let mut roots = Roots::default();
+    if c[0] == 0.0 {
+        if c[1] != 0.0 {
+            monic_quadratic(c[2] / c[1], c[3] / c[1], &mut roots);
+        } else if c[2] != 0.0 {
+            roots.push(-c[3] / c[2]);
+        }
+        return roots.sorted();
+    }
+
+    let (a, b, c) = (c[1] / c[0], c[2] / c[0], c[3] / c[0]);
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+
+    if r * r < q * q * q {
+        // Three real roots, found with the trigonometric method
+        let theta = (r / (q * q * q).sqrt()).clamp(-1.0, 1.0).acos();
+        let scale = -2.0 * q.sqrt();
+        for k in 0..3 {
+            let angle = (theta + 2.0 * std::f64::consts::PI * k as f64) / 3.0;
+            roots.push(scale * angle.cos() - a / 3.0);
+        }
+    } else {
+        let big = -(r.abs() + (r * r - q * q * q).sqrt()).cbrt().copysign(r);
+        let small = if big == 0.0 { 0.0 } else { q / big };
+        roots.push(big + small - a / 3.0);
+    }
+
+    roots.sorted()
+}
+
+/// Real roots of `c[0] x^4 + c[1] x^3 + c[2] x^2 + c[3] x + c[4]`.
+///
+/// Uses Ferrari's method on the depressed quartic and polishes the roots
+/// with a few Newton steps on the original polynomial, which recovers the
+/// precision lost to cancellation near double roots.
+pub fn solve_quartic(c: [f64; 5]) -> Roots {
+    if c[0] == 0.0 {
+        return solve_cubic([c[1], c[2], c[3], c[4]]);
+    }
+
+    let (a, b, cc, d) = (c[1] / c[0], c[2] / c[0], c[3] / c[0], c[4] / c[0]);
+
+    // Substituting x = y - a / 4 gives y^4 + p y^2 + q y + r
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = cc - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * cc / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+
+    let mut depressed = Roots::default();
+    if q.abs() < 1e-12 {
+        // Biquadratic, a quadratic in y^2
+        let mut squares = Roots::default();
+        monic_quadratic(p, r, &mut squares);
+        for &z in squares.iter().filter(|&&z| z >= 0.0) {
+            depressed.push(-z.sqrt());
+            depressed.push(z.sqrt());
+        }
+    } else {
+        // Splits the quartic into two quadratics using the largest root of
+        // the resolvent cubic, which is positive when q is not zero
+        let m = solve_cubic([1.0, p, p * p / 4.0 - r, -q * q / 8.0])
+            .last()
+            .copied()
+            .unwrap_or(0.0);
+        if m <= 0.0 {
+            return Roots::default();
+        }
+
+        let s = (2.0 * m).sqrt();
+        monic_quadratic(-s, p / 2.0 + m + q / (2.0 * s), &mut depressed);
+        monic_quadratic(s, p / 2.0 + m - q / (2.0 * s), &mut depressed);
+    }
+
+    let mut roots = Roots::default();
+    for &y in depressed.iter() {
+        let mut x = y - a / 4.0;
+        for _ in 0..3 {
+            let value = (((x + a) * x + b) * x + cc) * x + d;
+            let slope = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + cc;
+            if slope == 0.0 {
+                break;
+            }
+            x -= value / slope;
+        }
+        roots.push(x);
+    }
+
+    roots.sorted()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_roots(roots: &[f64], expected: &[f64]) {
+        assert_eq!(roots.len(), expected.len(), "{:?}", roots);
+        for (a, b) in roots.iter().zip(expected) {
+            assert!((a - b).abs() < 1e-6, "{:?} != {:?}", roots, expected);
+        }
+    }
+
+    #[test]
+    fn solves_quadratics_stably() {
+        assert_eq!(solve_quadratic(1.0, -2.5, 6.0), Some([2.0, 3.0]));
+        assert_eq!(solve_quadratic(1.0, 0.0, 1.0), None);
+        let [small, _] = solve_quadratic(1.0, -5e3, 1.0).unwrap();
+        assert!((small - 1e-4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn solves_cubics() {
+        // (x - 1)(x - 2)(x + 3)
+        assert_roots(&solve_cubic([2.0, 0.0, -14.0, 12.0]), &[-3.0, 1.0, 2.0]);
+        // (x - 2)(x^2 + 1)
+        assert_roots(&solve_cubic([1.0, -2.0, 1.0, -2.0]), &[2.0]);
+        assert_roots(&solve_cubic([0.0, 1.0, -3.0, 2.0]), &[1.0, 2.0]);
+    }
+
+    #[test]
+    fn solves_quartics() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots(
+            &solve_quartic([1.0, -10.0, 35.0, -50.0, 24.0]),
+            &[1.0, 2.0, 3.0, 4.0],
+        );
+        // (x^2 - 1)(x^2 - 9), with no odd terms
+        assert_roots(
+            &solve_quartic([2.0, 0.0, -20.0, 0.0, 18.0]),
+            &[-3.0, -1.0, 1.0, 3.0],
+        );
+        // (x + 0.5)(x - 5)(x^2 + 1)
+        assert_roots(&solve_quartic([1.0, -4.5, -1.5, -4.5, -2.5]), &[-0.5, 5.0]);
+        assert!(solve_quartic([1.0, 0.0, 1.0, 0.0, 1.0]).is_empty());
+
+        // A quadruple root, where rounding easily loses the real solutions
+        let roots = solve_quartic([1.0, -4.0, 6.0, -4.0, 1.0]);
+        assert!(!roots.is_empty());
+        assert!(roots.iter().all(|x| (x - 1.0).abs() < 1e-3), "{:?}", roots);
+    }
+}
@@ -0,0 +1,396 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use crate::geometry::HitInfo;
+use crate::material::Material;
+use crate::microfacet::{self, Ggx};
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::{Onb, Vec3};
+
+use rand::prelude::*;
+
+/// Principled BSDF following the glTF 2.0 metallic-roughness model and its
+/// `KHR_materials_specular`, `clearcoat`, `sheen`, `transmission` and
+/// `emissive_strength` extensions.
+///
+/// Every parameter except the index of refraction and the emission strength
+/// can be driven by a texture, matching glTF where both are plain factors.
+/// Scalar parameters read the first channel of their texture.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    pub specular: Arc<dyn Texture>,
+    pub specular_color: Arc<dyn Texture>,
+    pub ior: f32,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_roughness: Arc<dyn Texture>,
+    pub sheen_color: Arc<dyn Texture>,
+    pub sheen_roughness: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub emission: Arc<dyn Texture>,
+    pub emission_strength: f32,
+}
+
+impl Default for Principled {
+    /// Matches the glTF defaults: a white, fully metallic and fully rough
+    /// surface with every extension turned off.
+    fn default() -> Principled {
+        Principled {
+            base_color: Arc::new(Vec3::new(1.0, 1.0, 1.0)),
+            metallic: Arc::new(1.0),
+            roughness: Arc::new(1.0),
+            specular: Arc::new(1.0),
+            specular_color: Arc::new(Vec3::new(1.0, 1.0, 1.0)),
+            ior: 1.5,
+            clearcoat: Arc::new(0.0),
+            clearcoat_roughness: Arc::new(0.0),
+            sheen_color: Arc::new(Vec3::zero()),
+            sheen_roughness: Arc::new(0.0),
+            transmission: Arc::new(0.0),
+            emission: Arc::new(Vec3::zero()),
+            emission_strength: 1.0,
+        }
+    }
+}
+
+/// Material parameters looked up at a hit point.
+struct Lobes {
+    base_color: Vec3,
+    metallic: f32,
+    ggx: Ggx,
+    f0: Vec3,
+    f90: f32,
+    clearcoat: f32,
+    clearcoat_ggx: Ggx,
+    sheen_color: Vec3,
+    sheen_alpha: f32,
+    transmission: f32,
+}
+
+/// Selection probabilities of the diffuse, specular, transmission and
+/// clearcoat lobes.
+struct Weights {
+    diffuse: f32,
+    specular: f32,
+    transmission: f32,
+    clearcoat: f32,
+}
+
+impl Principled {
+    fn lobes(&self, hit: &HitInfo) -> Lobes {
+        let specular = self.specular.value(hit).x().clamp(0.0, 1.0);
+        let r0 = ((self.ior - 1.0) / (self.ior + 1.0)).powi(2);
+        let f0 = r0 * self.specular_color.value(hit) * specular;
+
+        Lobes {
+            base_color: self.base_color.value(hit),
+            metallic: self.metallic.value(hit).x().clamp(0.0, 1.0),
+            ggx: Ggx::from_roughness(self.roughness.value(hit).x()),
+            f0: Vec3::new(f0.x().min(1.0), f0.y().min(1.0), f0.z().min(1.0)),
+            f90: specular,
+            clearcoat: self.clearcoat.value(hit).x().clamp(0.0, 1.0),
+            clearcoat_ggx: Ggx::from_roughness(self.clearcoat_roughness.value(hit).x()),
+            sheen_color: self.sheen_color.value(hit),
+            sheen_alpha: self.sheen_roughness.value(hit).x().powi(2).max(1e-3),
+            transmission: self.transmission.value(hit).x().clamp(0.0, 1.0),
+        }
+    }
+
+    /// Frame around the normal on the side of `wo`, and whether `wo` lies
+    /// inside a transmissive object.
+    fn frame(lobes: &Lobes, hit: &HitInfo, wo: Vec3) -> (Onb, bool) {
+        if wo.dot(hit.normal) >= 0.0 {
+            (Onb::from_w(hit.normal), false)
+        } else {
+            (Onb::from_w(-hit.normal), lobes.transmission > 0.0)
+        }
+    }
+
+    fn weights(lobes: &Lobes, wo: Vec3) -> Weights {
+        let coat = lobes.clearcoat * schlick(0.04, 1.0, wo.z());
+        let fresnel = max_component(schlick_color(lobes.f0, lobes.f90, wo.z()));
+        let base = 1.0 - coat;
+        let dielectric = base * (1.0 - lobes.metallic) * (1.0 - fresnel);
+        let sheen = max_component(lobes.sheen_color).min(1.0);
+
+        let diffuse = dielectric * ((1.0 - lobes.transmission) + sheen);
+        let specular = base * (lobes.metallic + (1.0 - lobes.metallic) * fresnel);
+        let transmission = dielectric * lobes.transmission;
+        let total = (diffuse + specular + transmission + coat).max(1e-6);
+
+        Weights {
+            diffuse: diffuse / total,
+            specular: specular / total,
+            transmission: transmission / total,
+            clearcoat: coat / total,
+        }
+    }
+
+    /// Value and pdf in the local frame of a hit from outside.
+    fn eval_outside(&self, lobes: &Lobes, wo: Vec3, wi: Vec3) -> (Vec3, f32) {
+        let weights = Principled::weights(lobes, wo);
+        let coat_attenuation = 1.0 - lobes.clearcoat * schlick(0.04, 1.0, wo.z());
+
+        if wi.z() < 0.0 {
+            let (value, pdf) = microfacet::rough_transmission(&lobes.ggx, wo, wi, self.ior);
+            let scale = coat_attenuation * (1.0 - lobes.metallic) * lobes.transmission * value;
+            return (scale * lobes.base_color, weights.transmission * pdf);
+        }
+
+        let (specular, specular_pdf, m) = microfacet::rough_reflection(&lobes.ggx, wo, wi);
+        let cos_m = wo.dot(m);
+
+        let metal = schlick_color(lobes.base_color, 1.0, cos_m) * specular;
+
+        let fresnel = schlick_color(lobes.f0, lobes.f90, cos_m);
+        let diffuse = (1.0 - lobes.transmission) * lobes.base_color * wi.z() / PI;
+        let sheen = lobes.sheen_color * charlie(lobes.sheen_alpha, m) * ashikhmin(wo, wi) * wi.z();
+        let dielectric =
+            (Vec3::new(1.0, 1.0, 1.0) - fresnel) * diffuse + fresnel * specular + sheen;
+
+        let (coat, coat_pdf, coat_m) = microfacet::rough_reflection(&lobes.clearcoat_ggx, wo, wi);
+        let coat = lobes.clearcoat * schlick(0.04, 1.0, wo.dot(coat_m)) * coat;
+
+        let value = coat_attenuation
+            * (lobes.metallic * metal + (1.0 - lobes.metallic) * dielectric)
+            + Vec3::new(coat, coat, coat);
+        let pdf = weights.diffuse * wi.z() / PI
+            + weights.specular * specular_pdf
+            + weights.clearcoat * coat_pdf;
+
+        (value, pdf)
+    }
+
+    fn eval_local(&self, lobes: &Lobes, inside: bool, wo: Vec3, wi: Vec3) -> (Vec3, f32) {
+        if wo.z() <= 0.0 {
+            return (Vec3::zero(), 0.0);
+        }
+
+        if inside {
+            let (value, pdf) = microfacet::rough_dielectric(&lobes.ggx, wo, wi, 1.0 / self.ior);
+            return (Vec3::new(value, value, value), pdf);
+        }
+
+        self.eval_outside(lobes, wo, wi)
+    }
+}
+
+impl Material for Principled {
+    fn eval(&self, hit: &HitInfo, wo: Vec3, wi: Vec3) -> Vec3 {
+        let lobes = self.lobes(hit);
+        let (frame, inside) = Principled::frame(&lobes, hit, wo);
+
+        self.eval_local(&lobes, inside, frame.to_local(wo), frame.to_local(wi))
+            .0
+    }
+
+    fn pdf(&self, hit: &HitInfo, wo: Vec3, wi: Vec3) -> f32 {
+        let lobes = self.lobes(hit);
+        let (frame, inside) = Principled::frame(&lobes, hit, wo);
+
+        self.eval_local(&lobes, inside, frame.to_local(wo), frame.to_local(wi))
+            .1
+    }
+
+    fn emitted(&self, hit: &HitInfo) -> Vec3 {
+        self.emission.value(hit) * self.emission_strength
+    }
+
+    fn scatter(
+        &self,
+        ray: &Ray,
+        hit: &HitInfo,
+        rng: &mut dyn rand::RngCore,
+    ) -> Option<(Ray, Vec3)> {
+        let lobes = self.lobes(hit);
+        let wo = -ray.direction.normalized();
+        let (frame, inside) = Principled::frame(&lobes, hit, wo);
+        let wo = frame.to_local(wo);
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let wi = if inside {
+            microfacet::sample_rough_dielectric(&lobes.ggx, wo, 1.0 / self.ior, rng)?
+        } else {
+            let weights = Principled::weights(&lobes, wo);
+            let u: f32 = rng.gen();
+
+            if u < weights.diffuse {
+                Vec3::random_cosine_direction(rng)
+            } else if u < weights.diffuse + weights.specular {
+                let m = lobes.ggx.sample_visible_normal(wo, rng.gen(), rng.gen());
+                (-wo).reflect(m)
+            } else if u < weights.diffuse + weights.specular + weights.transmission {
+                microfacet::sample_rough_transmission(
+                    &lobes.ggx,
+                    wo,
+                    self.ior,
+                    rng.gen(),
+                    rng.gen(),
+                )?
+            } else {
+                let m = lobes
+                    .clearcoat_ggx
+                    .sample_visible_normal(wo, rng.gen(), rng.gen());
+                (-wo).reflect(m)
+            }
+        };
+
+        let (value, pdf) = self.eval_local(&lobes, inside, wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some((Ray::new(hit.p, frame.to_world(wi)), value / pdf))
+    }
+}
+
+fn schlick(f0: f32, f90: f32, cos: f32) -> f32 {
+    f0 + (f90 - f0) * (1.0 - cos).max(0.0).powi(5)
+}
+
+fn schlick_color(f0: Vec3, f90: f32, cos: f32) -> Vec3 {
+    Vec3::new(
+        schlick(f0.x(), f90, cos),
+        schlick(f0.y(), f90, cos),
+        schlick(f0.z(), f90, cos),
+    )
+}
+
+/// "Charlie" sheen distribution (Estevez and Kulla 2017).
+fn charlie(alpha: f32, m: Vec3) -> f32 {
+    let sin = (1.0 - m.z() * m.z()).max(0.0).sqrt();
+    (2.0 + 1.0 / alpha) * sin.powf(1.0 / alpha) / (2.0 * PI)
+}
+
+/// Ashikhmin's visibility term, as used for sheen by glTF.
+fn ashikhmin(wo: Vec3, wi: Vec3) -> f32 {
+    1.0 / (4.0 * (wi.z() + wo.z() - wi.z() * wo.z()))
+}
+
+fn max_component(v: Vec3) -> f32 {
+    v.x().max(v.y()).max(v.z())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::rng::Pcg32;
+
+    const SAMPLES: usize = 100_000;
+
+    fn hit(material: &Principled) -> HitInfo<'_> {
+        HitInfo {
+            t: 1.0,
+            p: Vec3::zero(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            u: 0.0,
+            v: 0.0,
+            color: None,
+            material,
+        }
+    }
+
+    fn plastic() -> Principled {
+        Principled {
+            base_color: Arc::new(Vec3::new(0.8, 0.6, 0.4)),
+            metallic: Arc::new(0.3),
+            roughness: Arc::new(0.5),
+            sheen_color: Arc::new(Vec3::new(0.2, 0.2, 0.2)),
+            sheen_roughness: Arc::new(0.5),
+            ..Principled::default()
+        }
+    }
+
+    fn uniform_hemisphere(rng: &mut Pcg32) -> Vec3 {
+        let mut d = Vec3::random_in_unit_sphere(rng).normalized();
+        if d.z() < 0.0 {
+            d = -d;
+        }
+        d
+    }
+
+    #[test]
+    fn reflects_no_more_than_it_receives() {
+        let white = Principled {
+            roughness: Arc::new(0.3),
+            ..Principled::default()
+        };
+        let mut rng = Pcg32::new(1, 0);
+
+        for material in &[plastic(), white] {
+            for &cos in &[1.0f32, 0.5, 0.1] {
+                let wo = Vec3::new((1.0 - cos * cos).sqrt(), 0.0, cos);
+                let ray = Ray::new(wo, -wo);
+                let albedo = (0..SAMPLES)
+                    .filter_map(|_| material.scatter(&ray, &hit(material), &mut rng))
+                    .fold(Vec3::zero(), |sum, (_, weight)| sum + weight)
+                    / SAMPLES as f32;
+
+                assert!(max_component(albedo) < 1.02, "{:?} at {}", albedo, cos);
+                assert!(albedo.x() > 0.1);
+            }
+        }
+    }
+
+    #[test]
+    fn reflection_is_reciprocal() {
+        let material = plastic();
+        let hit = hit(&material);
+        let mut rng = Pcg32::new(2, 0);
+
+        for _ in 0..100 {
+            let (a, b) = (uniform_hemisphere(&mut rng), uniform_hemisphere(&mut rng));
+            // eval includes the cosine of the incoming direction
+            let ab = material.eval(&hit, a, b) / b.z();
+            let ba = material.eval(&hit, b, a) / a.z();
+            assert!(
+                (ab - ba).lenght() < 1e-3 * ab.lenght().max(1.0),
+                "{:?} {:?}",
+                ab,
+                ba
+            );
+        }
+    }
+
+    #[test]
+    fn sampled_directions_follow_the_pdf() {
+        let material = plastic();
+        let hit = hit(&material);
+        let mut rng = Pcg32::new(3, 0);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let ray = Ray::new(wo, -wo);
+
+        // Importance sampling with the reported pdf has to agree with
+        // integrating eval over uniformly distributed directions
+        let mut sampled = Vec3::zero();
+        let mut uniform = Vec3::zero();
+        let mut total_pdf = 0.0;
+        for _ in 0..SAMPLES {
+            if let Some((scattered, _)) = material.scatter(&ray, &hit, &mut rng) {
+                let wi = scattered.direction.normalized();
+                let pdf = material.pdf(&hit, wo, wi);
+                assert!(pdf > 0.0);
+                sampled += material.eval(&hit, wo, wi) / pdf;
+            }
+
+            let wi = uniform_hemisphere(&mut rng);
+            uniform += material.eval(&hit, wo, wi) * (2.0 * PI);
+            total_pdf += material.pdf(&hit, wo, wi) * (2.0 * PI);
+        }
+
+        let (sampled, uniform) = (sampled / SAMPLES as f32, uniform / SAMPLES as f32);
+        assert!(
+            (sampled - uniform).lenght() < 0.02,
+            "{:?} {:?}",
+            sampled,
+            uniform
+        );
+        let total_pdf = total_pdf / SAMPLES as f32;
+        assert!(total_pdf > 0.9 && total_pdf < 1.02, "{}", total_pdf);
+    }
+}
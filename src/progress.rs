@@ -0,0 +1,205 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Receives updates on how far a render has come.
+pub trait Progress {
+    /// Reports that `done` out of `total` units of work are finished.
+    fn update(&mut self, done: u64, total: u64);
+
+    /// Called once rendering has stopped.
+    fn finish(&mut self);
+}
+
+/// Reports nothing at all.
+pub struct Quiet;
+
+impl Progress for Quiet {
+    fn update(&mut self, _: u64, _: u64) {}
+
+    fn finish(&mut self) {}
+}
+
+/// Progress bar with the elapsed time and an estimate of the time left,
+/// redrawn in place on standard error at most every `interval`.
+pub struct ProgressBar {
+    pub interval: Duration,
+    start: Instant,
+    last_draw: Option<Instant>,
+    done: u64,
+    total: u64,
+}
+
+impl ProgressBar {
+    pub fn new() -> ProgressBar {
+        ProgressBar {
+            interval: Duration::from_millis(100),
+            start: Instant::now(),
+            last_draw: None,
+            done: 0,
+            total: 0,
+        }
+    }
+
+    fn draw(&mut self) {
+        const WIDTH: usize = 30;
+
+        let fraction = fraction(self.done, self.total);
+        let filled = (fraction * WIDTH as f64) as usize;
+        let elapsed = self.start.elapsed();
+
+        let mut stderr = io::stderr();
+        let _ = write!(
+            stderr,
+            "\r[{}{}] {:5.1}% {} elapsed, {} left ",
+            "#".repeat(filled),
+            "-".repeat(WIDTH - filled),
+            100.0 * fraction,
+            format_duration(elapsed),
+            eta(elapsed, self.done, self.total).map_or_else(|| "?".to_string(), format_duration),
+        );
+        let _ = stderr.flush();
+
+        self.last_draw = Some(Instant::now());
+    }
+}
+
+impl Default for ProgressBar {
+    fn default() -> ProgressBar {
+        ProgressBar::new()
+    }
+}
+
+impl Progress for ProgressBar {
+    fn update(&mut self, done: u64, total: u64) {
+        self.done = done;
+        self.total = total;
+
+        if self.last_draw.is_none_or(|t| t.elapsed() >= self.interval) {
+            self.draw();
+        }
+    }
+
+    fn finish(&mut self) {
+        self.draw();
+        eprintln!();
+    }
+}
+
+/// One line per update for scripts wrapping the renderer, written at most
+/// every `interval`:
+///
+/// ```text
+/// progress <done> <total> <fraction> <elapsed seconds> <seconds left>
+/// done <elapsed seconds>
+/// ```
+///
+/// The time left is `-1` until it can be estimated.
+pub struct LineProgress<W: Write> {
+    pub writer: W,
+    pub interval: Duration,
+    start: Instant,
+    last_line: Option<Instant>,
+}
+
+impl<W: Write> LineProgress<W> {
+    pub fn new(writer: W, interval: Duration) -> LineProgress<W> {
+        LineProgress {
+            writer,
+            interval,
+            start: Instant::now(),
+            last_line: None,
+        }
+    }
+}
+
+impl<W: Write> Progress for LineProgress<W> {
+    fn update(&mut self, done: u64, total: u64) {
+        if self
+            .last_line
+            .is_some_and(|t| t.elapsed() < self.interval && done < total)
+        {
+            return;
+        }
+
+        let elapsed = self.start.elapsed();
+        let left = eta(elapsed, done, total).map_or(-1.0, |d| d.as_secs_f64());
+
+        let _ = writeln!(
+            self.writer,
+            "progress {} {} {:.4} {:.1} {:.1}",
+            done,
+            total,
+            fraction(done, total),
+            elapsed.as_secs_f64(),
+            left
+        );
+        let _ = self.writer.flush();
+
+        self.last_line = Some(Instant::now());
+    }
+
+    fn finish(&mut self) {
+        let _ = writeln!(
+            self.writer,
+            "done {:.1}",
+            self.start.elapsed().as_secs_f64()
+        );
+        let _ = self.writer.flush();
+    }
+}
+
+fn fraction(done: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        (done as f64 / total as f64).min(1.0)
+    }
+}
+
+/// Time left assuming the rest of the work goes as fast as what is done.
+fn eta(elapsed: Duration, done: u64, total: u64) -> Option<Duration> {
+    if done == 0 {
+        return None;
+    }
+
+    let left = total.saturating_sub(done) as f64 / done as f64;
+    Some(elapsed.mul_f64(left))
+}
+
+fn format_duration(d: Duration) -> String {
+    let s = d.as_secs();
+    format!("{}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_throttled() {
+        let mut progress = LineProgress::new(Vec::new(), Duration::from_secs(3600));
+
+        progress.update(0, 10);
+        progress.update(5, 10);
+        progress.update(10, 10);
+        progress.finish();
+
+        let output = String::from_utf8(progress.writer).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        // The first update and the final one always get through
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("progress 0 10 0.0000 "));
+        assert!(lines[0].ends_with(" -1.0"));
+        assert!(lines[1].starts_with("progress 10 10 1.0000 "));
+        assert!(lines[2].starts_with("done "));
+    }
+
+    #[test]
+    fn estimates_time_left() {
+        let eta = eta(Duration::from_secs(10), 25, 100).unwrap();
+        assert_eq!(eta, Duration::from_secs(30));
+
+        assert_eq!(format_duration(Duration::from_secs(3725)), "1:02:05");
+    }
+}
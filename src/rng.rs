@@ -0,0 +1,84 @@
+use rand::RngCore;
+
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+/// O'Neill's PCG32 (XSH RR) generator.
+///
+/// Unlike the thread local generator its whole state is two integers, so a
+/// render can be seeded for reproducible results and snapshotted to resume
+/// exactly where it stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pcg32 {
+    pub state: u64,
+    pub inc: u64,
+}
+
+impl Pcg32 {
+    /// Generator for the given seed. Different `stream`s give independent
+    /// sequences for the same seed.
+    pub fn new(seed: u64, stream: u64) -> Pcg32 {
+        let mut rng = Pcg32 {
+            state: 0,
+            inc: (stream << 1) | 1,
+        };
+        rng.step();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.step();
+        rng
+    }
+
+    fn step(&mut self) {
+        self.state = self.state.wrapping_mul(MULTIPLIER).wrapping_add(self.inc);
+    }
+}
+
+impl RngCore for Pcg32 {
+    fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.step();
+
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        u64::from(self.next_u32()) | (u64::from(self.next_u32()) << 32)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_reference_sequence() {
+        // First outputs of pcg32-global-demo from the PCG reference
+        // implementation, seeded with 42 on stream 54
+        let mut rng = Pcg32::new(42, 54);
+        let expected = [
+            0xa15c_02b7,
+            0x7b47_f409,
+            0xba1d_3330,
+            0x83d2_f293,
+            0xbfa4_784b,
+            0xcbed_606e,
+        ];
+
+        for &e in &expected {
+            assert_eq!(rng.next_u32(), e);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::film::Pixel;
+
+/// Controls how many samples each pixel receives.
+///
+/// Every pixel gets at least `min_samples`. After that, samples are taken
+/// in batches of `batch_size` until the pixel's estimated error drops below
+/// `threshold` or `max_samples` is reached. Setting both bounds to the same
+/// value gives a fixed number of samples per pixel.
+///
+/// The error is measured after the image is multiplied by `exposure`, so
+/// that the threshold means the same for physically scaled radiance.
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveSampling {
+    pub min_samples: u32,
+    pub max_samples: u32,
+    pub batch_size: u32,
+    pub threshold: f32,
+    pub exposure: f32,
+}
+
+impl AdaptiveSampling {
+    pub fn new(min_samples: u32, max_samples: u32, threshold: f32) -> AdaptiveSampling {
+        AdaptiveSampling {
+            min_samples: min_samples.max(1),
+            max_samples: max_samples.max(min_samples),
+            batch_size: 8,
+            threshold,
+            exposure: 1.0,
+        }
+    }
+
+    pub fn fixed(samples: u32) -> AdaptiveSampling {
+        AdaptiveSampling::new(samples, samples, 0.0)
+    }
+
+    /// Number of samples to add to `pixel` next, or zero once it is done.
+    pub fn next_batch(&self, pixel: &Pixel) -> u32 {
+        if pixel.samples < self.min_samples {
+            return self.min_samples - pixel.samples;
+        }
+
+        if pixel.samples >= self.max_samples
+            || pixel.error() * self.exposure.sqrt() <= self.threshold
+        {
+            return 0;
+        }
+
+        self.batch_size.min(self.max_samples - pixel.samples)
+    }
+}
+
+/// Multiple importance sampling weight of a sample taken with density `pdf`
+/// when another strategy could have produced it with density `other_pdf`.
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+
+    if a + b == 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::vec3::Vec3;
+
+    fn pixel(samples: &[f32]) -> Pixel {
+        let mut pixel = Pixel::new();
+        for &s in samples {
+            pixel.add_sample(Vec3::new(s, s, s));
+        }
+        pixel
+    }
+
+    #[test]
+    fn every_pixel_gets_the_minimum() {
+        let sampling = AdaptiveSampling::new(16, 64, 0.01);
+        assert_eq!(sampling.next_batch(&pixel(&[])), 16);
+        assert_eq!(sampling.next_batch(&pixel(&[1.0; 10])), 6);
+    }
+
+    #[test]
+    fn converged_pixels_stop_at_the_threshold() {
+        let sampling = AdaptiveSampling::new(4, 64, 0.01);
+        assert_eq!(sampling.next_batch(&pixel(&[0.5; 4])), 0);
+
+        // The same noise is below a looser threshold
+        let noisy = pixel(&[0.0, 10.0, 0.0, 10.0]);
+        assert_eq!(sampling.next_batch(&noisy), 8);
+        let loose = AdaptiveSampling::new(4, 64, noisy.error());
+        assert_eq!(loose.next_batch(&noisy), 0);
+
+        // Brighter exposures make the same error more visible
+        let mut bright = loose;
+        bright.exposure = 4.0;
+        assert_eq!(bright.next_batch(&noisy), 8);
+    }
+
+    #[test]
+    fn noisy_pixels_stop_at_the_maximum() {
+        let sampling = AdaptiveSampling::new(4, 12, 0.0);
+        let mut noisy = pixel(&[0.0, 10.0, 0.0, 10.0, 0.0, 10.0]);
+        assert_eq!(sampling.next_batch(&noisy), 6);
+
+        for _ in 0..6 {
+            noisy.add_sample(Vec3::new(5.0, 5.0, 5.0));
+        }
+        assert_eq!(sampling.next_batch(&noisy), 0);
+
+        let fixed = AdaptiveSampling::fixed(4);
+        assert_eq!(fixed.next_batch(&pixel(&[0.0, 10.0, 0.0, 10.0])), 0);
+    }
+}
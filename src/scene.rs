@@ -0,0 +1,28 @@
+use crate::environment::Environment;
+use crate::geometry::{HitInfo, Hitable};
+use crate::light::Light;
+use crate::ray::Ray;
+use crate::stats;
+use crate::vec3::Vec3;
+
+/// Everything the integrator needs to know about the world.
+pub struct Scene {
+    pub world: Vec<Box<dyn Hitable>>,
+    pub environment: Box<dyn Environment>,
+    pub lights: Vec<Box<dyn Light>>,
+}
+
+impl Scene {
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo<'_>> {
+        self.world.hit(ray, t_min, t_max)
+    }
+
+    /// Whether anything blocks the way from `origin` along `direction`,
+    /// up to a distance of `t_max` times its length.
+    pub fn occluded(&self, origin: Vec3, direction: Vec3, t_max: f32) -> bool {
+        stats::SHADOW_RAYS.add(1);
+        self.world
+            .hit(&Ray::new(origin, direction), 0.001, t_max)
+            .is_some()
+    }
+}
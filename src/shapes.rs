@@ -0,0 +1,811 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use crate::bvh::Aabb;
+use crate::geometry::{HitInfo, Hitable, Interval};
+use crate::material::Material;
+use crate::polynomial::{solve_quadratic, solve_quartic};
+use crate::ray::Ray;
+use crate::transform::Mat4;
+use crate::vec3::{Onb, Vec3};
+
+/// Infinite plane through `point`, facing `normal`.
+///
+/// `u` and `v` are distances from `point` along two perpendicular
+/// directions within the plane, so image textures repeat every unit.
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub material: Arc<dyn Material>,
+}
+
+impl Hitable for Plane {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo<'_>> {
+        let local = Local::new(ray, self.point, self.normal);
+        let t = local.plane(0.0)?;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let p = local.point(t);
+        Some(local.hit_info(
+            ray,
+            t,
+            Vec3::new(0.0, 0.0, 1.0),
+            p.x(),
+            p.y(),
+            &*self.material,
+        ))
+    }
+
+    fn bounds(&self) -> Aabb {
+        Aabb::infinite()
+    }
+}
+
+/// Flat disk of `radius` around `center`, facing `normal`.
+///
+/// `u` goes around the center and `v` from the center to the rim.
+pub struct Disk {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub radius: f32,
+    pub material: Arc<dyn Material>,
+}
+
+impl Hitable for Disk {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo<'_>> {
+        let local = Local::new(ray, self.center, self.normal);
+        let t = local.plane(0.0)?;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let p = local.point(t);
+        let r = p.x().hypot(p.y());
+        if r > self.radius {
+            return None;
+        }
+
+        Some(local.hit_info(
+            ray,
+            t,
+            Vec3::new(0.0, 0.0, 1.0),
+            angle(p) / (2.0 * PI),
+            r / self.radius,
+            &*self.material,
+        ))
+    }
+
+    fn bounds(&self) -> Aabb {
+        disk_bounds(self.center, self.normal, self.radius)
+    }
+}
+
+/// Cylinder of `radius` around the segment from `base` to `top`.
+///
+/// A `sweep` below 360 degrees cuts away a wedge, leaving the surface open
+/// there. The sweep starts from the `u` axis of `Onb::from_w` for the
+/// direction of the cylinder. Without `capped` the ends are open too.
+///
+/// On the side `u` goes around the axis and `v` from the base to the top.
+/// On the caps `v` goes from the center to the rim.
+pub struct Cylinder {
+    pub base: Vec3,
+    pub top: Vec3,
+    pub radius: f32,
+    pub sweep: f32,
+    pub capped: bool,
+    pub material: Arc<dyn Material>,
+}
+
+impl Cylinder {
+    /// Full, closed cylinder.
+    pub fn new(base: Vec3, top: Vec3, radius: f32, material: Arc<dyn Material>) -> Cylinder {
+        Cylinder {
+            base,
+            top,
+            radius,
+            sweep: 360.0,
+            capped: true,
+            material,
+        }
+    }
+}
+
+impl Cylinder {
+    /// Calls `found` with every crossing of the line through the ray with
+    /// the surfaces of the cylinder, in local coordinates.
+    fn crossings(&self, local: &Local, mut found: impl FnMut(f32, Vec3, f32, f32)) {
+        let height = (self.top - self.base).lenght();
+        let sweep = self.sweep.to_radians();
+
+        let (o, d) = (local.origin, local.direction);
+        let a = d.x() * d.x() + d.y() * d.y();
+        let half_b = o.x() * d.x() + o.y() * d.y();
+        let c = o.x() * o.x() + o.y() * o.y() - self.radius * self.radius;
+        for t in solve_quadratic(a, half_b, c).into_iter().flatten() {
+            let p = local.point(t);
+            let phi = angle(p);
+            if (0.0..=height).contains(&p.z()) && phi <= sweep {
+                let normal = Vec3::new(p.x(), p.y(), 0.0);
+                found(t, normal, phi / sweep, p.z() / height);
+            }
+        }
+
+        if self.capped {
+            for (z, facing) in [(0.0, -1.0), (height, 1.0)] {
+                if let Some(t) = local.plane(z) {
+                    let p = local.point(t);
+                    let (r, phi) = (p.x().hypot(p.y()), angle(p));
+                    if r <= self.radius && phi <= sweep {
+                        let normal = Vec3::new(0.0, 0.0, facing);
+                        found(t, normal, phi / sweep, r / self.radius);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Hitable for Cylinder {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo<'_>> {
+        let local = Local::new(ray, self.base, self.top - self.base);
+        let mut closest = Closest::new(t_min, t_max);
+        self.crossings(&local, |t, normal, u, v| closest.consider(t, normal, u, v));
+
+        let (t, normal, u, v) = closest.hit?;
+        Some(local.hit_info(ray, t, normal, u, v, &*self.material))
+    }
+
+    fn bounds(&self) -> Aabb {
+        let axis = self.top - self.base;
+        disk_bounds(self.base, axis, self.radius).union(disk_bounds(self.top, axis, self.radius))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        if !self.capped || self.sweep < 360.0 {
+            return Vec::new();
+        }
+
+        let local = Local::new(ray, self.base, self.top - self.base);
+        let mut hits = Vec::new();
+        self.crossings(&local, |t, normal, u, v| {
+            hits.push(local.hit_info(ray, t, normal, u, v, &*self.material))
+        });
+        pair_up(hits)
+    }
+}
+
+/// Cone with a base of `radius` around `base` and its tip at `apex`.
+///
+/// `sweep` and `capped` work as for `Cylinder`, except that there is only
+/// the base to cap. On the side `v` goes from the base to the apex.
+pub struct Cone {
+    pub base: Vec3,
+    pub apex: Vec3,
+    pub radius: f32,
+    pub sweep: f32,
+    pub capped: bool,
+    pub material: Arc<dyn Material>,
+}
+
+impl Cone {
+    /// Full cone, closed at the base.
+    pub fn new(base: Vec3, apex: Vec3, radius: f32, material: Arc<dyn Material>) -> Cone {
+        Cone {
+            base,
+            apex,
+            radius,
+            sweep: 360.0,
+            capped: true,
+            material,
+        }
+    }
+}
+
+impl Cone {
+    /// Calls `found` with every crossing of the line through the ray with
+    /// the surfaces of the cone, in local coordinates.
+    fn crossings(&self, local: &Local, mut found: impl FnMut(f32, Vec3, f32, f32)) {
+        let height = (self.apex - self.base).lenght();
+        let sweep = self.sweep.to_radians();
+
+        // The radius shrinks linearly to zero at the apex, so points on the
+        // side satisfy x^2 + y^2 = (k (height - z))^2
+        let k2 = (self.radius / height).powi(2);
+        let (o, d) = (local.origin, local.direction);
+        let w = height - o.z();
+        let a = d.x() * d.x() + d.y() * d.y() - k2 * d.z() * d.z();
+        let half_b = o.x() * d.x() + o.y() * d.y() + k2 * w * d.z();
+        let c = o.x() * o.x() + o.y() * o.y() - k2 * w * w;
+        for t in solve_quadratic(a, half_b, c).into_iter().flatten() {
+            let p = local.point(t);
+            let phi = angle(p);
+            if (0.0..=height).contains(&p.z()) && phi <= sweep {
+                let normal = Vec3::new(p.x(), p.y(), k2 * (height - p.z()));
+                found(t, normal, phi / sweep, p.z() / height);
+            }
+        }
+
+        if self.capped {
+            if let Some(t) = local.plane(0.0) {
+                let p = local.point(t);
+                let (r, phi) = (p.x().hypot(p.y()), angle(p));
+                if r <= self.radius && phi <= sweep {
+                    let normal = Vec3::new(0.0, 0.0, -1.0);
+                    found(t, normal, phi / sweep, r / self.radius);
+                }
+            }
+        }
+    }
+}
+
+impl Hitable for Cone {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo<'_>> {
+        let local = Local::new(ray, self.base, self.apex - self.base);
+        let mut closest = Closest::new(t_min, t_max);
+        self.crossings(&local, |t, normal, u, v| closest.consider(t, normal, u, v));
+
+        let (t, normal, u, v) = closest.hit?;
+        Some(local.hit_info(ray, t, normal, u, v, &*self.material))
+    }
+
+    fn bounds(&self) -> Aabb {
+        disk_bounds(self.base, self.apex - self.base, self.radius).grow(self.apex)
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        if !self.capped || self.sweep < 360.0 {
+            return Vec::new();
+        }
+
+        let local = Local::new(ray, self.base, self.apex - self.base);
+        let mut hits = Vec::new();
+        self.crossings(&local, |t, normal, u, v| {
+            hits.push(local.hit_info(ray, t, normal, u, v, &*self.material))
+        });
+        pair_up(hits)
+    }
+}
+
+/// Torus around `center`, with the ring of tubes lying in the plane
+/// facing `axis`.
+///
+/// `major_radius` is the distance from the center to the middle of the
+/// tube and `minor_radius` the radius of the tube. `u` goes around the
+/// axis and `v` around the tube, starting from its outer edge.
+pub struct Torus {
+    pub center: Vec3,
+    pub axis: Vec3,
+    pub major_radius: f32,
+    pub minor_radius: f32,
+    pub material: Arc<dyn Material>,
+}
+
+impl Torus {
+    /// Distances along the ray to every crossing of its line with the
+    /// surface, in increasing order.
+    fn crossings(&self, local: &Local) -> impl Iterator<Item = f32> {
+        let o = local.origin;
+        let d = local.direction;
+        let (big, small) = (f64::from(self.major_radius), f64::from(self.minor_radius));
+
+        // Solving from the point of the ray closest to the center keeps the
+        // coefficients small for rays starting far away, which the quartic
+        // is very sensitive to
+        let sum_d = f64::from(d.dot(d));
+        let shift = -f64::from(o.dot(d)) / sum_d;
+        let [ox, oy, oz] = [0, 1, 2].map(|i| f64::from(o[i]) + shift * f64::from(d[i]));
+        let [dx, dy, dz] = [0, 1, 2].map(|i| f64::from(d[i]));
+
+        // Points on the surface satisfy
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2)
+        let e = ox * ox + oy * oy + oz * oz + big * big - small * small;
+        let f = ox * dx + oy * dy + oz * dz;
+        let four_r2 = 4.0 * big * big;
+        let roots = solve_quartic([
+            sum_d * sum_d,
+            4.0 * sum_d * f,
+            2.0 * sum_d * e + 4.0 * f * f - four_r2 * (dx * dx + dy * dy),
+            4.0 * f * e - 2.0 * four_r2 * (ox * dx + oy * dy),
+            e * e - four_r2 * (ox * ox + oy * oy),
+        ]);
+
+        (0..roots.len()).map(move |i| (roots[i] + shift) as f32)
+    }
+
+    fn hit_info<'a>(&'a self, ray: &Ray, local: &Local, t: f32) -> HitInfo<'a> {
+        let p = local.point(t);
+        let s = p.lenght_squared() + self.major_radius.powi(2) - self.minor_radius.powi(2);
+        let radial = s - 2.0 * self.major_radius.powi(2);
+        let normal = Vec3::new(p.x() * radial, p.y() * radial, p.z() * s);
+        let tube = p.z().atan2(p.x().hypot(p.y()) - self.major_radius);
+        local.hit_info(
+            ray,
+            t,
+            normal,
+            angle(p) / (2.0 * PI),
+            tube.rem_euclid(2.0 * PI) / (2.0 * PI),
+            &*self.material,
+        )
+    }
+}
+
+impl Hitable for Torus {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo<'_>> {
+        let local = Local::new(ray, self.center, self.axis);
+        let t = self.crossings(&local).find(|&t| t > t_min && t < t_max)?;
+        Some(self.hit_info(ray, &local, t))
+    }
+
+    fn bounds(&self) -> Aabb {
+        let radius = self.major_radius + self.minor_radius;
+        let offset = self.minor_radius * self.axis.normalized();
+        disk_bounds(self.center - offset, self.axis, radius).union(disk_bounds(
+            self.center + offset,
+            self.axis,
+            radius,
+        ))
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        let local = Local::new(ray, self.center, self.axis);
+        let hits = self.crossings(&local);
+        pair_up(hits.map(|t| self.hit_info(ray, &local, t)).collect())
+    }
+}
+
+/// Quadric surface, the points `p` for which `p^T Q p = 0` in homogeneous
+/// coordinates, kept only within the `clip` box.
+///
+/// The coefficients are negative inside the solid so that normals point
+/// outwards. `u` goes around the vertical line through the center of the
+/// clip box and `v` from its bottom to its top.
+pub struct Quadric {
+    pub coefficients: Mat4,
+    pub clip: Aabb,
+    pub material: Arc<dyn Material>,
+}
+
+impl Quadric {
+    /// Quadric with the symmetric `coefficients` matrix.
+    pub fn new(coefficients: Mat4, clip: Aabb, material: Arc<dyn Material>) -> Quadric {
+        Quadric {
+            coefficients,
+            clip,
+            material,
+        }
+    }
+
+    /// Ellipsoid around `center` with different radii along each axis.
+    pub fn ellipsoid(center: Vec3, radii: Vec3, material: Arc<dyn Material>) -> Quadric {
+        let diagonal = [0, 1, 2].map(|i| 1.0 / (radii[i] * radii[i]));
+        let q = diagonal_matrix([diagonal[0], diagonal[1], diagonal[2], -1.0]);
+        // Slightly larger than the ellipsoid so that rounding does not
+        // clip away its extremes
+        let extent = radii * 1.001;
+        let clip = Aabb::from_points(&[center - extent, center + extent]);
+        Quadric::new(centered(q, center), clip, material)
+    }
+
+    /// Round paraboloid with its tip at `vertex`, opening upwards until it
+    /// reaches `radius` at `height` above the tip, where it is left open.
+    pub fn paraboloid(
+        vertex: Vec3,
+        radius: f32,
+        height: f32,
+        material: Arc<dyn Material>,
+    ) -> Quadric {
+        // (x^2 + z^2) / radius^2 - y / height = 0
+        let k = 1.0 / (radius * radius);
+        let mut q = diagonal_matrix([k, 0.0, k, 0.0]);
+        q.m[1][3] = -0.5 / height;
+        q.m[3][1] = -0.5 / height;
+        let clip = Aabb::from_points(&[
+            vertex + Vec3::new(-radius, 0.0, -radius),
+            vertex + Vec3::new(radius, height, radius),
+        ]);
+        Quadric::new(centered(q, vertex), clip, material)
+    }
+
+    /// Hyperboloid of one sheet around a vertical axis through `center`,
+    /// with a radius of `waist` at the center that widens to `radius` at
+    /// `half_height` above and below it. Both ends are left open.
+    pub fn hyperboloid(
+        center: Vec3,
+        waist: f32,
+        radius: f32,
+        half_height: f32,
+        material: Arc<dyn Material>,
+    ) -> Quadric {
+        // (x^2 + z^2) / waist^2 - k y^2 - 1 = 0, with k chosen to pass
+        // through the rims
+        let k = (radius * radius - waist * waist) / (waist * half_height).powi(2);
+        let w = 1.0 / (waist * waist);
+        let q = diagonal_matrix([w, -k, w, -1.0]);
+        let extent = Vec3::new(radius, half_height, radius);
+        let clip = Aabb::from_points(&[center - extent, center + extent]);
+        Quadric::new(centered(q, center), clip, material)
+    }
+
+    /// `a^T Q b` for homogeneous points or directions.
+    fn form(&self, a: [f32; 4], b: [f32; 4]) -> f32 {
+        let q = &self.coefficients.m;
+        (0..4)
+            .map(|i| a[i] * (0..4).map(|j| q[i][j] * b[j]).sum::<f32>())
+            .sum()
+    }
+}
+
+impl Quadric {
+    fn hit_info(&self, ray: &Ray, t: f32) -> HitInfo<'_> {
+        let p = ray.point_at_parameter(t);
+        let q = &self.coefficients.m;
+        let gradient = |i: usize| q[i][0] * p.x() + q[i][1] * p.y() + q[i][2] * p.z() + q[i][3];
+        let normal = Vec3::new(gradient(0), gradient(1), gradient(2)).normalized();
+
+        let offset = p - self.clip.center();
+        let phi = (-offset.z()).atan2(offset.x()) + PI;
+        let height = self.clip.max.y() - self.clip.min.y();
+        let v = if height.is_finite() && height > 0.0 {
+            (p.y() - self.clip.min.y()) / height
+        } else {
+            0.0
+        };
+
+        HitInfo {
+            t,
+            p,
+            normal,
+            u: phi / (2.0 * PI),
+            v,
+            color: None,
+            material: &*self.material,
+        }
+    }
+}
+
+impl Hitable for Quadric {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitInfo<'_>> {
+        let (o, d) = (ray.origin, ray.direction);
+        let o = [o.x(), o.y(), o.z(), 1.0];
+        let d = [d.x(), d.y(), d.z(), 0.0];
+
+        let roots = solve_quadratic(self.form(d, d), self.form(o, d), self.form(o, o))?;
+        let t = roots
+            .iter()
+            .copied()
+            .find(|&t| t > t_min && t < t_max && self.clip.contains(ray.point_at_parameter(t)))?;
+        Some(self.hit_info(ray, t))
+    }
+
+    fn bounds(&self) -> Aabb {
+        self.clip
+    }
+
+    fn intervals(&self, ray: &Ray) -> Vec<Interval<'_>> {
+        // Only the stretches that the surface closes off within the clip
+        // box on its own, such as the inside of an ellipsoid, count as solid
+        let (o, d) = (ray.origin, ray.direction);
+        let o = [o.x(), o.y(), o.z(), 1.0];
+        let d = [d.x(), d.y(), d.z(), 0.0];
+
+        // The coefficients are negative between the two crossings only when
+        // the quadratic opens upwards
+        let a = self.form(d, d);
+        match solve_quadratic(a, self.form(o, d), self.form(o, o)) {
+            Some([t0, t1])
+                if a > 0.0
+                    && t0 < t1
+                    && self.clip.contains(ray.point_at_parameter(t0))
+                    && self.clip.contains(ray.point_at_parameter(t1)) =>
+            {
+                vec![Interval {
+                    enter: self.hit_info(ray, t0),
+                    exit: self.hit_info(ray, t1),
+                }]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Ray in the frame of a shape, with the origin at `center` and `z` along
+/// its axis.
+struct Local {
+    frame: Onb,
+    origin: Vec3,
+    direction: Vec3,
+}
+
+impl Local {
+    fn new(ray: &Ray, center: Vec3, axis: Vec3) -> Local {
+        let frame = Onb::from_w(axis.normalized());
+        Local {
+            frame,
+            origin: frame.to_local(ray.origin - center),
+            direction: frame.to_local(ray.direction),
+        }
+    }
+
+    fn point(&self, t: f32) -> Vec3 {
+        self.origin + t * self.direction
+    }
+
+    /// Where the ray crosses the plane at height `z`, if it is not parallel.
+    fn plane(&self, z: f32) -> Option<f32> {
+        if self.direction.z() == 0.0 {
+            None
+        } else {
+            Some((z - self.origin.z()) / self.direction.z())
+        }
+    }
+
+    fn hit_info<'a>(
+        &self,
+        ray: &Ray,
+        t: f32,
+        normal: Vec3,
+        u: f32,
+        v: f32,
+        material: &'a dyn Material,
+    ) -> HitInfo<'a> {
+        HitInfo {
+            t,
+            p: ray.point_at_parameter(t),
+            normal: self.frame.to_world(normal).normalized(),
+            u,
+            v,
+            color: None,
+            material,
+        }
+    }
+}
+
+/// Nearest of several candidate intersections with the surfaces of a
+/// shape, in local coordinates.
+struct Closest {
+    t_min: f32,
+    t_max: f32,
+    hit: Option<(f32, Vec3, f32, f32)>,
+}
+
+impl Closest {
+    fn new(t_min: f32, t_max: f32) -> Closest {
+        Closest {
+            t_min,
+            t_max,
+            hit: None,
+        }
+    }
+
+    fn consider(&mut self, t: f32, normal: Vec3, u: f32, v: f32) {
+        if t > self.t_min && t < self.t_max {
+            self.t_max = t;
+            self.hit = Some((t, normal, u, v));
+        }
+    }
+}
+
+/// Angle around the local z axis, between 0 and 2 pi.
+fn angle(p: Vec3) -> f32 {
+    p.y().atan2(p.x()).rem_euclid(2.0 * PI)
+}
+
+fn diagonal_matrix(diagonal: [f32; 4]) -> Mat4 {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        row[i] = diagonal[i];
+    }
+    Mat4 { m }
+}
+
+/// Moves the quadric with coefficients `q` from the origin to `center`.
+fn centered(q: Mat4, center: Vec3) -> Mat4 {
+    let inverse = Mat4::translate(-center);
+    inverse.transpose() * q * inverse
+}
+
+/// Pairs up crossings of a closed surface into the intervals between
+/// them, after sorting them along the ray.
+fn pair_up(mut hits: Vec<HitInfo<'_>>) -> Vec<Interval<'_>> {
+    hits.sort_by(|a, b| a.t.total_cmp(&b.t));
+    hits.chunks_exact(2)
+        .map(|pair| Interval {
+            enter: pair[0],
+            exit: pair[1],
+        })
+        .collect()
+}
+
+/// Tight box around a disk facing `axis`.
+fn disk_bounds(center: Vec3, axis: Vec3, radius: f32) -> Aabb {
+    let n = axis.normalized();
+    let extent = |a: f32| radius * (1.0 - a * a).max(0.0).sqrt();
+    let e = Vec3::new(extent(n.x()), extent(n.y()), extent(n.z()));
+    Aabb::from_points(&[center - e, center + e])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_util::{assert_close, material};
+
+    #[test]
+    fn planes_and_disks() {
+        let plane = Plane {
+            point: Vec3::new(0.0, 1.0, 0.0),
+            normal: Vec3::up(),
+            material: material(),
+        };
+        let ray = Ray::new(Vec3::new(3.0, 5.0, -2.0), Vec3::new(0.0, -2.0, 0.0));
+        let hit = plane.hit(&ray, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-6);
+        assert_close(hit.normal, Vec3::up());
+        assert!((hit.u.hypot(hit.v) - 13f32.sqrt()).abs() < 1e-5);
+        assert!(plane.hit(&ray, 0.001, 1.5).is_none());
+        assert_eq!(plane.bounds().max.x(), f32::INFINITY);
+
+        let disk = Disk {
+            center: Vec3::zero(),
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            radius: 2.0,
+            material: material(),
+        };
+        let hit = disk
+            .hit(
+                &Ray::new(Vec3::new(1.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0)),
+                0.001,
+                f32::MAX,
+            )
+            .unwrap();
+        assert!((hit.v - 0.5).abs() < 1e-6);
+        let outside = Ray::new(Vec3::new(2.5, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(disk.hit(&outside, 0.001, f32::MAX).is_none());
+        assert_eq!(
+            disk.bounds(),
+            Aabb::from_points(&[Vec3::new(-2.0, -2.0, 0.0), Vec3::new(2.0, 2.0, 0.0)])
+        );
+    }
+
+    #[test]
+    fn cylinders_hit_sides_and_caps() {
+        let mut cylinder = Cylinder::new(Vec3::zero(), Vec3::new(0.0, 2.0, 0.0), 1.0, material());
+
+        // Through the side, halfway up
+        let side = Ray::new(Vec3::new(-5.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = cylinder.hit(&side, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-5);
+        assert_close(hit.normal, Vec3::new(-1.0, 0.0, 0.0));
+        assert!((hit.v - 0.5).abs() < 1e-5);
+
+        // Down onto the top cap, and then from inside out through the base
+        let down = Ray::new(Vec3::new(0.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = cylinder.hit(&down, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-5);
+        assert_close(hit.normal, Vec3::up());
+        let hit = cylinder.hit(&down, 3.5, f32::MAX).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-5);
+        assert_close(hit.normal, Vec3::down());
+
+        cylinder.capped = false;
+        assert!(cylinder.hit(&down, 0.001, f32::MAX).is_none());
+
+        // With half of the side cut away, only one of two opposite points
+        // is left
+        cylinder.sweep = 180.0;
+        let hits: Vec<_> = [-1.0, 1.0]
+            .iter()
+            .filter(|&&z| {
+                let ray = Ray::new(Vec3::new(0.0, 1.0, z * 5.0), Vec3::new(0.0, 0.0, -z));
+                cylinder.hit(&ray, 0.001, 4.5).is_some()
+            })
+            .collect();
+        assert_eq!(hits.len(), 1);
+
+        let bounds = cylinder.bounds();
+        assert_close(bounds.min, Vec3::new(-1.0, 0.0, -1.0));
+        assert_close(bounds.max, Vec3::new(1.0, 2.0, 1.0));
+    }
+
+    #[test]
+    fn cone_normals_lean_towards_the_apex() {
+        let cone = Cone::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 1.0, material());
+
+        let ray = Ray::new(Vec3::new(-5.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = cone.hit(&ray, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-5);
+        assert_close(hit.normal, Vec3::new(-1.0, 1.0, 0.0).normalized());
+
+        // Just above the apex, and through the base
+        let above = Ray::new(Vec3::new(-5.0, 1.01, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(cone.hit(&above, 0.001, f32::MAX).is_none());
+        let up = Ray::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let hit = cone.hit(&up, 0.001, f32::MAX).unwrap();
+        assert_close(hit.normal, Vec3::down());
+
+        assert_close(cone.bounds().max, Vec3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn tori_hit_the_tube_but_not_the_hole() {
+        let torus = Torus {
+            center: Vec3::zero(),
+            axis: Vec3::up(),
+            major_radius: 2.0,
+            minor_radius: 0.5,
+            material: material(),
+        };
+
+        let side = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = torus.hit(&side, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 2.5).abs() < 1e-4);
+        assert_close(hit.normal, Vec3::new(-1.0, 0.0, 0.0));
+        // And from inside the hole out through the other side
+        let hit = torus.hit(&side, 4.0, f32::MAX).unwrap();
+        assert!((hit.t - 6.5).abs() < 1e-4);
+
+        let top = Ray::new(Vec3::new(2.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = torus.hit(&top, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-4);
+        assert_close(hit.normal, Vec3::up());
+        assert!((hit.v - 0.25).abs() < 1e-4);
+
+        let intervals = torus.intervals(&side);
+        assert_eq!(intervals.len(), 2);
+        assert!((intervals[1].enter.t - 6.5).abs() < 1e-4);
+
+        let hole = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(torus.hit(&hole, 0.001, f32::MAX).is_none());
+
+        // From far away, where the quartic loses precision without the
+        // shifted origin
+        let far = Ray::new(Vec3::new(-1000.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = torus.hit(&far, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 997.5).abs() < 1e-3);
+
+        assert_close(torus.bounds().max, Vec3::new(2.5, 0.5, 2.5));
+    }
+
+    #[test]
+    fn quadrics_from_coefficient_matrices() {
+        let ellipsoid = Quadric::ellipsoid(
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(2.0, 1.0, 1.0),
+            material(),
+        );
+        let down = Ray::new(Vec3::new(1.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = ellipsoid.hit(&down, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-4);
+        assert_close(hit.normal, Vec3::up());
+        let side = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = ellipsoid.hit(&side, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-4);
+        assert_close(hit.normal, Vec3::new(-1.0, 0.0, 0.0));
+
+        let paraboloid = Quadric::paraboloid(Vec3::zero(), 1.0, 1.0, material());
+        let hit = paraboloid
+            .hit(
+                &Ray::new(Vec3::new(0.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+                0.001,
+                f32::MAX,
+            )
+            .unwrap();
+        assert!((hit.t - 4.75).abs() < 1e-4);
+        assert!((hit.v - 0.25).abs() < 1e-4);
+        // Above the rim, where the surface is clipped away
+        let above = Ray::new(Vec3::new(-5.0, 2.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(paraboloid.hit(&above, 0.001, f32::MAX).is_none());
+
+        let hyperboloid = Quadric::hyperboloid(Vec3::zero(), 1.0, 2.0, 1.0, material());
+        let hit = hyperboloid.hit(&side, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-4);
+        let rim = Ray::new(Vec3::new(-5.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = hyperboloid.hit(&rim, 0.001, f32::MAX).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-3);
+        let axis = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(hyperboloid.hit(&axis, 0.001, f32::MAX).is_none());
+    }
+}
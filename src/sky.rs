@@ -0,0 +1,313 @@
+use std::f32::consts::PI;
+
+use crate::environment::Environment;
+use crate::vec3::{Onb, Vec3};
+
+use rand::prelude::*;
+
+/// Angular radius of the sun seen from earth, in radians.
+const SUN_ANGULAR_RADIUS: f32 = 0.004_65;
+
+/// Luminance of the sun outside the atmosphere, in kcd/m^2 like the sky.
+const SUN_LUMINANCE: f32 = 1.6e6;
+
+/// Daylight sky following Preetham, Shirley and Smits' "A Practical
+/// Analytic Model for Daylight" (1999), with the sun as a disk of the
+/// correct angular size.
+///
+/// The sun elevation is measured from the horizon and its azimuth from the
+/// `+x` axis towards `+z`, both in degrees. Radiance is in kcd/m^2 scaled
+/// by `intensity`, whose default maps a sunlit white surface to roughly one.
+pub struct PhysicalSky {
+    pub sun_direction: Vec3,
+    pub turbidity: f32,
+    pub intensity: f32,
+    /// Fraction of the light reflected back up by the ground below the
+    /// horizon.
+    pub ground_albedo: f32,
+    /// Perez coefficients for the luminance and the two chromaticities.
+    perez: [[f32; 5]; 3],
+    zenith: [f32; 3],
+    sun_radiance: Vec3,
+}
+
+impl PhysicalSky {
+    pub fn new(elevation: f32, azimuth: f32, turbidity: f32) -> PhysicalSky {
+        let elevation = elevation.to_radians();
+        let azimuth = azimuth.to_radians();
+        let t = turbidity;
+
+        let sun_direction = Vec3::new(
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+            elevation.cos() * azimuth.sin(),
+        );
+
+        // The model is only defined for the sun above the horizon
+        let theta_s = (PI / 2.0 - elevation).clamp(0.0, PI / 2.0);
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let zenith_chromaticity = |m: [[f32; 4]; 3]| {
+            let thetas = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+            let ts = [t * t, t, 1.0];
+            let mut sum = 0.0;
+            for (i, row) in m.iter().enumerate() {
+                for (j, c) in row.iter().enumerate() {
+                    sum += ts[i] * c * thetas[j];
+                }
+            }
+            sum
+        };
+
+        let zenith_x = zenith_chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = zenith_chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        // Scale the zenith values so that F(theta, gamma) can be used as is
+        let mut zenith = [zenith_luminance, zenith_x, zenith_y];
+        for (z, coeffs) in zenith.iter_mut().zip(perez.iter()) {
+            *z /= perez_f(coeffs, 0.0, theta_s);
+        }
+
+        let sun_radiance = if elevation > 0.0 {
+            SUN_LUMINANCE * sun_transmittance(theta_s, t)
+        } else {
+            Vec3::zero()
+        };
+
+        PhysicalSky {
+            sun_direction,
+            turbidity,
+            intensity: 0.025,
+            ground_albedo: 0.3,
+            perez,
+            zenith,
+            sun_radiance,
+        }
+    }
+
+    /// Radiance of the sky dome alone, without the sun.
+    pub fn sky_radiance(&self, direction: Vec3) -> Vec3 {
+        if direction.y() < 0.0 {
+            // Light from the sky bounced off a diffuse ground
+            let horizon = Vec3::new(direction.x(), 0.0, direction.z());
+            if horizon.lenght_squared() == 0.0 {
+                return Vec3::zero();
+            }
+            return self.ground_albedo * self.sky_radiance(horizon.normalized());
+        }
+
+        let theta = direction.y().clamp(1e-3, 1.0).acos();
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+
+        let luminance = self.zenith[0] * perez_f(&self.perez[0], theta, gamma);
+        let x = self.zenith[1] * perez_f(&self.perez[1], theta, gamma);
+        let y = self.zenith[2] * perez_f(&self.perez[2], theta, gamma);
+
+        self.intensity * xyy_to_rgb(x, y, luminance.max(0.0))
+    }
+
+    fn sun_solid_angle(&self) -> f32 {
+        2.0 * PI * sun_one_minus_cos()
+    }
+
+    /// Whether `direction` is within the sun disk. The cosine of the sun's
+    /// radius rounds to within a few ulps of 1, so this compares the
+    /// distance between the unit vectors instead.
+    fn in_sun(&self, direction: Vec3) -> bool {
+        (direction - self.sun_direction).lenght_squared() <= 2.0 * sun_one_minus_cos()
+    }
+
+    fn sun_probability(&self) -> f32 {
+        if self.sun_radiance == Vec3::zero() {
+            0.0
+        } else {
+            0.5
+        }
+    }
+}
+
+impl Environment for PhysicalSky {
+    fn radiance(&self, direction: Vec3) -> Vec3 {
+        let mut radiance = self.sky_radiance(direction);
+
+        if self.in_sun(direction) {
+            radiance += self.intensity * self.sun_radiance;
+        }
+
+        radiance
+    }
+
+    /// Picks the sun disk half of the time, and otherwise a uniformly
+    /// distributed direction for the smooth sky dome.
+    fn sample(&self, rng: &mut dyn rand::RngCore) -> Option<(Vec3, Vec3, f32)> {
+        let direction = if rng.gen::<f32>() < self.sun_probability() {
+            // Sampled as 1 - cos(theta) to keep the precision near the center
+            let one_minus_cos = rng.gen::<f32>() * sun_one_minus_cos();
+            let cos_theta = 1.0 - one_minus_cos;
+            let sin_theta = (one_minus_cos * (2.0 - one_minus_cos)).sqrt();
+            let phi = 2.0 * PI * rng.gen::<f32>();
+
+            Onb::from_w(self.sun_direction).to_world(Vec3::new(
+                sin_theta * phi.cos(),
+                sin_theta * phi.sin(),
+                cos_theta,
+            ))
+        } else {
+            Vec3::random_in_unit_sphere(rng).normalized()
+        };
+
+        let pdf = self.pdf(direction);
+        if pdf == 0.0 {
+            return None;
+        }
+
+        Some((direction, self.radiance(direction), pdf))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f32 {
+        let p_sun = self.sun_probability();
+        let mut pdf = (1.0 - p_sun) / (4.0 * PI);
+
+        if self.in_sun(direction) {
+            pdf += p_sun / self.sun_solid_angle();
+        }
+
+        pdf
+    }
+}
+
+/// 1 - cos of the sun's angular radius, written to avoid cancellation.
+fn sun_one_minus_cos() -> f32 {
+    2.0 * (SUN_ANGULAR_RADIUS / 2.0).sin().powi(2)
+}
+
+/// Perez et al. sky luminance distribution.
+fn perez_f(c: &[f32; 5], theta: f32, gamma: f32) -> f32 {
+    (1.0 + c[0] * (c[1] / theta.cos()).exp())
+        * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos() * gamma.cos())
+}
+
+/// Attenuation of sunlight through the atmosphere by Rayleigh and aerosol
+/// scattering, evaluated at representative wavelengths for red, green and
+/// blue (Preetham et al., appendix).
+fn sun_transmittance(theta_s: f32, turbidity: f32) -> Vec3 {
+    let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+
+    let transmittance = |lambda: f32| {
+        let rayleigh = (-0.008_735 * lambda.powf(-4.08) * air_mass).exp();
+        let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+        rayleigh * aerosol
+    };
+
+    // Wavelengths in micrometers
+    Vec3::new(
+        transmittance(0.680),
+        transmittance(0.550),
+        transmittance(0.440),
+    )
+}
+
+fn xyy_to_rgb(x: f32, y: f32, luminance: f32) -> Vec3 {
+    if y <= 0.0 {
+        return Vec3::zero();
+    }
+
+    let cx = x / y * luminance;
+    let cz = (1.0 - x - y) / y * luminance;
+
+    // CIE XYZ to linear sRGB
+    let rgb = Vec3::new(
+        3.2406 * cx - 1.5372 * luminance - 0.4986 * cz,
+        -0.9689 * cx + 1.8758 * luminance + 0.0415 * cz,
+        0.0557 * cx - 0.2040 * luminance + 1.0570 * cz,
+    );
+
+    Vec3::new(rgb.r().max(0.0), rgb.g().max(0.0), rgb.b().max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::rng::Pcg32;
+
+    #[test]
+    fn sky_is_brightest_around_the_sun() {
+        let sky = PhysicalSky::new(30.0, 90.0, 3.0);
+
+        let near_sun = sky.sky_radiance(
+            Onb::from_w(sky.sun_direction).to_world(Vec3::new(0.1, 0.0, 1.0).normalized()),
+        );
+        let away = sky.sky_radiance(Vec3::new(0.0, 0.5, -1.0).normalized());
+
+        assert!(near_sun.g() > away.g());
+        assert!(away.g() > 0.0 && away.g().is_finite());
+
+        // Clear skies are blue away from the sun
+        assert!(away.b() > away.r());
+    }
+
+    #[test]
+    fn sun_samples_match_pdf() {
+        let sky = PhysicalSky::new(45.0, 0.0, 2.5);
+        let mut rng = Pcg32::new(11, 3);
+
+        // Half of the samples go to a cone of 1 - cos(r), worked out in f64
+        let one_minus_cos = 1.0 - f64::from(SUN_ANGULAR_RADIUS).cos();
+        let sky_pdf = 0.5 / (4.0 * std::f64::consts::PI);
+        let sun_pdf = sky_pdf + 0.5 / (2.0 * std::f64::consts::PI * one_minus_cos);
+
+        let mut in_sun = 0;
+        for _ in 0..1000 {
+            let (direction, radiance, pdf) = sky.sample(&mut rng).unwrap();
+            assert!((direction.lenght() - 1.0).abs() < 1e-4);
+            assert_eq!(radiance, sky.radiance(direction));
+
+            // Directions right at the rim of the disk may go either way
+            let cos = f64::from(direction.dot(sky.sun_direction));
+            let off_center = (1.0 - cos).max(0.0);
+            if off_center < 0.99 * one_minus_cos {
+                in_sun += 1;
+                assert!((f64::from(pdf) - sun_pdf).abs() < 1e-3 * sun_pdf, "{}", pdf);
+            } else if off_center > 1.01 * one_minus_cos {
+                assert!((f64::from(pdf) - sky_pdf).abs() < 1e-6, "{}", pdf);
+            }
+        }
+        assert!((400..600).contains(&in_sun), "{}", in_sun);
+    }
+}
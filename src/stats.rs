@@ -0,0 +1,160 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Event counter that can be bumped from anywhere in the renderer.
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub const fn new() -> Counter {
+        Counter(AtomicU64::new(0))
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub static CAMERA_RAYS: Counter = Counter::new();
+pub static BOUNCE_RAYS: Counter = Counter::new();
+pub static SHADOW_RAYS: Counter = Counter::new();
+pub static NODE_VISITS: Counter = Counter::new();
+pub static PRIMITIVE_TESTS: Counter = Counter::new();
+
+/// Counters and phase timings of a finished render.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    pub camera_rays: u64,
+    pub bounce_rays: u64,
+    pub shadow_rays: u64,
+    pub node_visits: u64,
+    pub primitive_tests: u64,
+    pub scene_time: Duration,
+    pub render_time: Duration,
+    pub output_time: Duration,
+}
+
+impl RenderStats {
+    /// Reads the global counters, combined with the given phase timings.
+    pub fn collect(
+        scene_time: Duration,
+        render_time: Duration,
+        output_time: Duration,
+    ) -> RenderStats {
+        RenderStats {
+            camera_rays: CAMERA_RAYS.get(),
+            bounce_rays: BOUNCE_RAYS.get(),
+            shadow_rays: SHADOW_RAYS.get(),
+            node_visits: NODE_VISITS.get(),
+            primitive_tests: PRIMITIVE_TESTS.get(),
+            scene_time,
+            render_time,
+            output_time,
+        }
+    }
+
+    pub fn total_rays(&self) -> u64 {
+        self.camera_rays + self.bounce_rays + self.shadow_rays
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        ratio(self.total_rays(), self.render_time.as_secs_f64())
+    }
+
+    /// Average number of segments in a path, counting the camera ray.
+    pub fn average_path_length(&self) -> f64 {
+        ratio(self.camera_rays + self.bounce_rays, self.camera_rays as f64)
+    }
+
+    pub fn node_visits_per_ray(&self) -> f64 {
+        ratio(self.node_visits, self.total_rays() as f64)
+    }
+
+    pub fn primitive_tests_per_ray(&self) -> f64 {
+        ratio(self.primitive_tests, self.total_rays() as f64)
+    }
+
+    /// Human readable report.
+    pub fn summary(self) -> String {
+        format!(
+            "Rays: {} camera, {} bounce, {} shadow, {:.0} per second\n\
+             Average path length: {:.2}\n\
+             Per ray: {:.2} node visits, {:.2} primitive tests\n\
+             Time: {:.3}s scene, {:.3}s render, {:.3}s output",
+            self.camera_rays,
+            self.bounce_rays,
+            self.shadow_rays,
+            self.rays_per_second(),
+            self.average_path_length(),
+            self.node_visits_per_ray(),
+            self.primitive_tests_per_ray(),
+            self.scene_time.as_secs_f64(),
+            self.render_time.as_secs_f64(),
+            self.output_time.as_secs_f64(),
+        )
+    }
+
+    pub fn to_json(self) -> String {
+        format!(
+            "{{\n  \"rays\": {{\"camera\": {}, \"bounce\": {}, \"shadow\": {}, \"total\": {}}},\n  \
+             \"rays_per_second\": {:.1},\n  \
+             \"average_path_length\": {:.4},\n  \
+             \"node_visits\": {},\n  \
+             \"primitive_tests\": {},\n  \
+             \"node_visits_per_ray\": {:.4},\n  \
+             \"primitive_tests_per_ray\": {:.4},\n  \
+             \"time_seconds\": {{\"scene\": {:.3}, \"render\": {:.3}, \"output\": {:.3}}}\n}}\n",
+            self.camera_rays,
+            self.bounce_rays,
+            self.shadow_rays,
+            self.total_rays(),
+            self.rays_per_second(),
+            self.average_path_length(),
+            self.node_visits,
+            self.primitive_tests,
+            self.node_visits_per_ray(),
+            self.primitive_tests_per_ray(),
+            self.scene_time.as_secs_f64(),
+            self.render_time.as_secs_f64(),
+            self.output_time.as_secs_f64(),
+        )
+    }
+}
+
+fn ratio(a: u64, b: f64) -> f64 {
+    if b > 0.0 {
+        a as f64 / b
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_statistics() {
+        let stats = RenderStats {
+            camera_rays: 100,
+            bounce_rays: 150,
+            shadow_rays: 250,
+            primitive_tests: 5000,
+            render_time: Duration::from_millis(250),
+            ..RenderStats::default()
+        };
+
+        assert_eq!(stats.total_rays(), 500);
+        assert_eq!(stats.rays_per_second(), 2000.0);
+        assert_eq!(stats.average_path_length(), 2.5);
+        assert_eq!(stats.primitive_tests_per_ray(), 10.0);
+        assert_eq!(stats.node_visits_per_ray(), 0.0);
+        assert!(stats.to_json().contains("\"total\": 500"));
+
+        // Nothing rendered at all
+        assert_eq!(RenderStats::default().average_path_length(), 0.0);
+    }
+}
@@ -0,0 +1,232 @@
+use crate::camera::{look_at_basis, Camera, EquirectangularCamera, PerspectiveCamera};
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+/// How the two eyes are packed into a single frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StereoLayout {
+    /// Left eye in the left half, right eye in the right half.
+    SideBySide,
+    /// Left eye in the top half, right eye in the bottom half.
+    TopBottom,
+}
+
+impl StereoLayout {
+    /// Aspect ratio of a single eye in a frame of the given aspect ratio.
+    pub fn eye_aspect(self, aspect: f32) -> f32 {
+        match self {
+            StereoLayout::SideBySide => aspect / 2.0,
+            StereoLayout::TopBottom => aspect * 2.0,
+        }
+    }
+}
+
+/// Renders two cameras into the halves of one frame.
+pub struct StereoCamera {
+    pub left: Box<dyn Camera>,
+    pub right: Box<dyn Camera>,
+    pub layout: StereoLayout,
+}
+
+impl StereoCamera {
+    /// Pair of off-axis perspective cameras `interocular` units apart,
+    /// created by `eye` from a shifted eye position and target.
+    ///
+    /// Both eyes look in the same direction and their frustums are sheared
+    /// so that objects `convergence` units away appear at the same place in
+    /// both images, which avoids the vertical parallax of toed-in cameras.
+    /// An infinite convergence distance gives parallel frustums. The eyes
+    /// should use the aspect ratio given by `StereoLayout::eye_aspect`.
+    pub fn perspective<F>(
+        look_from: Vec3,
+        look_at: Vec3,
+        up: Vec3,
+        interocular: f32,
+        convergence: f32,
+        layout: StereoLayout,
+        eye: F,
+    ) -> StereoCamera
+    where
+        F: Fn(Vec3, Vec3) -> PerspectiveCamera,
+    {
+        let (u, _, _) = look_at_basis(look_from, look_at, up);
+
+        let shifted = |offset: f32| {
+            let shift = offset * u;
+            let mut camera = eye(look_from + shift, look_at + shift);
+
+            // Move the image window back towards the center line so that it
+            // is shared by both eyes at the convergence distance
+            let center = camera.lower_left_corner + camera.horizontal / 2.0 + camera.vertical / 2.0;
+            let focus_dist = (center - camera.origin).lenght();
+            camera.lower_left_corner -= shift * (focus_dist / convergence);
+            camera
+        };
+
+        StereoCamera {
+            left: Box::new(shifted(-interocular / 2.0)),
+            right: Box::new(shifted(interocular / 2.0)),
+            layout,
+        }
+    }
+
+    /// Omnidirectional stereo panorama pair, see `OdsCamera`.
+    pub fn ods(
+        look_from: Vec3,
+        look_at: Vec3,
+        up: Vec3,
+        interocular: f32,
+        convergence: f32,
+        layout: StereoLayout,
+    ) -> StereoCamera {
+        StereoCamera {
+            left: Box::new(OdsCamera::new(
+                look_from,
+                look_at,
+                up,
+                -interocular / 2.0,
+                convergence,
+            )),
+            right: Box::new(OdsCamera::new(
+                look_from,
+                look_at,
+                up,
+                interocular / 2.0,
+                convergence,
+            )),
+            layout,
+        }
+    }
+}
+
+impl Camera for StereoCamera {
+    fn get_ray(&self, u: f32, v: f32, rng: &mut dyn rand::RngCore) -> Option<Ray> {
+        match self.layout {
+            StereoLayout::SideBySide if u < 0.5 => self.left.get_ray(u * 2.0, v, rng),
+            StereoLayout::SideBySide => self.right.get_ray(u * 2.0 - 1.0, v, rng),
+            StereoLayout::TopBottom if v >= 0.5 => self.left.get_ray(u, v * 2.0 - 1.0, rng),
+            StereoLayout::TopBottom => self.right.get_ray(u, v * 2.0, rng),
+        }
+    }
+}
+
+/// One eye of an omnidirectional stereo (ODS) panorama.
+///
+/// Every column of the equirectangular image is seen from a different
+/// point on a horizontal circle of radius `|eye_offset|` around the
+/// origin, as if the viewer turned their head to look in that direction.
+/// Negative offsets give the left eye. Rays are turned inwards to meet in
+/// front of the viewer at the `convergence` distance, or stay parallel to
+/// the panorama direction when it is infinite.
+pub struct OdsCamera {
+    pub panorama: EquirectangularCamera,
+    pub eye_offset: f32,
+    pub convergence: f32,
+    up: Vec3,
+}
+
+impl OdsCamera {
+    pub fn new(
+        look_from: Vec3,
+        look_at: Vec3,
+        up: Vec3,
+        eye_offset: f32,
+        convergence: f32,
+    ) -> OdsCamera {
+        let (_, v, _) = look_at_basis(look_from, look_at, up);
+
+        OdsCamera {
+            panorama: EquirectangularCamera::new(look_from, look_at, up),
+            eye_offset,
+            convergence,
+            up: v,
+        }
+    }
+}
+
+impl Camera for OdsCamera {
+    fn get_ray(&self, u: f32, v: f32, _: &mut dyn rand::RngCore) -> Option<Ray> {
+        let direction = self.panorama.direction(u, v);
+
+        // The eyes sit on the horizon circle regardless of the latitude
+        let forward = self.panorama.direction(u, 0.5);
+        let right = forward.cross(self.up);
+        let origin = self.panorama.origin + self.eye_offset * right;
+
+        let direction = if self.convergence.is_finite() {
+            let target = self.panorama.origin + self.convergence * direction;
+            target - origin
+        } else {
+            direction
+        };
+
+        Some(Ray::new(origin, direction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance_to_point(ray: &Ray, p: Vec3) -> f32 {
+        let d = ray.direction.normalized();
+        let to_p = p - ray.origin;
+        (to_p - to_p.dot(d) * d).lenght()
+    }
+
+    #[test]
+    fn perspective_eyes_converge() {
+        let camera = StereoCamera::perspective(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::up(),
+            0.065,
+            3.0,
+            StereoLayout::SideBySide,
+            |from, at| PerspectiveCamera::new(from, at, Vec3::up(), 40.0, 1.0, 0.0, 1.0),
+        );
+        let mut rng = rand::thread_rng();
+
+        // The same spot in both halves looks at the same point on the
+        // convergence plane
+        for &(u, v) in &[(0.25, 0.5), (0.1, 0.8), (0.4, 0.2)] {
+            let left = camera.get_ray(u, v, &mut rng).unwrap();
+            let right = camera.get_ray(u + 0.5, v, &mut rng).unwrap();
+
+            let t = -3.0 / left.direction.z();
+            let p = left.point_at_parameter(t);
+
+            assert!((left.origin.x() + 0.0325).abs() < 1e-6);
+            assert!((right.origin.x() - 0.0325).abs() < 1e-6);
+            assert!(distance_to_point(&right, p) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn ods_eyes_are_tangent_to_the_view_circle() {
+        let camera = StereoCamera::ods(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(1.0, 2.0, 0.0),
+            Vec3::up(),
+            0.065,
+            f32::INFINITY,
+            StereoLayout::TopBottom,
+        );
+        let mut rng = rand::thread_rng();
+
+        for &(u, v) in &[(0.5, 0.5), (0.1, 0.7), (0.8, 0.3)] {
+            let left = camera.get_ray(u, v * 0.5 + 0.5, &mut rng).unwrap();
+            let right = camera.get_ray(u, v * 0.5, &mut rng).unwrap();
+
+            let baseline = right.origin - left.origin;
+            assert!((baseline.lenght() - 0.065).abs() < 1e-5);
+            assert!(baseline.y().abs() < 1e-6);
+            assert!(baseline.dot(left.direction).abs() < 1e-5);
+            assert!((left.direction - right.direction).lenght() < 1e-6);
+        }
+
+        // Looking straight ahead, the right eye is to the right
+        let right = camera.get_ray(0.5, 0.25, &mut rng).unwrap();
+        assert!(right.origin.x() > 1.0);
+    }
+}
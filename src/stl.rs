@@ -0,0 +1,168 @@
+use std::io::{self, Read};
+
+use crate::error::invalid_data;
+use crate::mesh::MeshData;
+use crate::vec3::Vec3;
+
+/// Size of a binary STL header plus the triangle count.
+const HEADER_SIZE: usize = 84;
+/// Normal, three vertices and the attribute byte count of a triangle.
+const TRIANGLE_SIZE: usize = 50;
+
+/// Decodes an ASCII or binary STL file.
+///
+/// STL stores every triangle separately, so vertices are not shared and
+/// the facet normals are ignored in favor of the vertex winding. Binary
+/// files may also start with `solid`, so the encoding is decided by
+/// whether the size matches the triangle count of a binary file.
+pub fn decode<R: Read>(mut reader: R) -> io::Result<MeshData> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    let binary_count = if data.len() >= HEADER_SIZE {
+        u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize
+    } else {
+        0
+    };
+
+    let mesh =
+        if data.len() >= HEADER_SIZE && data.len() == HEADER_SIZE + binary_count * TRIANGLE_SIZE {
+            decode_binary(&data[HEADER_SIZE..], binary_count)?
+        } else if data.starts_with(b"solid") {
+            decode_ascii(&String::from_utf8_lossy(&data))?
+        } else {
+            return Err(invalid_data("not an STL file"));
+        };
+
+    // Anything starting with "solid" gets this far, so an empty result
+    // more likely means a different file than an empty model
+    if mesh.triangles.is_empty() {
+        return Err(invalid_data("no facets in STL file"));
+    }
+
+    mesh.validate()?;
+    Ok(mesh)
+}
+
+fn decode_binary(data: &[u8], count: usize) -> io::Result<MeshData> {
+    let mut mesh = MeshData::default();
+
+    for triangle in data.chunks_exact(TRIANGLE_SIZE).take(count) {
+        let f = |i: usize| {
+            let o = 12 + 4 * i;
+            f32::from_le_bytes([
+                triangle[o],
+                triangle[o + 1],
+                triangle[o + 2],
+                triangle[o + 3],
+            ])
+        };
+
+        let first = mesh.positions.len() as u32;
+        for v in 0..3 {
+            mesh.positions
+                .push(Vec3::new(f(3 * v), f(3 * v + 1), f(3 * v + 2)));
+        }
+        mesh.triangles.push([first, first + 1, first + 2]);
+    }
+
+    Ok(mesh)
+}
+
+fn decode_ascii(text: &str) -> io::Result<MeshData> {
+    let mut mesh = MeshData::default();
+    let mut tokens = text.split_ascii_whitespace();
+    let mut facet = Vec::with_capacity(3);
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "vertex" => {
+                let mut coordinate = || -> io::Result<f32> {
+                    let token = tokens
+                        .next()
+                        .ok_or_else(|| invalid_data("unexpected end of file"))?;
+                    token
+                        .parse()
+                        .map_err(|_| invalid_data(&format!("invalid number {}", token)))
+                };
+                facet.push(Vec3::new(coordinate()?, coordinate()?, coordinate()?));
+            }
+            "endloop" => {
+                if facet.len() < 3 {
+                    return Err(invalid_data(&format!(
+                        "facet with {} vertices, expected at least 3",
+                        facet.len()
+                    )));
+                }
+
+                // Polygons with more than three vertices are split into fans
+                let first = mesh.positions.len() as u32;
+                mesh.positions.extend(&facet);
+                for k in 1..facet.len() as u32 - 1 {
+                    mesh.triangles.push([first, first + k, first + k + 1]);
+                }
+                facet.clear();
+            }
+            _ => {}
+        }
+    }
+
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_ascii() {
+        let text = "solid cube\n\
+            facet normal 0 0 1\n\
+              outer loop\n\
+                vertex 0 0 0\n\
+                vertex 1 0 0\n\
+                vertex 1 1.5 0\n\
+              endloop\n\
+            endfacet\n\
+            endsolid cube\n";
+
+        let mesh = decode(text.as_bytes()).unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+        assert_eq!(mesh.positions[2], Vec3::new(1.0, 1.5, 0.0));
+    }
+
+    #[test]
+    fn decodes_binary_starting_with_solid() {
+        let mut data = b"solid but actually binary".to_vec();
+        data.resize(80, 0);
+        data.extend(2u32.to_le_bytes());
+        for t in 0..2 {
+            data.extend([0u8; 12]);
+            for v in 0..9 {
+                data.extend(((t * 9 + v) as f32).to_le_bytes());
+            }
+            data.extend([0u8; 2]);
+        }
+
+        let mesh = decode(&data[..]).unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [3, 4, 5]]);
+        assert_eq!(mesh.positions[4], Vec3::new(12.0, 13.0, 14.0));
+    }
+
+    #[test]
+    fn rejects_files_without_facets() {
+        assert!(decode(&b"solid"[..]).is_err());
+        assert!(decode(&b"solidarity with the workers\n"[..]).is_err());
+        assert!(decode(&b"not an stl file at all"[..]).is_err());
+
+        let short = "solid bad\n\
+            facet normal 0 0 1\n\
+              outer loop\n\
+                vertex 0 0 0\n\
+                vertex 1 0 0\n\
+              endloop\n\
+            endfacet\n\
+            endsolid bad\n";
+        assert!(decode(short.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,15 @@
+use std::sync::Arc;
+
+use crate::material::{Lambertian, Material};
+use crate::vec3::Vec3;
+
+/// Plain gray material for tests that only care about geometry.
+pub fn material() -> Arc<dyn Material> {
+    Arc::new(Lambertian {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+    })
+}
+
+pub fn assert_close(a: Vec3, b: Vec3) {
+    assert!((a - b).lenght() < 1e-4, "{:?} != {:?}", a, b);
+}
@@ -0,0 +1,287 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::geometry::HitInfo;
+use crate::hdr;
+use crate::vec3::Vec3;
+
+/// Spatially varying material parameter.
+///
+/// Scalar parameters read the first channel of the returned value.
+pub trait Texture: Send + Sync {
+    fn value(&self, hit: &HitInfo) -> Vec3;
+}
+
+impl Texture for Vec3 {
+    fn value(&self, _: &HitInfo) -> Vec3 {
+        *self
+    }
+}
+
+impl Texture for f32 {
+    fn value(&self, _: &HitInfo) -> Vec3 {
+        Vec3::new(*self, *self, *self)
+    }
+}
+
+/// 3D checkerboard alternating between two textures every `scale` units.
+pub struct Checker {
+    pub odd: Arc<dyn Texture>,
+    pub even: Arc<dyn Texture>,
+    pub scale: f32,
+}
+
+impl Texture for Checker {
+    fn value(&self, hit: &HitInfo) -> Vec3 {
+        let p = hit.p / self.scale;
+        let sum = p.x().floor() + p.y().floor() + p.z().floor();
+
+        if sum.rem_euclid(2.0) < 1.0 {
+            self.even.value(hit)
+        } else {
+            self.odd.value(hit)
+        }
+    }
+}
+
+/// Broadcasts a single channel of another texture, e.g. the roughness
+/// stored in the green channel of a packed metallic-roughness map.
+pub struct Channel {
+    pub texture: Arc<dyn Texture>,
+    pub channel: usize,
+}
+
+impl Texture for Channel {
+    fn value(&self, hit: &HitInfo) -> Vec3 {
+        let c = self.texture.value(hit)[self.channel];
+        Vec3::new(c, c, c)
+    }
+}
+
+/// Color interpolated from the vertices of a mesh, or `fallback` for
+/// surfaces without vertex colors.
+pub struct VertexColor {
+    pub fallback: Arc<dyn Texture>,
+}
+
+impl Texture for VertexColor {
+    fn value(&self, hit: &HitInfo) -> Vec3 {
+        match hit.color {
+            Some(color) => color,
+            None => self.fallback.value(hit),
+        }
+    }
+}
+
+/// Multiplies two textures, typically a constant factor with an image.
+pub struct Product {
+    pub a: Arc<dyn Texture>,
+    pub b: Arc<dyn Texture>,
+}
+
+impl Texture for Product {
+    fn value(&self, hit: &HitInfo) -> Vec3 {
+        self.a.value(hit) * self.b.value(hit)
+    }
+}
+
+/// Bitmap sampled with wrapping texture coordinates and bilinear filtering.
+///
+/// `v` grows upwards, so `v = 0` is the bottom row of the image.
+pub struct ImageTexture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Vec3>,
+}
+
+impl ImageTexture {
+    /// Loads a PNG or Radiance HDR file. Color data in PNGs is usually
+    /// stored in sRGB and should be linearized, while data maps such as
+    /// roughness are already linear. HDR files are always linear.
+    pub fn load(path: &Path, srgb: bool) -> io::Result<ImageTexture> {
+        let file = BufReader::new(File::open(path)?);
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("hdr") => hdr::decode(file),
+            _ => ImageTexture::from_png(file, srgb),
+        }
+    }
+
+    pub fn from_png<R: Read>(reader: R, srgb: bool) -> io::Result<ImageTexture> {
+        let decoder = png::Decoder::new(reader);
+        let (info, mut reader) = decoder
+            .read_info()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        let mut buf = vec![0; info.buffer_size()];
+        reader
+            .next_frame(&mut buf)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        let channels = info.line_size / info.width as usize;
+        let decode = |c: u8| {
+            let c = f32::from(c) / 255.0;
+            if srgb {
+                srgb_to_linear(c)
+            } else {
+                c
+            }
+        };
+
+        let mut pixels = Vec::with_capacity((info.width * info.height) as usize);
+        for y in 0..info.height as usize {
+            let row = &buf[y * info.line_size..];
+            for x in 0..info.width as usize {
+                let texel = &row[x * channels..];
+                pixels.push(match channels {
+                    1 | 2 => Vec3::new(decode(texel[0]), decode(texel[0]), decode(texel[0])),
+                    _ => Vec3::new(decode(texel[0]), decode(texel[1]), decode(texel[2])),
+                });
+            }
+        }
+
+        Ok(ImageTexture {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Vec3 {
+        let x = x.rem_euclid(i64::from(self.width)) as u32;
+        let y = y.rem_euclid(i64::from(self.height)) as u32;
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn sample(&self, u: f32, v: f32) -> Vec3 {
+        let x = u * self.width as f32 - 0.5;
+        let y = (1.0 - v) * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        (1.0 - fy) * ((1.0 - fx) * self.texel(x0, y0) + fx * self.texel(x0 + 1, y0))
+            + fy * ((1.0 - fx) * self.texel(x0, y0 + 1) + fx * self.texel(x0 + 1, y0 + 1))
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, hit: &HitInfo) -> Vec3 {
+        self.sample(hit.u, hit.v)
+    }
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use png::HasParameters;
+
+    use crate::material::Lambertian;
+    use crate::test_util::assert_close;
+
+    fn encode_png(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut data, width, height);
+            encoder.set(png::ColorType::RGB).set(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(rgb).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn decodes_srgb() {
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb_to_linear(0.5) - 0.21404).abs() < 1e-4);
+        // The linear segment near black
+        assert!((srgb_to_linear(0.04) - 0.04 / 12.92).abs() < 1e-7);
+    }
+
+    #[test]
+    fn images_wrap_and_filter_bilinearly() {
+        // Red and green on the top row, blue and mid gray on the bottom
+        let png = encode_png(2, 2, &[255, 0, 0, 0, 255, 0, 0, 0, 255, 128, 128, 128]);
+
+        let linear = ImageTexture::from_png(&png[..], false).unwrap();
+        assert_eq!((linear.width, linear.height), (2, 2));
+        let gray = 128.0 / 255.0;
+        assert_close(linear.sample(0.75, 0.25), Vec3::new(gray, gray, gray));
+
+        let image = ImageTexture::from_png(&png[..], true).unwrap();
+        let (red, green) = (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let gray = srgb_to_linear(gray);
+
+        // v grows upwards, so the top row is at v = 0.75
+        assert_close(image.sample(0.25, 0.75), red);
+        assert_close(image.sample(0.75, 0.25), Vec3::new(gray, gray, gray));
+        assert_close(image.sample(0.5, 0.75), 0.5 * (red + green));
+
+        // Coordinates wrap around in both directions
+        assert_close(image.sample(1.25, 0.75), red);
+        assert_close(image.sample(-0.75, -0.25), red);
+        // Halfway between the last and the first column
+        assert_close(image.sample(0.0, 0.75), 0.5 * (red + green));
+
+        assert!(ImageTexture::from_png(&png[1..], true).is_err());
+    }
+
+    #[test]
+    fn combines_textures() {
+        let material = Lambertian {
+            albedo: Vec3::zero(),
+        };
+        let mut hit = HitInfo {
+            t: 1.0,
+            p: Vec3::new(0.5, 0.5, 0.5),
+            normal: Vec3::up(),
+            u: 0.0,
+            v: 0.0,
+            color: None,
+            material: &material,
+        };
+
+        let checker = Checker {
+            odd: Arc::new(1.0),
+            even: Arc::new(0.0),
+            scale: 1.0,
+        };
+        assert_eq!(checker.value(&hit), Vec3::zero());
+        hit.p = Vec3::new(1.5, 0.5, -0.5);
+        assert_eq!(checker.value(&hit), Vec3::zero());
+        hit.p = Vec3::new(1.5, 0.5, 0.5);
+        assert_eq!(checker.value(&hit), Vec3::new(1.0, 1.0, 1.0));
+
+        let packed: Arc<dyn Texture> = Arc::new(Vec3::new(0.1, 0.2, 0.3));
+        let roughness = Channel {
+            texture: packed.clone(),
+            channel: 1,
+        };
+        assert_eq!(roughness.value(&hit), Vec3::new(0.2, 0.2, 0.2));
+
+        let product = Product {
+            a: packed,
+            b: Arc::new(2.0),
+        };
+        assert_close(product.value(&hit), Vec3::new(0.2, 0.4, 0.6));
+
+        let vertex = VertexColor {
+            fallback: Arc::new(0.5),
+        };
+        assert_eq!(vertex.value(&hit), Vec3::new(0.5, 0.5, 0.5));
+        hit.color = Some(Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(vertex.value(&hit), Vec3::new(1.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,213 @@
+use std::ops::Mul;
+
+use crate::vec3::Vec3;
+
+/// Affine transformation as a row-major 4x4 matrix acting on column
+/// vectors, so `a * b` applies `b` first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f32; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn identity() -> Mat4 {
+        Mat4::scale(Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translate(t: Vec3) -> Mat4 {
+        let mut result = Mat4::identity();
+        for i in 0..3 {
+            result.m[i][3] = t[i];
+        }
+        result
+    }
+
+    pub fn scale(s: Vec3) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = if i < 3 { s[i] } else { 1.0 };
+        }
+        Mat4 { m }
+    }
+
+    /// Rotation by the unit quaternion `[x, y, z, w]`.
+    pub fn rotate_quaternion([x, y, z, w]: [f32; 4]) -> Mat4 {
+        Mat4 {
+            m: [
+                [
+                    1.0 - 2.0 * (y * y + z * z),
+                    2.0 * (x * y - z * w),
+                    2.0 * (x * z + y * w),
+                    0.0,
+                ],
+                [
+                    2.0 * (x * y + z * w),
+                    1.0 - 2.0 * (x * x + z * z),
+                    2.0 * (y * z - x * w),
+                    0.0,
+                ],
+                [
+                    2.0 * (x * z - y * w),
+                    2.0 * (y * z + x * w),
+                    1.0 - 2.0 * (x * x + y * y),
+                    0.0,
+                ],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Rotation by `angle` degrees counterclockwise around `axis`.
+    pub fn rotate(angle: f32, axis: Vec3) -> Mat4 {
+        let half = angle.to_radians() / 2.0;
+        let axis = axis.normalized() * half.sin();
+        Mat4::rotate_quaternion([axis.x(), axis.y(), axis.z(), half.cos()])
+    }
+
+    /// Reads the 16 elements of a matrix stored column by column, as in
+    /// glTF and OpenGL.
+    pub fn from_columns(elements: &[f32; 16]) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, e) in elements.iter().enumerate() {
+            m[i % 4][i / 4] = *e;
+        }
+        Mat4 { m }
+    }
+
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        self.transform_vector(p) + Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let row = |i: usize| Vec3::new(self.m[i][0], self.m[i][1], self.m[i][2]).dot(v);
+        Vec3::new(row(0), row(1), row(2))
+    }
+
+    /// Transforms a surface normal by the inverse transpose of the linear
+    /// part, which keeps it perpendicular to transformed tangents. The
+    /// result is normalized.
+    pub fn transform_normal(&self, n: Vec3) -> Vec3 {
+        let [a, b, c] = self.columns();
+        // The cross products give the cofactor matrix, which is the inverse
+        // transpose times the determinant, so mirroring needs a sign flip
+        let n = n.x() * b.cross(c) + n.y() * c.cross(a) + n.z() * a.cross(b);
+        (self.determinant().signum() * n).normalized()
+    }
+
+    /// Determinant of the linear part, negative for transformations that
+    /// mirror the geometry and flip the winding of triangles.
+    pub fn determinant(&self) -> f32 {
+        let [a, b, c] = self.columns();
+        a.cross(b).dot(c)
+    }
+
+    /// Inverse of an affine transformation.
+    pub fn inverse(&self) -> Mat4 {
+        let [a, b, c] = self.columns();
+        let det = self.determinant();
+        // The rows of the inverse of the linear part are the cross
+        // products of its columns, divided by the determinant
+        let rows = [b.cross(c) / det, c.cross(a) / det, a.cross(b) / det];
+        let t = Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3]);
+
+        let mut m = [[0.0; 4]; 4];
+        m[3][3] = 1.0;
+        for (i, row) in rows.iter().enumerate() {
+            m[i] = [row.x(), row.y(), row.z(), -row.dot(t)];
+        }
+        Mat4 { m }
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, e) in row.iter_mut().enumerate() {
+                *e = self.m[j][i];
+            }
+        }
+        Mat4 { m }
+    }
+
+    fn columns(&self) -> [Vec3; 3] {
+        [0, 1, 2].map(|j| Vec3::new(self.m[0][j], self.m[1][j], self.m[2][j]))
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, e) in row.iter_mut().enumerate() {
+                *e = (0..4).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Mat4 { m }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_util::assert_close;
+
+    #[test]
+    fn composes_scale_rotation_and_translation() {
+        // Quarter turn around y, taking +x to -z
+        let h = std::f32::consts::FRAC_1_SQRT_2;
+        let m = Mat4::translate(Vec3::new(1.0, 2.0, 3.0))
+            * Mat4::rotate_quaternion([0.0, h, 0.0, h])
+            * Mat4::scale(Vec3::new(2.0, 1.0, 1.0));
+
+        assert_close(
+            m.transform_point(Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(1.0, 2.0, 1.0),
+        );
+        assert_close(
+            m.transform_vector(Vec3::new(0.0, 1.0, 0.0)),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        assert!((m.determinant() - 2.0).abs() < 1e-5);
+
+        let columns = [
+            m.m[0][0], m.m[1][0], m.m[2][0], m.m[3][0], m.m[0][1], m.m[1][1], m.m[2][1], m.m[3][1],
+            m.m[0][2], m.m[1][2], m.m[2][2], m.m[3][2], m.m[0][3], m.m[1][3], m.m[2][3], m.m[3][3],
+        ];
+        assert_eq!(Mat4::from_columns(&columns), m);
+    }
+
+    #[test]
+    fn inverts_affine_transformations() {
+        let m = Mat4::translate(Vec3::new(1.0, -2.0, 0.5))
+            * Mat4::rotate(30.0, Vec3::new(1.0, 1.0, 0.0))
+            * Mat4::scale(Vec3::new(2.0, 3.0, -1.0));
+        let p = Vec3::new(0.3, 4.0, -2.0);
+
+        assert_close(m.inverse().transform_point(m.transform_point(p)), p);
+        assert_close((m * m.inverse()).transform_point(p), p);
+        assert_close(
+            Mat4::rotate(90.0, Vec3::up()).transform_vector(Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_shear() {
+        let mut m = Mat4::identity();
+        m.m[0][1] = 1.0;
+
+        let tangent = Vec3::new(1.0, -1.0, 0.0);
+        let normal = Vec3::new(1.0, 1.0, 0.0).normalized();
+        let dot = m.transform_vector(tangent).dot(m.transform_normal(normal));
+        assert!(dot.abs() < 1e-6);
+
+        // Mirrored normals still face the mirrored outside
+        let mirror = Mat4::scale(Vec3::new(-1.0, 1.0, 1.0));
+        let n = mirror.transform_normal(Vec3::new(1.0, 0.0, 0.0));
+        assert!((n - Vec3::new(-1.0, 0.0, 0.0)).lenght() < 1e-6, "{:?}", n);
+        let n = mirror.transform_normal(Vec3::new(0.0, 1.0, 0.0));
+        assert!((n - Vec3::up()).lenght() < 1e-6, "{:?}", n);
+    }
+}
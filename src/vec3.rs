@@ -3,6 +3,8 @@ use std::ops::{
     Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
 };
 
+use std::f32::consts::PI;
+
 use rand::prelude::*;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -87,7 +89,7 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_unit_sphere(rng: &mut rand::RngCore) -> Vec3 {
+    pub fn random_in_unit_sphere(rng: &mut dyn rand::RngCore) -> Vec3 {
         let mut v: Vec3;
 
         loop {
@@ -101,7 +103,7 @@ impl Vec3 {
         v
     }
 
-    pub fn random_in_unit_disk(rng: &mut rand::RngCore) -> Vec3 {
+    pub fn random_in_unit_disk(rng: &mut dyn rand::RngCore) -> Vec3 {
         let mut v: Vec3;
 
         loop {
@@ -114,6 +116,17 @@ impl Vec3 {
 
         v
     }
+
+    /// Direction in the `+z` hemisphere, distributed proportionally to its
+    /// cosine with the `z` axis (Malley's method).
+    pub fn random_cosine_direction(rng: &mut dyn rand::RngCore) -> Vec3 {
+        let r1: f32 = rng.gen();
+        let r2: f32 = rng.gen();
+        let r = r1.sqrt();
+        let phi = 2.0 * PI * r2;
+
+        Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - r1).sqrt())
+    }
 }
 
 /**
@@ -301,6 +314,39 @@ impl IndexMut<usize> for Vec3 {
     }
 }
 
+/// Orthonormal basis, used to move directions between world space and a
+/// local frame where `w` is the `+z` axis.
+#[derive(Clone, Copy, Debug)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    /// Builds a basis around the unit vector `n` without branching on its
+    /// largest component (Duff et al. 2017).
+    pub fn from_w(n: Vec3) -> Onb {
+        let sign = 1.0f32.copysign(n.z());
+        let a = -1.0 / (sign + n.z());
+        let b = n.x() * n.y() * a;
+
+        Onb {
+            u: Vec3::new(1.0 + sign * n.x() * n.x() * a, sign * b, -sign * n.x()),
+            v: Vec3::new(b, sign + n.y() * n.y() * a, -n.y()),
+            w: n,
+        }
+    }
+
+    pub fn to_local(self, a: Vec3) -> Vec3 {
+        Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+
+    pub fn to_world(self, a: Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -427,9 +473,9 @@ mod tests {
     fn index_operator() {
         let v = Vec3::new(1.0, 2.0, 3.0);
 
-        assert!((v[0] - 1.0).abs() < std::f32::EPSILON);
-        assert!((v[1] - 2.0).abs() < std::f32::EPSILON);
-        assert!((v[2] - 3.0).abs() < std::f32::EPSILON);
+        assert!((v[0] - 1.0).abs() < f32::EPSILON);
+        assert!((v[1] - 2.0).abs() < f32::EPSILON);
+        assert!((v[2] - 3.0).abs() < f32::EPSILON);
     }
     #[test]
     fn index_mut_operator() {
@@ -437,9 +483,9 @@ mod tests {
 
         v[0] = 2.0;
 
-        assert!((v[0] - 2.0).abs() < std::f32::EPSILON);
-        assert!((v[1] - 2.0).abs() < std::f32::EPSILON);
-        assert!((v[2] - 3.0).abs() < std::f32::EPSILON);
+        assert!((v[0] - 2.0).abs() < f32::EPSILON);
+        assert!((v[1] - 2.0).abs() < f32::EPSILON);
+        assert!((v[2] - 3.0).abs() < f32::EPSILON);
     }
 
     #[test]
@@ -451,7 +497,7 @@ mod tests {
         assert!(v1.dot(v2) == 0.0);
         assert!(v2.dot(v1) == 0.0);
         assert!(v1.dot(v3) == 0.0);
-        assert!((v1.dot(v1) - 1.0).abs() < std::f32::EPSILON);
+        assert!((v1.dot(v1) - 1.0).abs() < f32::EPSILON);
     }
 
     #[test]
@@ -463,37 +509,76 @@ mod tests {
 
         assert!(c1.x() == 0.0);
         assert!(c1.y() == 0.0);
-        assert!((c1.z() - 1.0).abs() < std::f32::EPSILON);
+        assert!((c1.z() - 1.0).abs() < f32::EPSILON);
 
         let v3 = Vec3::new(0.0, -1.0, 0.0);
         let c2 = v3.cross(v1);
-        assert!((c1.x() - c2.x()).abs() < std::f32::EPSILON);
-        assert!((c1.y() - c2.y()).abs() < std::f32::EPSILON);
-        assert!((c1.z() - c2.z()).abs() < std::f32::EPSILON);
+        assert!((c1.x() - c2.x()).abs() < f32::EPSILON);
+        assert!((c1.y() - c2.y()).abs() < f32::EPSILON);
+        assert!((c1.z() - c2.z()).abs() < f32::EPSILON);
     }
 
     #[test]
     fn length() {
         let v1 = Vec3::new(1.0, 2.0, 3.0);
 
-        assert!((v1.lenght() - (14.0f32).sqrt()).abs() < std::f32::EPSILON);
+        assert!((v1.lenght() - (14.0f32).sqrt()).abs() < f32::EPSILON);
     }
 
     #[test]
     fn length_squared() {
         let v1 = Vec3::new(1.0, 2.0, 3.0);
 
-        assert!((v1.lenght_squared() - 14.0).abs() < std::f32::EPSILON);
+        assert!((v1.lenght_squared() - 14.0).abs() < f32::EPSILON);
     }
 
     #[test]
     fn normalization() {
         let mut v1 = Vec3::new(1.0, 2.0, 3.0);
 
-        assert!((v1.normalized().lenght() - 1.0).abs() < std::f32::EPSILON);
+        assert!((v1.normalized().lenght() - 1.0).abs() < f32::EPSILON);
 
         let v2 = v1.normalized();
         v1.normalize();
         assert_eq!(v1, v2);
     }
+
+    #[test]
+    fn onb_is_orthonormal() {
+        for n in [
+            Vec3::new(0.3, -0.5, 0.8),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        ]
+        .iter()
+        {
+            let onb = Onb::from_w(n.normalized());
+
+            assert!((onb.u.lenght() - 1.0).abs() < 1e-5);
+            assert!((onb.v.lenght() - 1.0).abs() < 1e-5);
+            assert!(onb.u.dot(onb.v).abs() < 1e-5);
+            assert!(onb.u.dot(onb.w).abs() < 1e-5);
+            assert!((onb.u.cross(onb.v) - onb.w).lenght() < 1e-5);
+
+            let a = Vec3::new(1.0, 2.0, -3.0);
+            assert!((onb.to_world(onb.to_local(a)) - a).lenght() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn cosine_directions_are_in_the_upper_hemisphere() {
+        let mut rng = rand::thread_rng();
+        let mut mean_cos = 0.0;
+
+        for _ in 0..10000 {
+            let d = Vec3::random_cosine_direction(&mut rng);
+
+            assert!((d.lenght() - 1.0).abs() < 1e-4);
+            assert!(d.z() > 0.0);
+            mean_cos += d.z() / 10000.0;
+        }
+
+        // The expected cosine under a cosine distribution is 2/3
+        assert!((mean_cos - 2.0 / 3.0).abs() < 0.02);
+    }
 }